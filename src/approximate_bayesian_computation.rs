@@ -0,0 +1,553 @@
+//! Source: [Beaumont et al. (2009), Adaptive approximate Bayesian computation](https://doi.org/10.1093/biomet/asp052)
+//!
+//! Approximate Bayesian computation (ABC) infers the parameters of models without a tractable
+//! likelihood. Parameters are drawn from the priors and the model is simulated. A draw is
+//! accepted when the summary statistics of the simulation lie within a tolerance of those of the
+//! observed data. Implemented are rejection sampling and the sequential Monte Carlo sampler
+//! (ABC-SMC), which lowers the tolerance from one generation of particles to the next. The
+//! simulations run in parallel.
+use ndarray::{Array1, Array2, ArrayView1};
+use rand::distributions::WeightedIndex;
+use rand::prelude::*;
+use rand_distr::Normal;
+use rayon::prelude::*;
+use std::f64::consts::PI;
+use std::fmt::{Display, Error, Formatter};
+
+type Numeric = f64;
+
+/// Runs the model with the given parameters.
+pub type Simulator<T> = Box<dyn Fn(ArrayView1<Numeric>) -> T + Send + Sync>;
+/// Summary statistics of the output of a model, or of the observed data.
+pub type Summary<T> = Box<dyn Fn(&T) -> Array1<Numeric> + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Prior {
+    Uniform {
+        low: Numeric,
+        high: Numeric,
+    },
+    Normal {
+        mean: Numeric,
+        standard_deviation: Numeric,
+    },
+}
+
+impl Prior {
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Numeric {
+        match *self {
+            Prior::Uniform { low, high } => rng.gen_range(low, high),
+            Prior::Normal {
+                mean,
+                standard_deviation,
+            } => Normal::new(mean, standard_deviation)
+                .expect("standard deviation must be positive")
+                .sample(rng),
+        }
+    }
+
+    pub fn density(&self, x: Numeric) -> Numeric {
+        match *self {
+            Prior::Uniform { low, high } => {
+                if (low..=high).contains(&x) {
+                    1. / (high - low)
+                } else {
+                    0.
+                }
+            }
+            Prior::Normal {
+                mean,
+                standard_deviation,
+            } => normal_density(x, mean, standard_deviation),
+        }
+    }
+}
+
+fn normal_density(x: Numeric, mean: Numeric, standard_deviation: Numeric) -> Numeric {
+    let z = (x - mean) / standard_deviation;
+    (-0.5 * z * z).exp() / (standard_deviation * (2. * PI).sqrt())
+}
+
+/// Distance between the summary statistics of a simulation and of the observed data.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Distance {
+    Euclidean,
+    Manhattan,
+    /// Euclidean distance after dividing each statistic by its scale.
+    Scaled(Array1<Numeric>),
+}
+
+impl Distance {
+    pub fn between(&self, u: ArrayView1<Numeric>, v: ArrayView1<Numeric>) -> Numeric {
+        assert_eq!(u.len(), v.len(), "summaries must have the same length");
+        let differences = u.iter().zip(v.iter()).map(|(a, b)| a - b);
+        match self {
+            Distance::Euclidean => differences.map(|d| d * d).sum::<Numeric>().sqrt(),
+            Distance::Manhattan => differences.map(Numeric::abs).sum(),
+            Distance::Scaled(scale) => differences
+                .zip(scale)
+                .map(|(d, s)| (d / s).powi(2))
+                .sum::<Numeric>()
+                .sqrt(),
+        }
+    }
+}
+
+/// How rejection sampling decides on the tolerance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tolerance {
+    /// Simulate until enough draws are within the given distance.
+    Fixed(Numeric),
+    /// Simulate `particles / quantile` draws and keep the closest `particles` of them.
+    Quantile(Numeric),
+}
+
+/// Weighted sample of the approximate posterior.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Posterior {
+    /// One particle per row.
+    samples: Array2<Numeric>,
+    /// Normalised importance weights.
+    weights: Array1<Numeric>,
+    distances: Array1<Numeric>,
+    tolerance: Numeric,
+    /// Number of simulations it took to accept the particles.
+    simulations: usize,
+}
+
+impl Posterior {
+    fn new(
+        particles: Vec<(Array1<Numeric>, Numeric)>,
+        weights: Array1<Numeric>,
+        dimension: usize,
+        tolerance: Numeric,
+        simulations: usize,
+    ) -> Self {
+        let samples =
+            Array2::from_shape_fn((particles.len(), dimension), |(i, k)| particles[i].0[k]);
+        let distances = particles.iter().map(|(_, distance)| *distance).collect();
+        let total = weights.sum();
+        Self {
+            samples,
+            weights: weights / total,
+            distances,
+            tolerance,
+            simulations,
+        }
+    }
+
+    pub fn samples(&self) -> &Array2<Numeric> {
+        &self.samples
+    }
+
+    pub fn weights(&self) -> &Array1<Numeric> {
+        &self.weights
+    }
+
+    pub fn distances(&self) -> &Array1<Numeric> {
+        &self.distances
+    }
+
+    pub fn tolerance(&self) -> Numeric {
+        self.tolerance
+    }
+
+    pub fn simulations(&self) -> usize {
+        self.simulations
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.nrows()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn acceptance_rate(&self) -> Numeric {
+        self.len() as Numeric / self.simulations as Numeric
+    }
+
+    /// Kish's effective sample size of the weights, `1 / Σ w²`.
+    pub fn effective_sample_size(&self) -> Numeric {
+        1. / self.weights.iter().map(|w| w * w).sum::<Numeric>()
+    }
+
+    /// Weighted mean of each parameter.
+    pub fn mean(&self) -> Array1<Numeric> {
+        self.samples.t().dot(&self.weights)
+    }
+
+    /// Weighted variance of each parameter.
+    pub fn variance(&self) -> Array1<Numeric> {
+        let mean = self.mean();
+        let mut variance = Array1::zeros(mean.len());
+        for (sample, weight) in self.samples.genrows().into_iter().zip(&self.weights) {
+            variance.scaled_add(*weight, &(&sample - &mean).mapv(|x| x * x));
+        }
+        variance
+    }
+
+    /// Weighted `probability`-quantile of the parameter in column `parameter`.
+    pub fn quantile(&self, parameter: usize, probability: Numeric) -> Numeric {
+        let mut column: Vec<_> = self
+            .samples
+            .column(parameter)
+            .iter()
+            .copied()
+            .zip(self.weights.iter().copied())
+            .collect();
+        column.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap());
+        let mut cumulative = 0.;
+        for (x, weight) in &column {
+            cumulative += weight;
+            if cumulative >= probability {
+                return *x;
+            }
+        }
+        column.last().expect("posterior is empty").0
+    }
+}
+
+impl Display for Posterior {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        for (k, (mean, variance)) in self.mean().iter().zip(&self.variance()).enumerate() {
+            writeln!(
+                f,
+                "θ{} = {:.4} ± {:.4} [{:.4}, {:.4}]",
+                k,
+                mean,
+                variance.sqrt(),
+                self.quantile(k, 0.025),
+                self.quantile(k, 0.975)
+            )?;
+        }
+        write!(
+            f,
+            "ε = {:.4}, ESS = {:.1} of {}, acceptance rate = {:.4}",
+            self.tolerance,
+            self.effective_sample_size(),
+            self.len(),
+            self.acceptance_rate()
+        )
+    }
+}
+
+/// Inference of the parameters of a stochastic model from `observed` data, through the
+/// summary statistics of the data and of simulations of the model.
+pub struct Abc<T> {
+    priors: Vec<Prior>,
+    simulator: Simulator<T>,
+    summary: Summary<T>,
+    observed: Array1<Numeric>,
+    distance: Distance,
+    /// Attempts allowed for each particle before rejection sampling or a generation of ABC-SMC
+    /// gives up.
+    max_attempts: usize,
+}
+
+impl<T> Abc<T> {
+    pub fn new(
+        priors: Vec<Prior>,
+        simulator: Simulator<T>,
+        summary: Summary<T>,
+        observed: &T,
+    ) -> Self {
+        assert!(!priors.is_empty(), "at least one parameter is needed");
+        let observed = summary(observed);
+        Self {
+            priors,
+            simulator,
+            summary,
+            observed,
+            distance: Distance::Euclidean,
+            max_attempts: 10_000,
+        }
+    }
+
+    pub fn set_distance(mut self, distance: Distance) -> Self {
+        self.distance = distance;
+        self
+    }
+
+    pub fn set_max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Summary statistics of the observed data.
+    pub fn observed(&self) -> &Array1<Numeric> {
+        &self.observed
+    }
+
+    fn sample_prior<R: Rng>(&self, rng: &mut R) -> Array1<Numeric> {
+        self.priors.iter().map(|prior| prior.sample(rng)).collect()
+    }
+
+    fn prior_density(&self, parameters: ArrayView1<Numeric>) -> Numeric {
+        self.priors
+            .iter()
+            .zip(parameters)
+            .map(|(prior, x)| prior.density(*x))
+            .product()
+    }
+
+    /// Distance between a simulation with `parameters` and the observed data.
+    pub fn simulate(&self, parameters: ArrayView1<Numeric>) -> Numeric {
+        let output = (self.simulator)(parameters);
+        self.distance
+            .between((self.summary)(&output).view(), self.observed.view())
+    }
+
+    /// Draws from the priors and simulates, `draws` times in parallel.
+    fn simulate_prior(&self, draws: usize) -> Vec<(Array1<Numeric>, Numeric)> {
+        (0..draws)
+            .into_par_iter()
+            .map(|_| {
+                let parameters = self.sample_prior(&mut thread_rng());
+                let distance = self.simulate(parameters.view());
+                (parameters, distance)
+            })
+            .collect()
+    }
+
+    /// Rejection ABC. With a [`Tolerance::Fixed`] that is too small, fewer than `particles` are
+    /// returned after `particles * max_attempts` simulations.
+    pub fn rejection(&self, particles: usize, tolerance: Tolerance) -> Posterior {
+        let (accepted, tolerance, simulations) = match tolerance {
+            Tolerance::Fixed(epsilon) => {
+                let mut accepted = Vec::with_capacity(particles);
+                let mut simulations = 0;
+                while accepted.len() < particles && simulations < particles * self.max_attempts {
+                    accepted.extend(
+                        self.simulate_prior(particles)
+                            .into_iter()
+                            .filter(|(_, distance)| *distance <= epsilon),
+                    );
+                    simulations += particles;
+                }
+                accepted.truncate(particles);
+                (accepted, epsilon, simulations)
+            }
+            Tolerance::Quantile(quantile) => {
+                assert!(
+                    quantile > 0. && quantile <= 1.,
+                    "quantile must be in (0, 1]"
+                );
+                let simulations = (particles as Numeric / quantile).ceil() as usize;
+                let mut draws = self.simulate_prior(simulations);
+                draws.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+                draws.truncate(particles);
+                let epsilon = draws.last().map(|(_, distance)| *distance).unwrap_or(0.);
+                (draws, epsilon, simulations)
+            }
+        };
+        let weights = Array1::ones(accepted.len());
+        Posterior::new(accepted, weights, self.priors.len(), tolerance, simulations)
+    }
+
+    /// ABC-SMC with adaptive tolerances. The first generation is a sample of the priors. The
+    /// tolerance of each later generation is the `quantile` of the distances of the one before,
+    /// and its particles are perturbed by a Gaussian kernel with twice the weighted variance of
+    /// the previous generation. Returns every generation. Stops early when a particle is not
+    /// accepted within `max_attempts` simulations.
+    pub fn smc(&self, particles: usize, generations: usize, quantile: Numeric) -> Vec<Posterior> {
+        assert!(quantile > 0. && quantile < 1., "quantile must be in (0, 1)");
+        let mut history = vec![self.rejection(particles, Tolerance::Quantile(1.))];
+        for _generation in 1..generations {
+            let previous = history.last().unwrap();
+            let mut distances = previous.distances.to_vec();
+            distances.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let epsilon = distances[(quantile * (distances.len() - 1) as Numeric) as usize];
+            let scale = previous
+                .variance()
+                .mapv(|variance| (2. * variance).sqrt().max(1e-12));
+            let index = WeightedIndex::new(previous.weights.iter()).unwrap();
+
+            let accepted: Option<Vec<_>> = (0..particles)
+                .into_par_iter()
+                .map(|_| {
+                    let mut rng = thread_rng();
+                    let mut simulations = 0;
+                    for _attempt in 0..self.max_attempts {
+                        let ancestor = previous.samples.row(index.sample(&mut rng));
+                        let parameters: Array1<Numeric> = ancestor
+                            .iter()
+                            .zip(&scale)
+                            .map(|(x, s)| {
+                                x + s * rng.sample::<Numeric, _>(rand_distr::StandardNormal)
+                            })
+                            .collect();
+                        if self.prior_density(parameters.view()) == 0. {
+                            continue;
+                        }
+                        let distance = self.simulate(parameters.view());
+                        simulations += 1;
+                        if distance <= epsilon {
+                            return Some((parameters, distance, simulations));
+                        }
+                    }
+                    None
+                })
+                .collect();
+            let accepted = match accepted {
+                Some(accepted) => accepted,
+                None => break,
+            };
+
+            let weights: Array1<Numeric> = accepted
+                .iter()
+                .map(|(parameters, _, _)| {
+                    let kernel = previous
+                        .samples
+                        .genrows()
+                        .into_iter()
+                        .zip(&previous.weights)
+                        .map(|(ancestor, weight)| {
+                            weight
+                                * parameters
+                                    .iter()
+                                    .zip(ancestor)
+                                    .zip(&scale)
+                                    .map(|((x, y), s)| normal_density(*x, *y, *s))
+                                    .product::<Numeric>()
+                        })
+                        .sum::<Numeric>();
+                    self.prior_density(parameters.view()) / kernel
+                })
+                .collect();
+            let simulations = accepted.iter().map(|(_, _, simulations)| simulations).sum();
+            let accepted = accepted
+                .into_iter()
+                .map(|(parameters, distance, _)| (parameters, distance))
+                .collect();
+            history.push(Posterior::new(
+                accepted,
+                weights,
+                self.priors.len(),
+                epsilon,
+                simulations,
+            ));
+        }
+        history
+    }
+}
+
+#[cfg(test)]
+fn normal_mean_problem() -> Abc<Array1<Numeric>> {
+    let simulator: Simulator<Array1<Numeric>> = Box::new(|parameters| {
+        let normal = Normal::new(parameters[0], 1.).unwrap();
+        let mut rng = thread_rng();
+        (0..50).map(|_| normal.sample(&mut rng)).collect()
+    });
+    let summary: Summary<Array1<Numeric>> = Box::new(|data| ndarray::arr1(&[data.mean().unwrap()]));
+    let observed = ndarray::Array1::linspace(1., 3., 50);
+    Abc::new(
+        vec![Prior::Uniform { low: -5., high: 5. }],
+        simulator,
+        summary,
+        &observed,
+    )
+}
+
+#[test]
+fn rejection_of_a_normal_mean() {
+    let abc = normal_mean_problem();
+    let posterior = abc.rejection(200, Tolerance::Quantile(0.05));
+    println!("{}", posterior);
+    assert_eq!(posterior.len(), 200);
+    assert_eq!(posterior.simulations(), 4000);
+    assert!((posterior.effective_sample_size() - 200.).abs() < 1e-9);
+    assert!(posterior
+        .distances()
+        .iter()
+        .all(|d| *d <= posterior.tolerance()));
+    // The posterior of the mean is about N(2, 1/50).
+    assert!((posterior.mean()[0] - 2.).abs() < 0.1);
+    assert!(posterior.variance()[0].sqrt() < 0.3);
+    assert!(posterior.quantile(0, 0.025) < 2. && 2. < posterior.quantile(0, 0.975));
+
+    let posterior = abc.rejection(100, Tolerance::Fixed(0.2));
+    assert_eq!(posterior.len(), 100);
+    assert!(posterior.distances().iter().all(|d| *d <= 0.2));
+}
+
+#[test]
+fn smc_lowers_the_tolerance() {
+    let generations = normal_mean_problem().smc(200, 5, 0.5);
+    for (generation, posterior) in generations.iter().enumerate() {
+        println!("generation {}\n{}", generation, posterior);
+    }
+    assert_eq!(generations.len(), 5);
+    for pair in generations.windows(2) {
+        assert!(pair[1].tolerance() < pair[0].tolerance());
+        assert!(pair[1].effective_sample_size() > 20.);
+        assert!(pair[1].effective_sample_size() <= 200. + 1e-9);
+    }
+    let last = generations.last().unwrap();
+    assert!((last.weights().sum() - 1.).abs() < 1e-9);
+    assert!((last.mean()[0] - 2.).abs() < 0.1);
+    assert!(last.variance()[0] < generations[0].variance()[0] / 50.);
+}
+
+#[test]
+fn forest_tree_density() {
+    use crate::simple_forest_fire::Forrest;
+
+    // The forest left after a fire from the left edge, and the share of the cells that burnt.
+    let simulator: Simulator<(Forrest, Numeric)> = Box::new(|parameters| {
+        let mut forest = Forrest::new(30, parameters[0]);
+        let initial = forest.tree_fraction();
+        forest.burn();
+        let burnt = initial - forest.tree_fraction();
+        (forest, burnt)
+    });
+    let summary: Summary<(Forrest, Numeric)> =
+        Box::new(|(forest, burnt)| ndarray::arr1(&[forest.tree_fraction(), *burnt]));
+    let observed = simulator(ndarray::arr1(&[0.6]).view());
+    let abc = Abc::new(
+        vec![Prior::Uniform {
+            low: 0.05,
+            high: 0.95,
+        }],
+        simulator,
+        summary,
+        &observed,
+    );
+    let posterior = abc.rejection(50, Tolerance::Quantile(0.05));
+    println!("{}", posterior);
+    assert!((posterior.mean()[0] - 0.6).abs() < 0.05);
+}
+
+#[test]
+fn schelling_tolerance() {
+    use crate::schelling_segregation::Model;
+
+    // The agents get at most 10 sweeps to settle.
+    let simulator: Simulator<Model> = Box::new(|parameters| {
+        let mut model = Model::with_lattice_size(16, 90, 90, 8, 8, parameters[0], parameters[0]);
+        for _ in 0..10 {
+            if model.update() == 0 {
+                break;
+            }
+        }
+        model
+    });
+    let summary: Summary<Model> =
+        Box::new(|model| ndarray::arr1(&[model.segregation_index(), model.happy_fraction()]));
+    let observed = simulator(ndarray::arr1(&[0.4]).view());
+    let abc = Abc::new(
+        vec![Prior::Uniform {
+            low: 0.1,
+            high: 0.9,
+        }],
+        simulator,
+        summary,
+        &observed,
+    );
+    let generations = abc.smc(40, 3, 0.5);
+    let last = generations.last().unwrap();
+    println!("{}", last);
+    // `j` enters the model as the number of alike neighbours, floor(8 j) = 3.
+    assert!(last.quantile(0, 0.5) >= 0.25 && last.quantile(0, 0.5) < 0.6);
+}
@@ -4,3 +4,333 @@
 //!
 //! Source: [Assignment 8](http://prac.im.pwr.wroc.pl/~szwabin/assets/abm/labs/l8.pdf)
 //!
+//! A product is adopted by innovators at rate `p`, and by imitators at rate `q` times the
+//! fraction that has adopted it already. [`BassModel`] is the aggregate model and
+//! [`AgentBasedBass`] puts the agents on a network, where imitators only look at their
+//! neighbours.
+pub use crate::network::Network;
+use rand::prelude::*;
+use rayon::prelude::*;
+use std::fmt::{Display, Error, Formatter};
+
+type Count = f64;
+type Rate = f64;
+type Time = f64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BassModel {
+    /// Coefficient of innovation, `p`.
+    pub innovation: Rate,
+    /// Coefficient of imitation, `q`.
+    pub imitation: Rate,
+    /// Number of eventual adopters, `m`.
+    pub market_size: Count,
+}
+
+impl BassModel {
+    pub fn new(innovation: Rate, imitation: Rate, market_size: Count) -> Self {
+        Self {
+            innovation,
+            imitation,
+            market_size,
+        }
+    }
+
+    /// Fraction of the market that has adopted by time `t`, solving
+    /// `F' = (p + q F)(1 - F)` with `F(0) = 0`.
+    pub fn adopted_fraction(&self, t: Time) -> Count {
+        let BassModel {
+            innovation: p,
+            imitation: q,
+            ..
+        } = *self;
+        let decay = (-(p + q) * t).exp();
+        (1. - decay) / (1. + q / p * decay)
+    }
+
+    pub fn cumulative_adoption(&self, t: Time) -> Count {
+        self.market_size * self.adopted_fraction(t)
+    }
+
+    /// New adopters per unit of time at time `t`.
+    pub fn adoption_rate(&self, t: Time) -> Rate {
+        let fraction = self.adopted_fraction(t);
+        self.market_size * (self.innovation + self.imitation * fraction) * (1. - fraction)
+    }
+
+    /// Time of the largest adoption rate. Without enough imitation, `q <= p`, adoption is
+    /// fastest at the start.
+    pub fn peak_time(&self) -> Time {
+        let BassModel {
+            innovation: p,
+            imitation: q,
+            ..
+        } = *self;
+        if q <= p {
+            0.
+        } else {
+            (q / p).ln() / (p + q)
+        }
+    }
+
+    /// The original discrete time model, `N(t + 1) = N(t) + (p + q N(t) / m)(m - N(t))`, from
+    /// `N(0) = 0`.
+    pub fn discrete(&self, steps: usize) -> Vec<Count> {
+        let mut adopters = vec![0.];
+        for _ in 0..steps {
+            let current = *adopters.last().unwrap();
+            adopters.push(
+                current
+                    + (self.innovation + self.imitation * current / self.market_size)
+                        * (self.market_size - current),
+            );
+        }
+        adopters
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentBasedBass {
+    innovation: Rate,
+    imitation: Rate,
+    full_mixing: bool,
+    neighbours: Vec<Vec<usize>>,
+    adopted: Vec<bool>,
+    no_adopters: usize,
+    time: Time,
+}
+
+impl AgentBasedBass {
+    pub fn new(n: usize, innovation: Rate, imitation: Rate, network: &Network) -> Self {
+        Self {
+            innovation,
+            imitation,
+            full_mixing: *network == Network::FullMixing,
+            neighbours: network.adjacency(n),
+            adopted: vec![false; n],
+            no_adopters: 0,
+            time: 0.,
+        }
+    }
+
+    pub fn adopted(&self) -> &[bool] {
+        &self.adopted
+    }
+
+    pub fn no_adopters(&self) -> usize {
+        self.no_adopters
+    }
+
+    pub fn time(&self) -> Time {
+        self.time
+    }
+
+    /// Fraction of the neighbours of `agent` that have adopted.
+    fn adopted_neighbours(&self, agent: usize) -> f64 {
+        if self.full_mixing {
+            let others = self.adopted.len() - 1;
+            if others == 0 {
+                return 0.;
+            }
+            (self.no_adopters - self.adopted[agent] as usize) as f64 / others as f64
+        } else {
+            let neighbours = &self.neighbours[agent];
+            if neighbours.is_empty() {
+                return 0.;
+            }
+            neighbours
+                .iter()
+                .filter(|&&other| self.adopted[other])
+                .count() as f64
+                / neighbours.len() as f64
+        }
+    }
+
+    /// Every agent that hasn't adopted does so with probability `1 - exp(-hazard * delta_time)`,
+    /// all at once.
+    pub fn step(&mut self, delta_time: Time) {
+        let mut rng = thread_rng();
+        let adopting: Vec<usize> = (0..self.adopted.len())
+            .filter(|&agent| !self.adopted[agent])
+            .filter(|&agent| {
+                let hazard = self.innovation + self.imitation * self.adopted_neighbours(agent);
+                rng.gen_bool(1. - (-hazard * delta_time).exp())
+            })
+            .collect();
+        for agent in adopting {
+            self.adopted[agent] = true;
+            self.no_adopters += 1;
+        }
+        self.time += delta_time;
+    }
+
+    /// Number of adopters at the start and after every step until `end_time`.
+    pub fn run(&mut self, end_time: Time, delta_time: Time) -> Vec<Count> {
+        let mut adopters = vec![self.no_adopters as Count];
+        while self.time + delta_time <= end_time + 1e-12 {
+            self.step(delta_time);
+            adopters.push(self.no_adopters as Count);
+        }
+        adopters
+    }
+}
+
+/// The aggregate S-curve next to the mean of the agent-based model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub times: Vec<Time>,
+    pub aggregate: Vec<Count>,
+    pub agent_based: Vec<Count>,
+    /// Root mean square difference, as a fraction of the market.
+    pub rmse: f64,
+    /// Largest difference, as a fraction of the market.
+    pub max_error: f64,
+}
+
+/// Runs `replicates` of the agent-based model with one agent per member of the market, in
+/// parallel, and compares their mean with the aggregate model.
+pub fn compare(
+    model: &BassModel,
+    network: &Network,
+    replicates: usize,
+    end_time: Time,
+    delta_time: Time,
+) -> Comparison {
+    assert!(replicates > 0, "at least one replicate is needed");
+    let n = model.market_size.round() as usize;
+    let runs: Vec<Vec<Count>> = (0..replicates)
+        .into_par_iter()
+        .map(|_| {
+            AgentBasedBass::new(n, model.innovation, model.imitation, network)
+                .run(end_time, delta_time)
+        })
+        .collect();
+    let no_times = runs[0].len();
+    let times: Vec<Time> = (0..no_times).map(|i| i as Time * delta_time).collect();
+    let aggregate: Vec<Count> = times
+        .iter()
+        .map(|&t| model.cumulative_adoption(t))
+        .collect();
+    let agent_based: Vec<Count> = (0..no_times)
+        .map(|i| runs.iter().map(|run| run[i]).sum::<Count>() / replicates as Count)
+        .collect();
+    let errors: Vec<f64> = aggregate
+        .iter()
+        .zip(&agent_based)
+        .map(|(a, b)| (a - b).abs() / model.market_size)
+        .collect();
+    Comparison {
+        rmse: (errors.iter().map(|e| e * e).sum::<f64>() / no_times as f64).sqrt(),
+        max_error: errors.iter().cloned().fold(0., f64::max),
+        times,
+        aggregate,
+        agent_based,
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        writeln!(f, "{:>8} {:>12} {:>12}", "time", "aggregate", "agent-based")?;
+        for ((time, aggregate), agent_based) in self
+            .times
+            .iter()
+            .zip(&self.aggregate)
+            .zip(&self.agent_based)
+        {
+            writeln!(f, "{:>8.2} {:>12.2} {:>12.2}", time, aggregate, agent_based)?;
+        }
+        write!(f, "rmse = {:.4} max = {:.4}", self.rmse, self.max_error)
+    }
+}
+
+#[test]
+fn aggregate_s_curve() {
+    let model = BassModel::new(0.03, 0.38, 1000.);
+    assert!(model.cumulative_adoption(0.).abs() < 1e-12);
+    assert!((model.cumulative_adoption(100.) - 1000.).abs() < 1e-6);
+
+    let peak = model.peak_time();
+    let rate = |t| model.adoption_rate(t);
+    assert!(rate(peak) > rate(peak - 0.1) && rate(peak) > rate(peak + 0.1));
+    // At the peak the fraction that has adopted is (q - p) / 2q.
+    assert!((model.adopted_fraction(peak) - (0.38 - 0.03) / (2. * 0.38)).abs() < 1e-12);
+
+    // The derivative agrees with the closed form.
+    let h = 1e-5;
+    let t = 4.;
+    let derivative =
+        (model.cumulative_adoption(t + h) - model.cumulative_adoption(t - h)) / (2. * h);
+    assert!((derivative - model.adoption_rate(t)).abs() < 1e-5);
+
+    let discrete = model.discrete(40);
+    assert!(discrete.windows(2).all(|pair| pair[0] <= pair[1]));
+    assert!((discrete[40] - 1000.).abs() < 1.);
+    assert_eq!(BassModel::new(0.5, 0.1, 1.).peak_time(), 0.);
+}
+
+#[test]
+fn full_mixing_reproduces_the_aggregate() {
+    let model = BassModel::new(0.03, 0.38, 1000.);
+    let comparison = compare(&model, &Network::FullMixing, 40, 20., 0.1);
+    println!("rmse = {} max = {}", comparison.rmse, comparison.max_error);
+    assert!(comparison.rmse < 0.02);
+    assert!(comparison.max_error < 0.05);
+    assert_eq!(comparison.times.len(), 201);
+}
+
+#[test]
+#[should_panic(expected = "at least one replicate")]
+fn comparing_without_replicates() {
+    compare(
+        &BassModel::new(0.03, 0.38, 100.),
+        &Network::FullMixing,
+        0,
+        1.,
+        0.1,
+    );
+}
+
+#[test]
+fn local_imitation_is_slower() {
+    let model = BassModel::new(0.01, 0.5, 900.);
+    let full_mixing = compare(&model, &Network::FullMixing, 4, 10., 0.1);
+    let lattice = compare(
+        &model,
+        &Network::Lattice {
+            width: 30,
+            height: 30,
+        },
+        4,
+        10.,
+        0.1,
+    );
+    let ring = compare(&model, &Network::Ring { neighbours: 2 }, 4, 10., 0.1);
+    let end = full_mixing.times.len() - 1;
+    println!(
+        "adopters at t = 10: full mixing {} lattice {} ring {}",
+        full_mixing.agent_based[end], lattice.agent_based[end], ring.agent_based[end]
+    );
+    assert!(full_mixing.agent_based[end] > lattice.agent_based[end]);
+    assert!(lattice.agent_based[end] > ring.agent_based[end]);
+
+    let lattice = AgentBasedBass::new(
+        9,
+        0.,
+        0.,
+        &Network::Lattice {
+            width: 3,
+            height: 3,
+        },
+    );
+    assert!(lattice
+        .neighbours
+        .iter()
+        .all(|neighbours| neighbours.len() == 8));
+    let random = AgentBasedBass::new(50, 0., 0., &Network::Random { probability: 1. });
+    assert!(random
+        .neighbours
+        .iter()
+        .all(|neighbours| neighbours.len() == 49));
+    let edges = AgentBasedBass::new(3, 0., 0., &Network::Edges(vec![(0, 1), (1, 0), (1, 2)]));
+    assert_eq!(edges.neighbours, vec![vec![1], vec![0, 2], vec![1]]);
+}
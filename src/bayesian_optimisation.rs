@@ -1,35 +1,501 @@
+//! Source: [Tutorial](https://machinelearningmastery.com/what-is-bayesian-optimization/)
+//!
+//! Maximises an expensive, possibly noisy, objective on a box. A Gaussian process is fitted to
+//! the samples so far and the next sample is taken where an acquisition function, trading off
+//! the predicted mean against the uncertainty, is largest.
+use crate::statistics::{normal_cdf, normal_pdf};
+use ndarray::{Array1, Array2, ArrayView1};
+use rand::prelude::*;
+
 type Numeric = f64;
-struct Model {
-    objective: fn(Numeric) -> Numeric,
-    posterior: fn(Numeric) -> Numeric,
-    likelihood: fn(Numeric) -> Numeric,
-    prior: fn(Numeric) -> Numeric,
-    surrogate_function: fn(Numeric) -> Numeric, // response-surface
+pub type Objective = Box<dyn Fn(ArrayView1<Numeric>) -> Numeric>;
+
+/// Number of times [`GaussianProcess::fit`] raises the noise before it drops the correlations.
+const MAX_JITTER_ATTEMPTS: usize = 20;
+
+pub fn gaussian_kernel_numeric(u: Numeric, v: Numeric) -> Numeric {
+    ((u - v).powi(2) * (-0.5)).exp()
+}
+
+/// Squared exponential kernel with unit length scale.
+pub fn gaussian_kernel_vec(u: ArrayView1<Numeric>, v: ArrayView1<Numeric>) -> Numeric {
+    (squared_distance(u, v) * (-0.5)).exp()
+}
+
+/// Gram matrix of [`gaussian_kernel_vec`] for the rows of `x`.
+pub fn create_gaussian_kernel(x: &Array2<Numeric>) -> Array2<Numeric> {
+    Kernel::Rbf {
+        length_scale: 1.,
+        variance: 1.,
+    }
+    .gram(x)
+}
+
+fn squared_distance(u: ArrayView1<Numeric>, v: ArrayView1<Numeric>) -> Numeric {
+    u.iter().zip(v).map(|(a, b)| (a - b).powi(2)).sum()
+}
+
+/// Smoothness of a Matérn kernel; sample paths are `⌈ν⌉ - 1` times differentiable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Nu {
+    /// The exponential kernel.
+    Half,
+    ThreeHalves,
+    FiveHalves,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kernel {
+    /// Squared exponential, or Gaussian, kernel.
+    Rbf {
+        length_scale: Numeric,
+        variance: Numeric,
+    },
+    Matern {
+        nu: Nu,
+        length_scale: Numeric,
+        variance: Numeric,
+    },
+}
+
+impl Kernel {
+    pub fn covariance(&self, u: ArrayView1<Numeric>, v: ArrayView1<Numeric>) -> Numeric {
+        match *self {
+            Kernel::Rbf {
+                length_scale,
+                variance,
+            } => variance * (-0.5 * squared_distance(u, v) / length_scale.powi(2)).exp(),
+            Kernel::Matern {
+                nu,
+                length_scale,
+                variance,
+            } => {
+                let r = squared_distance(u, v).sqrt() / length_scale;
+                variance
+                    * match nu {
+                        Nu::Half => (-r).exp(),
+                        Nu::ThreeHalves => {
+                            let s = 3f64.sqrt() * r;
+                            (1. + s) * (-s).exp()
+                        }
+                        Nu::FiveHalves => {
+                            let s = 5f64.sqrt() * r;
+                            (1. + s + s * s / 3.) * (-s).exp()
+                        }
+                    }
+            }
+        }
+    }
+
+    pub fn variance(&self) -> Numeric {
+        match *self {
+            Kernel::Rbf { variance, .. } | Kernel::Matern { variance, .. } => variance,
+        }
+    }
+
+    /// Covariances between the rows of `x`.
+    pub fn gram(&self, x: &Array2<Numeric>) -> Array2<Numeric> {
+        let n = x.nrows();
+        Array2::from_shape_fn((n, n), |(i, j)| self.covariance(x.row(i), x.row(j)))
+    }
+}
+
+/// Lower triangular `L` with `L Lᵀ = a`, if `a` is symmetric positive definite.
+pub fn cholesky(a: &Array2<Numeric>) -> Option<Array2<Numeric>> {
+    let n = a.nrows();
+    let mut l = Array2::zeros((n, n));
+    for j in 0..n {
+        let diagonal = a[(j, j)] - (0..j).map(|k| l[(j, k)] * l[(j, k)]).sum::<Numeric>();
+        if diagonal <= 0. || !diagonal.is_finite() {
+            return None;
+        }
+        l[(j, j)] = diagonal.sqrt();
+        for i in j + 1..n {
+            let off_diagonal = a[(i, j)] - (0..j).map(|k| l[(i, k)] * l[(j, k)]).sum::<Numeric>();
+            l[(i, j)] = off_diagonal / l[(j, j)];
+        }
+    }
+    Some(l)
+}
+
+/// Solves `L x = b` for lower triangular `L`.
+fn solve_lower(l: &Array2<Numeric>, b: &Array1<Numeric>) -> Array1<Numeric> {
+    let mut x = b.clone();
+    for i in 0..x.len() {
+        x[i] = (x[i] - (0..i).map(|k| l[(i, k)] * x[k]).sum::<Numeric>()) / l[(i, i)];
+    }
+    x
+}
 
+/// Solves `Lᵀ x = b` for lower triangular `L`.
+fn solve_upper_transposed(l: &Array2<Numeric>, b: &Array1<Numeric>) -> Array1<Numeric> {
+    let n = b.len();
+    let mut x = b.clone();
+    for i in (0..n).rev() {
+        x[i] = (x[i] - (i + 1..n).map(|k| l[(k, i)] * x[k]).sum::<Numeric>()) / l[(i, i)];
+    }
+    x
+}
+
+/// Gaussian process regression with a constant mean, the mean of the observations.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GaussianProcess {
+    kernel: Kernel,
+    /// Variance of the observation noise, added to the diagonal.
+    noise: Numeric,
+    x: Array2<Numeric>,
+    y_mean: Numeric,
+    cholesky: Array2<Numeric>,
+    /// `K⁻¹ (y - ȳ)`
+    alpha: Array1<Numeric>,
+}
+
+impl GaussianProcess {
+    /// Fits the process to observations `y` at the rows of `x`. The noise is raised, a limited
+    /// number of times, until the covariance matrix can be factorised. If it never can, e.g. for
+    /// a kernel with a variance that is not a number, the observations are taken as independent.
+    pub fn fit(kernel: Kernel, noise: Numeric, x: Array2<Numeric>, y: &Array1<Numeric>) -> Self {
+        assert_eq!(x.nrows(), y.len());
+        let y_mean = if y.is_empty() { 0. } else { y.mean().unwrap() };
+        let centred = y - y_mean;
+        let gram = kernel.gram(&x);
+        let mut noise = noise.max(0.);
+        let mut attempts = 0;
+        let cholesky = loop {
+            let mut covariance = gram.clone();
+            covariance.diag_mut().map_inplace(|d| *d += noise);
+            if let Some(l) = cholesky(&covariance) {
+                break l;
+            }
+            if attempts == MAX_JITTER_ATTEMPTS {
+                // `max` ignores a variance that is not a number
+                break Array2::from_diag(&covariance.diag().mapv(|d| d.max(noise).sqrt()));
+            }
+            attempts += 1;
+            noise = (noise * 10.).max(1e-10 * kernel.variance()).max(1e-10);
+        };
+        let alpha = solve_upper_transposed(&cholesky, &solve_lower(&cholesky, &centred));
+        Self {
+            kernel,
+            noise,
+            x,
+            y_mean,
+            cholesky,
+            alpha,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.x.nrows()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Posterior mean and variance of the latent function at `point`.
+    pub fn predict(&self, point: ArrayView1<Numeric>) -> (Numeric, Numeric) {
+        let covariances: Array1<Numeric> = self
+            .x
+            .genrows()
+            .into_iter()
+            .map(|row| self.kernel.covariance(row, point))
+            .collect();
+        let mean = self.y_mean + covariances.dot(&self.alpha);
+        let v = solve_lower(&self.cholesky, &covariances);
+        let variance = (self.kernel.covariance(point, point) - v.dot(&v)).max(0.);
+        (mean, variance)
+    }
+
+    /// `log p(y | x)`, for comparing kernels.
+    pub fn log_marginal_likelihood(&self) -> Numeric {
+        let n = self.len() as Numeric;
+        // K α = y - ȳ
+        let centred = self.cholesky.dot(&self.cholesky.t().dot(&self.alpha));
+        -0.5 * centred.dot(&self.alpha)
+            - self.cholesky.diag().iter().map(|d| d.ln()).sum::<Numeric>()
+            - 0.5 * n * (2. * std::f64::consts::PI).ln()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Acquisition {
+    /// Expected amount by which a sample beats the best so far by more than `xi`.
+    ExpectedImprovement { xi: Numeric },
+    /// Mean plus `kappa` standard deviations.
+    UpperConfidenceBound { kappa: Numeric },
+    /// Probability that a sample beats the best so far by more than `xi`.
+    ProbabilityOfImprovement { xi: Numeric },
+}
+
+impl Acquisition {
+    pub fn value(&self, mean: Numeric, standard_deviation: Numeric, best: Numeric) -> Numeric {
+        match *self {
+            Acquisition::ExpectedImprovement { xi } => {
+                let improvement = mean - best - xi;
+                if standard_deviation == 0. {
+                    return improvement.max(0.);
+                }
+                let z = improvement / standard_deviation;
+                improvement * normal_cdf(z) + standard_deviation * normal_pdf(z)
+            }
+            Acquisition::UpperConfidenceBound { kappa } => mean + kappa * standard_deviation,
+            Acquisition::ProbabilityOfImprovement { xi } => {
+                if standard_deviation == 0. {
+                    return if mean > best + xi { 1. } else { 0. };
+                }
+                normal_cdf((mean - best - xi) / standard_deviation)
+            }
+        }
+    }
+}
+
+/// Bayesian optimisation of `objective` on the box given by `bounds`. The Gaussian process
+/// works on the box scaled to the unit cube, so the length scale of the kernel is relative to
+/// the size of the box.
+pub struct Model {
+    objective: Objective,
+    bounds: Vec<(Numeric, Numeric)>,
+    kernel: Kernel,
+    acquisition: Acquisition,
+    noise: Numeric,
+    initial_samples: usize,
+    /// Random points at which the acquisition function is tried in each iteration.
+    candidates: usize,
     max_iterations: u32,
+    /// Samples in the unit cube, and the value of the objective at each.
+    samples: Vec<(Array1<Numeric>, Numeric)>,
 }
 
 impl Model {
-    fn run(&self) {
-        for _t in (0..self.max_iterations) {
+    pub fn new(objective: Objective, bounds: Vec<(Numeric, Numeric)>) -> Self {
+        assert!(bounds.iter().all(|(low, high)| low < high));
+        Self {
+            objective,
+            bounds,
+            kernel: Kernel::Matern {
+                nu: Nu::FiveHalves,
+                length_scale: 0.1,
+                variance: 1.,
+            },
+            acquisition: Acquisition::ExpectedImprovement { xi: 0.01 },
+            noise: 1e-6,
+            initial_samples: 5,
+            candidates: 1000,
+            max_iterations: 50,
+            samples: Vec::new(),
+        }
+    }
 
-            // find the x_t that is the result of argmax_x{u(x|D[0.._t]}
-            //Sample the objective function around x_t
-            // Amend x_t and y_t to the gaussian process and update the gaussian process
+    pub fn set_kernel(mut self, kernel: Kernel) -> Self {
+        self.kernel = kernel;
+        self
+    }
+
+    pub fn set_acquisition(mut self, acquisition: Acquisition) -> Self {
+        self.acquisition = acquisition;
+        self
+    }
+
+    /// Variance of the noise of the objective.
+    pub fn set_noise(mut self, noise: Numeric) -> Self {
+        self.noise = noise;
+        self
+    }
+
+    pub fn set_initial_samples(mut self, initial_samples: usize) -> Self {
+        self.initial_samples = initial_samples;
+        self
+    }
+
+    pub fn set_candidates(mut self, candidates: usize) -> Self {
+        assert!(candidates > 0, "at least one candidate is needed");
+        self.candidates = candidates;
+        self
+    }
+
+    pub fn set_max_iterations(mut self, max_iterations: u32) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    fn to_bounds(&self, unit: ArrayView1<Numeric>) -> Array1<Numeric> {
+        unit.iter()
+            .zip(&self.bounds)
+            .map(|(u, (low, high))| low + u * (high - low))
+            .collect()
+    }
+
+    /// Every sample so far, in the original coordinates, with the value of the objective.
+    pub fn samples(&self) -> Vec<(Array1<Numeric>, Numeric)> {
+        self.samples
+            .iter()
+            .map(|(unit, y)| (self.to_bounds(unit.view()), *y))
+            .collect()
+    }
+
+    /// The best sample so far, ignoring samples where the objective is not a number.
+    pub fn best(&self) -> Option<(Array1<Numeric>, Numeric)> {
+        self.samples
+            .iter()
+            .filter(|(_, y)| !y.is_nan())
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(unit, y)| (self.to_bounds(unit.view()), *y))
+    }
+
+    fn sample(&mut self, unit: Array1<Numeric>) {
+        let y = (self.objective)(self.to_bounds(unit.view()).view());
+        self.samples.push((unit, y));
+    }
+
+    fn random_point<R: Rng>(&self, rng: &mut R) -> Array1<Numeric> {
+        (0..self.bounds.len())
+            .map(|_| rng.gen::<Numeric>())
+            .collect()
+    }
+
+    /// The Gaussian process fitted to the samples where the objective is a number.
+    pub fn surrogate(&self) -> GaussianProcess {
+        let dimension = self.bounds.len();
+        let samples: Vec<_> = self.samples.iter().filter(|(_, y)| !y.is_nan()).collect();
+        let x = Array2::from_shape_fn((samples.len(), dimension), |(i, k)| samples[i].0[k]);
+        let y: Array1<Numeric> = samples.iter().map(|(_, y)| *y).collect();
+        GaussianProcess::fit(self.kernel, self.noise, x, &y)
+    }
+
+    /// Runs the optimisation and returns the best sample. Panics if the objective is not a
+    /// number at any of the samples.
+    pub fn run(&mut self) -> (Array1<Numeric>, Numeric) {
+        let mut rng = thread_rng();
+        while self.samples.len() < self.initial_samples.max(1) {
+            let point = self.random_point(&mut rng);
+            self.sample(point);
+        }
+        for _t in 0..self.max_iterations {
+            // Find the x_t that is the result of argmax_x{u(x|D[0.._t]}
+            let surrogate = self.surrogate();
+            let best = match self.best() {
+                Some((_, best)) => best,
+                // nothing to go on yet
+                None => Numeric::NEG_INFINITY,
+            };
+            let next = (0..self.candidates)
+                .map(|_| self.random_point(&mut rng))
+                .map(|point| {
+                    let (mean, variance) = surrogate.predict(point.view());
+                    let value = self.acquisition.value(mean, variance.sqrt(), best);
+                    (point, value)
+                })
+                .filter(|(_, value)| !value.is_nan())
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map_or_else(|| self.random_point(&mut rng), |(point, _)| point);
+            // Sample the objective function at x_t, amend it to the samples and refit.
+            self.sample(next);
         }
+        self.best()
+            .expect("the objective is not a number at any of the samples")
     }
 }
 
-fn gaussian_kernel_numeric(u: Numeric, v: Numeric) -> Numeric {
-    ((u - v).powi(2) * (-0.5)).exp()
+#[test]
+fn cholesky_and_posterior() {
+    let a = ndarray::arr2(&[[4., 12., -16.], [12., 37., -43.], [-16., -43., 98.]]);
+    let l = cholesky(&a).unwrap();
+    assert_eq!(
+        l,
+        ndarray::arr2(&[[2., 0., 0.], [6., 1., 0.], [-8., 5., 3.]])
+    );
+    assert!(cholesky(&ndarray::arr2(&[[1., 2.], [2., 1.]])).is_none());
+
+    let x: Array2<Numeric> = ndarray::arr2(&[[0.], [0.3], [0.5], [0.9]]);
+    let y = x.column(0).mapv(|x| (6. * x).sin());
+    for &kernel in &[
+        Kernel::Rbf {
+            length_scale: 0.2,
+            variance: 1.,
+        },
+        Kernel::Matern {
+            nu: Nu::ThreeHalves,
+            length_scale: 0.2,
+            variance: 1.,
+        },
+    ] {
+        let gp = GaussianProcess::fit(kernel, 1e-10, x.clone(), &y);
+        // Interpolates the observations, without uncertainty there.
+        for (row, &observed) in x.genrows().into_iter().zip(&y) {
+            let (mean, variance) = gp.predict(row);
+            assert!((mean - observed).abs() < 1e-4);
+            assert!(variance < 1e-4);
+        }
+        // Far away, it falls back to the prior.
+        let (mean, variance) = gp.predict(ndarray::arr1(&[10.]).view());
+        assert!((mean - y.mean().unwrap()).abs() < 1e-9);
+        assert!((variance - 1.).abs() < 1e-9);
+        println!(
+            "{:?}: log marginal likelihood {}",
+            kernel,
+            gp.log_marginal_likelihood()
+        );
+    }
+    assert!(
+        (gaussian_kernel_vec(
+            ndarray::arr1(&[0., 1.]).view(),
+            ndarray::arr1(&[1., 1.]).view()
+        ) - gaussian_kernel_numeric(0., 1.))
+        .abs()
+            < 1e-15
+    );
+    assert_eq!(create_gaussian_kernel(&x).diag().to_vec(), vec![1.; 4]);
+
+    // A kernel without variance and no noise still gets a factorisable covariance.
+    let flat = Kernel::Rbf {
+        length_scale: 0.2,
+        variance: 0.,
+    };
+    let gp = GaussianProcess::fit(flat, 0., x.clone(), &y);
+    assert_eq!(gp.predict(ndarray::arr1(&[0.3]).view()).1, 0.);
+
+    // A covariance that can never be factorised falls back to independent observations.
+    let broken = Kernel::Rbf {
+        length_scale: 0.2,
+        variance: Numeric::NAN,
+    };
+    let gp = GaussianProcess::fit(broken, 0., x.clone(), &y);
+    assert_eq!(gp.len(), 4);
+}
+
+#[test]
+fn objectives_that_are_not_a_number() {
+    let mut model = Model::new(
+        Box::new(|x| {
+            if x[0] < 0.3 {
+                Numeric::NAN
+            } else {
+                -(x[0] - 0.6).powi(2)
+            }
+        }),
+        vec![(0., 1.)],
+    )
+    .set_max_iterations(15);
+    let (best, value) = model.run();
+    println!("best {} at {}", value, best);
+    assert!(!value.is_nan() && best[0] >= 0.3);
 }
 
-fn gaussian_kernel_vec(u: ndarray::Array1<Numeric>, v: ndarray::Array1<Numeric>) -> Numeric {
-    let mut diff: ndarray::Array1<_> = (u - v);
-    diff.par_mapv_inplace(|x| x.powi(2));
-    diff.sum()
+#[test]
+#[should_panic(expected = "at least one candidate")]
+fn no_candidates() {
+    Model::new(Box::new(|x| x[0]), vec![(0., 1.)]).set_candidates(0);
 }
 
-fn create_gaussian_kernel(x: ndarray::Array2<Numeric>) -> ndarray::Array2<Numeric> {
-    x.stride
+#[test]
+fn acquisition_functions() {
+    let ei = Acquisition::ExpectedImprovement { xi: 0. };
+    // Same mean, more uncertainty is better.
+    assert!(ei.value(1., 0.5, 1.) > ei.value(1., 0.1, 1.));
+    assert!((ei.value(1., 0.5, 1.) - 0.5 * normal_pdf(0.)).abs() < 1e-12);
+    let pi = Acquisition::ProbabilityOfImprovement { xi: 0. };
+    assert!((pi.value(1., 0.5, 1.) - 0.5).abs() < 1e-9);
+    let ucb = Acquisition::UpperConfidenceBound { kappa: 2. };
+    assert_eq!(ucb.value(1., 0.5, 10.), 2.);
 }
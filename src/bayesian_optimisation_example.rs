@@ -1,30 +1,32 @@
+use crate::bayesian_optimisation::{Acquisition, Model};
 use rand::prelude::Distribution;
 use rand::thread_rng;
-use std::cmp::Ordering::Equal;
 use std::f64::consts::PI as pi;
 
+type Numeric = f64;
+
+fn objective(x: Numeric) -> Numeric {
+    x.powi(2) * (5. * pi * x).sin().powi(6)
+}
+
+fn create_objective_with_noise(noise: Numeric) -> impl Fn(Numeric) -> Numeric {
+    let noise_distr = rand_distr::Normal::new(0., noise).unwrap();
+
+    move |x| objective(x) + noise_distr.sample(&mut thread_rng())
+}
+
 #[test]
 #[ignore]
+#[allow(non_snake_case)]
 /// Source: [Tutorial](https://machinelearningmastery.com/what-is-bayesian-optimization/)
 fn example() {
-    type Numeric = f64;
-    fn objective(x: Numeric) -> Numeric {
-        x.powi(2) * (5. * pi * x).sin().powi(6)
-    }
-
-    fn create_objective_with_noise(noise: Numeric) -> impl Fn(Numeric) -> Numeric {
-        let noise_distr = rand_distr::Normal::new(0., noise).unwrap();
-
-        move |x| objective(x) + noise_distr.sample(&mut thread_rng())
-    }
-
     let X = ndarray::Array1::range(0., 1., 0.01);
     println!("{:.3}", X);
 
     let y = X.mapv(create_objective_with_noise(0.));
     println!("y = {:.3}", y);
     let ynoise = X.mapv(create_objective_with_noise(0.1));
-    println!("ynoise = {:.4}", y);
+    println!("ynoise = {:.4}", ynoise);
 
     let (ix, yix) = y
         .indexed_iter()
@@ -34,3 +36,41 @@ fn example() {
 
     println!("Optima: x = {:.3}, y = {:.3}", xix, yix);
 }
+
+#[test]
+/// The tutorial samples the noisy objective 100 times, on top of 100 random samples, and finds
+/// the optimum at `x = 0.9`, `y = 0.81`.
+fn optimise_the_tutorial_objective() {
+    let noisy = create_objective_with_noise(0.1);
+    let mut model = Model::new(Box::new(move |x| noisy(x[0])), vec![(0., 1.)])
+        .set_noise(0.1f64.powi(2))
+        .set_initial_samples(10)
+        .set_max_iterations(40);
+    let (x, y) = model.run();
+    println!("Best Result: x={:.3}, y={:.3}", x[0], y);
+    assert_eq!(model.samples().len(), 50);
+
+    // The surrogate is a smoothed version of the objective, so ask it where the optimum is.
+    let surrogate = model.surrogate();
+    let grid = ndarray::Array1::range(0., 1., 0.001);
+    let x_max = grid
+        .iter()
+        .max_by(|&&a, &&b| {
+            let mean = |x: Numeric| surrogate.predict(ndarray::arr1(&[x]).view()).0;
+            mean(a).partial_cmp(&mean(b)).unwrap()
+        })
+        .unwrap();
+    println!(
+        "Surrogate optimum: x={:.3}, y={:.3}",
+        x_max,
+        objective(*x_max)
+    );
+    assert!((x_max - 0.9).abs() < 0.02);
+
+    let mut exact = Model::new(Box::new(|x| objective(x[0])), vec![(0., 1.)])
+        .set_acquisition(Acquisition::UpperConfidenceBound { kappa: 2. })
+        .set_max_iterations(30);
+    let (x, y) = exact.run();
+    assert!((x[0] - 0.9).abs() < 0.01);
+    assert!(y > 0.8);
+}
@@ -1,66 +1,1361 @@
 //! Source: [Assignment 6](http://prac.im.pwr.wroc.pl/~szwabin/assets/abm/labs/l6.pdf)
+//!
+//! Reynolds' boids in any number of dimensions, although 2 and 3 are the ones of interest.
+//! Positions and velocities of the flock are kept as the rows of `n x dimension` arrays.
+use crate::particle_system::{Integrator, Particle};
+use crate::spatial_data_structures::UniformGrid;
+use ndarray::{Array1, Array2, ArrayView1, ArrayViewMut1, Axis};
+use ndarray_rand::rand_distr::{StandardNormal, Uniform};
+use ndarray_rand::RandomExt;
 
 type Numeric = f64;
-type NumericVector = f64;
+pub type NumericVector = Array1<Numeric>;
 
-struct Boid {
-    position: NumericVector,
-    velocity: NumericVector,
-    mass: Numeric,
+pub fn norm(vector: ArrayView1<Numeric>) -> Numeric {
+    vector.dot(&vector).sqrt()
+}
+
+/// Scales `vector` down to `max_norm`, if it is longer.
+pub fn limit(vector: &mut NumericVector, max_norm: Numeric) {
+    let vector_norm = norm(vector.view());
+    if vector_norm > max_norm {
+        *vector *= max_norm / vector_norm;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Boid {
+    pub position: NumericVector,
+    pub velocity: NumericVector,
+    pub mass: Numeric,
 }
 
 /// Also called a "field of view"
-struct Neighbourhood {
+///
+/// A cone of half-angle `angle` around `heading`, cut off at `distance`. An `angle` of `π` sees
+/// all around.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Neighbourhood {
     heading: NumericVector,
     angle: Numeric,
     distance: Numeric,
 }
 
+impl Neighbourhood {
+    pub fn new(heading: NumericVector, angle: Numeric, distance: Numeric) -> Self {
+        Self {
+            heading,
+            angle,
+            distance,
+        }
+    }
+
+    /// Whether a boid at `offset` from the centre of the neighbourhood can be seen. Without a
+    /// heading, i.e. at rest, everything within `distance` can be seen.
+    pub fn contains(&self, offset: ArrayView1<Numeric>) -> bool {
+        let distance = norm(offset);
+        if distance > self.distance {
+            return false;
+        }
+        let heading_norm = norm(self.heading.view());
+        if heading_norm == 0. || distance == 0. || self.angle >= std::f64::consts::PI {
+            return true;
+        }
+        let cosine = self.heading.dot(&offset) / (heading_norm * distance);
+        cosine >= self.angle.cos()
+    }
+}
+
+/// The indices of, and offsets to, the rows of `position` in the neighbourhood of row `i`.
+pub fn neighbours(
+    arena: &Arena,
+    position: &Array2<Numeric>,
+    i: usize,
+    neighbourhood: &Neighbourhood,
+) -> Vec<(usize, NumericVector)> {
+    let centre = position.row(i);
+    position
+        .genrows()
+        .into_iter()
+        .enumerate()
+        .filter(|&(j, _)| j != i)
+        .map(|(j, other)| (j, arena.offset(centre, other)))
+        .filter(|(_, offset)| neighbourhood.contains(offset.view()))
+        .collect()
+}
+
 /// Approximation to [`Neighbourhood`].
-struct Sphere {
-    radius: Numeric,
-    center: NumericVector,
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sphere {
+    pub radius: Numeric,
+    pub center: NumericVector,
 }
 
-enum FlockingRules {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FlockingRules {
+    /// Steer away from neighbours that are too close.
     Separation,
+    /// Steer towards the centre of the neighbours.
     Cohesion,
+    /// Steer towards the mean velocity of the neighbours.
     Alignment,
 }
 
-enum SteeringRules {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SteeringRules {
+    /// Steer around the obstacles found by looking ahead along the heading.
     ObstacleAvoidance,
+    /// Steer towards where the nearest goal is going to be.
     GoalSeeking,
 }
 
+/// Static and solid; boids steer around obstacles and cannot fly through them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Obstacle {
+    /// A circle, or a sphere in three dimensions.
+    Sphere(Sphere),
+    /// Closed polygon given by its vertices in order. Only in the plane.
+    Polygon(Vec<NumericVector>),
+    /// A line, or a plane in three dimensions, through `point`. The side `normal` points to
+    /// is free, the other side is solid.
+    Wall {
+        point: NumericVector,
+        normal: NumericVector,
+    },
+}
+
+impl Obstacle {
+    /// Distance from `point` to the surface of the obstacle, negative inside, and the unit
+    /// direction away from the obstacle.
+    pub fn signed_distance(&self, point: ArrayView1<Numeric>) -> (Numeric, NumericVector) {
+        match self {
+            Obstacle::Sphere(Sphere { radius, center }) => {
+                let offset = &point - center;
+                let distance = norm(offset.view());
+                if distance == 0. {
+                    let mut direction = Array1::zeros(point.len());
+                    direction[0] = 1.;
+                    (-radius, direction)
+                } else {
+                    (distance - radius, offset / distance)
+                }
+            }
+            Obstacle::Polygon(vertices) => {
+                assert_eq!(point.len(), 2, "polygons are only supported in the plane");
+                let closest = vertices
+                    .iter()
+                    .zip(vertices.iter().cycle().skip(1))
+                    .map(|(a, b)| closest_point_on_segment(point, a.view(), b.view()))
+                    .min_by(|p, q| {
+                        norm((&point - p).view())
+                            .partial_cmp(&norm((&point - q).view()))
+                            .unwrap()
+                    })
+                    .expect("a polygon needs vertices");
+                let offset = &point - &closest;
+                let distance = norm(offset.view());
+                let sign = if polygon_contains(vertices, point) {
+                    -1.
+                } else {
+                    1.
+                };
+                if distance == 0. {
+                    // On the boundary; push away from the centroid.
+                    let centroid = vertices
+                        .iter()
+                        .fold(Array1::zeros(2), |acc: NumericVector, vertex| acc + vertex)
+                        / vertices.len() as Numeric;
+                    let away = &point - &centroid;
+                    let away_norm = norm(away.view());
+                    (0., away / away_norm)
+                } else {
+                    (sign * distance, offset * (sign / distance))
+                }
+            }
+            Obstacle::Wall {
+                point: on_wall,
+                normal,
+            } => {
+                let normal = normal / norm(normal.view());
+                ((&point - on_wall).dot(&normal), normal)
+            }
+        }
+    }
+
+    /// Moves a boid that has ended up inside the obstacle onto its surface, and removes the
+    /// part of its velocity going into the obstacle.
+    fn resolve(&self, mut position: ArrayViewMut1<Numeric>, mut velocity: ArrayViewMut1<Numeric>) {
+        let (distance, direction) = self.signed_distance(position.view());
+        if distance < 0. {
+            position.scaled_add(-distance, &direction);
+            let inwards = velocity.dot(&direction).min(0.);
+            velocity.scaled_add(-inwards, &direction);
+        }
+    }
+}
+
+fn closest_point_on_segment(
+    point: ArrayView1<Numeric>,
+    a: ArrayView1<Numeric>,
+    b: ArrayView1<Numeric>,
+) -> NumericVector {
+    let edge = &b - &a;
+    let length_squared = edge.dot(&edge);
+    let t = if length_squared == 0. {
+        0.
+    } else {
+        ((&point - &a).dot(&edge) / length_squared).clamp(0., 1.)
+    };
+    &a + &(edge * t)
+}
+
+/// Crossing number test.
+fn polygon_contains(vertices: &[NumericVector], point: ArrayView1<Numeric>) -> bool {
+    let (x, y) = (point[0], point[1]);
+    vertices
+        .iter()
+        .zip(vertices.iter().cycle().skip(1))
+        .filter(|(a, b)| {
+            (a[1] > y) != (b[1] > y) && x < a[0] + (y - a[1]) / (b[1] - a[1]) * (b[0] - a[0])
+        })
+        .count()
+        % 2
+        == 1
+}
+
+/// A unit vector perpendicular to `vector`, in two or more dimensions.
+fn perpendicular(vector: ArrayView1<Numeric>) -> NumericVector {
+    let unit = &vector / norm(vector);
+    let axis = (0..unit.len())
+        .min_by(|&i, &j| unit[i].abs().partial_cmp(&unit[j].abs()).unwrap())
+        .unwrap();
+    let mut perpendicular = -&unit * unit[axis];
+    perpendicular[axis] += 1.;
+    let perpendicular_norm = norm(perpendicular.view());
+    perpendicular / perpendicular_norm
+}
+
+/// A point the boids are attracted to, e.g. an exit or food. Goals move in straight lines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Goal {
+    pub position: NumericVector,
+    pub velocity: NumericVector,
+}
+
+impl Goal {
+    pub fn fixed(position: NumericVector) -> Self {
+        let velocity = Array1::zeros(position.len());
+        Self { position, velocity }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SteeringParameters {
+    pub obstacle_avoidance_weight: Numeric,
+    pub goal_seeking_weight: Numeric,
+    /// How far ahead, in time, boids look for obstacles and predict where goals will be.
+    pub look_ahead: Numeric,
+    /// Obstacles closer than this to the path ahead are steered around.
+    pub obstacle_margin: Numeric,
+}
+
+impl Default for SteeringParameters {
+    fn default() -> Self {
+        Self {
+            obstacle_avoidance_weight: 2.,
+            goal_seeking_weight: 0.5,
+            look_ahead: 2.,
+            obstacle_margin: 0.5,
+        }
+    }
+}
+
+impl SteeringParameters {
+    pub fn weight(&self, rule: SteeringRules) -> Numeric {
+        match rule {
+            SteeringRules::ObstacleAvoidance => self.obstacle_avoidance_weight,
+            SteeringRules::GoalSeeking => self.goal_seeking_weight,
+        }
+    }
+}
+
 // Alignment
 // Cohesion
 // Goal-seeking
 // Separation
 // Obstacle Avoidance
 
-struct Simulation {
-    n: usize,
-    boids: Vec<Boid>,
-    end_time: f64,
-    delta_time: f64,
+/// The space the boids fly in, an axis-aligned box from the origin to `size`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arena {
+    /// Boids leaving on one side come back on the opposite side, and see across the edges.
+    Periodic { size: NumericVector },
+    /// Boids bounce off the sides of the box.
+    Walled { size: NumericVector },
+    /// Unbounded space; `size` is only used to place the boids initially.
+    Open { size: NumericVector },
+}
+
+impl Arena {
+    pub fn size(&self) -> &NumericVector {
+        match self {
+            Arena::Periodic { size } | Arena::Walled { size } | Arena::Open { size } => size,
+        }
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.size().len()
+    }
+
+    /// The shortest vector from `from` to `to`, i.e. across the edges of a periodic arena.
+    pub fn offset(&self, from: ArrayView1<Numeric>, to: ArrayView1<Numeric>) -> NumericVector {
+        let mut offset = &to - &from;
+        if let Arena::Periodic { size } = self {
+            offset.zip_mut_with(size, |x, &length| *x -= length * (*x / length).round());
+        }
+        offset
+    }
+
+    /// An empty grid for neighbour search in the arena.
+    pub fn grid(&self, cell_size: Numeric) -> UniformGrid {
+        match self {
+            Arena::Periodic { size } => UniformGrid::periodic(cell_size, size.clone()),
+            Arena::Walled { .. } | Arena::Open { .. } => UniformGrid::new(cell_size),
+        }
+    }
+
+    /// Puts a boid that has left the arena back inside, wrapping or reflecting as needed.
+    fn confine(&self, mut position: ArrayViewMut1<Numeric>, mut velocity: ArrayViewMut1<Numeric>) {
+        match self {
+            Arena::Periodic { size } => {
+                position.zip_mut_with(size, |x, &length| *x = x.rem_euclid(length));
+            }
+            Arena::Walled { size } => {
+                for ((x, v), &length) in position.iter_mut().zip(velocity.iter_mut()).zip(size) {
+                    if *x < 0. {
+                        *x = -*x;
+                        *v = v.abs();
+                    } else if *x > length {
+                        *x = 2. * length - *x;
+                        *v = -v.abs();
+                    }
+                    // A boid far outside would be reflected beyond the opposite wall.
+                    *x = x.clamp(0., length);
+                }
+            }
+            Arena::Open { .. } => {}
+        }
+    }
+}
+
+/// Weights and ranges of the flocking rules, and the limits on the boids' motion.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlockingParameters {
+    pub separation_weight: Numeric,
+    pub cohesion_weight: Numeric,
+    pub alignment_weight: Numeric,
+    /// Neighbours closer than this are steered away from.
+    pub separation_distance: Numeric,
+    /// Half-angle of the field of view, in radians.
+    pub view_angle: Numeric,
+    pub view_distance: Numeric,
+    pub min_speed: Numeric,
+    pub max_speed: Numeric,
+    /// Upper bound on the norm of the steering force.
+    pub max_force: Numeric,
+}
+
+impl Default for FlockingParameters {
+    fn default() -> Self {
+        Self {
+            separation_weight: 1.5,
+            cohesion_weight: 0.2,
+            alignment_weight: 1.,
+            separation_distance: 1.,
+            view_angle: 0.75 * std::f64::consts::PI,
+            view_distance: 5.,
+            min_speed: 0.5,
+            max_speed: 2.,
+            max_force: 1.,
+        }
+    }
+}
+
+impl FlockingParameters {
+    pub fn weight(&self, rule: FlockingRules) -> Numeric {
+        match rule {
+            FlockingRules::Separation => self.separation_weight,
+            FlockingRules::Cohesion => self.cohesion_weight,
+            FlockingRules::Alignment => self.alignment_weight,
+        }
+    }
 }
 
-struct BoidsArray {
-    force: ndarray::Array2<Numeric>,
-    acceleration: ndarray::Array2<Numeric>,
-    mass: ndarray::Array1<Numeric>,
-    velocity: ndarray::Array2<Numeric>,
-    position: ndarray::Array2<Numeric>,
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoidsArray {
+    force: Array2<Numeric>,
+    acceleration: Array2<Numeric>,
+    mass: Array1<Numeric>,
+    velocity: Array2<Numeric>,
+    position: Array2<Numeric>,
     time: Numeric,
 }
 
 impl BoidsArray {
+    pub fn new(
+        position: Array2<Numeric>,
+        velocity: Array2<Numeric>,
+        mass: Array1<Numeric>,
+    ) -> Self {
+        assert_eq!(position.dim(), velocity.dim());
+        assert_eq!(position.nrows(), mass.len());
+        Self {
+            force: Array2::zeros(position.dim()),
+            acceleration: Array2::zeros(position.dim()),
+            mass,
+            velocity,
+            position,
+            time: 0.,
+        }
+    }
+
+    /// `n` boids of unit mass, uniformly placed in the arena and heading in uniformly random
+    /// directions at `speed`.
+    pub fn random(n: usize, arena: &Arena, speed: Numeric) -> Self {
+        let dimension = arena.dimension();
+        let mut position = Array2::random((n, dimension), Uniform::new(0., 1.));
+        for mut row in position.genrows_mut() {
+            row *= arena.size();
+        }
+        let mut velocity = Array2::random((n, dimension), StandardNormal);
+        for mut row in velocity.genrows_mut() {
+            let row_norm = norm(row.view());
+            row *= speed / row_norm;
+        }
+        Self::new(position, velocity, Array1::ones(n))
+    }
+
+    pub fn from_boids(boids: &[Boid]) -> Self {
+        let dimension = boids.first().map_or(0, |boid| boid.position.len());
+        let rows = |f: &dyn Fn(&Boid) -> &NumericVector| {
+            Array2::from_shape_fn((boids.len(), dimension), |(i, k)| f(&boids[i])[k])
+        };
+        Self::new(
+            rows(&|boid| &boid.position),
+            rows(&|boid| &boid.velocity),
+            boids.iter().map(|boid| boid.mass).collect(),
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.position.nrows()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn boid(&self, i: usize) -> Boid {
+        Boid {
+            position: self.position.row(i).to_owned(),
+            velocity: self.velocity.row(i).to_owned(),
+            mass: self.mass[i],
+        }
+    }
+
+    pub fn position(&self) -> &Array2<Numeric> {
+        &self.position
+    }
+
+    pub fn velocity(&self) -> &Array2<Numeric> {
+        &self.velocity
+    }
+
+    pub fn time(&self) -> Numeric {
+        self.time
+    }
+
+    /// The boids as particles, with their index as id.
+    pub fn particles(&self) -> Vec<Particle> {
+        (0..self.len())
+            .map(|i| Particle {
+                id: i,
+                position: self.position.row(i).to_owned(),
+                velocity: self.velocity.row(i).to_owned(),
+                mass: self.mass[i],
+                age: self.time,
+                lifetime: None,
+            })
+            .collect()
+    }
+
+    fn set_particles(&mut self, particles: &[Particle]) {
+        for particle in particles {
+            self.position
+                .row_mut(particle.id)
+                .assign(&particle.position);
+            self.velocity
+                .row_mut(particle.id)
+                .assign(&particle.velocity);
+        }
+    }
+
+    /// Explicit Euler step with the current `force`.
     fn update(&mut self, delta: f64) {
-        self.acceleration = self.force / self.mass;
-        self.velocity += delta * self.acceleration;
-        self.position += delta * self.velocity;
+        self.acceleration = &self.force / &self.mass.view().insert_axis(Axis(1));
+        self.velocity.scaled_add(delta, &self.acceleration);
+        self.position.scaled_add(delta, &self.velocity);
+
+        self.time += delta;
+    }
+}
+
+pub struct Simulation {
+    boids: BoidsArray,
+    parameters: FlockingParameters,
+    arena: Arena,
+    obstacles: Vec<Obstacle>,
+    goals: Vec<Goal>,
+    steering: SteeringParameters,
+    /// Steps the flock instead of the semi-implicit Euler step of [`BoidsArray`], if set.
+    integrator: Option<Integrator>,
+    /// Positions of the boids, for finding neighbours.
+    grid: UniformGrid,
+    end_time: f64,
+    delta_time: f64,
+}
+
+impl Simulation {
+    pub fn new(
+        boids: BoidsArray,
+        parameters: FlockingParameters,
+        arena: Arena,
+        end_time: f64,
+        delta_time: f64,
+    ) -> Self {
+        assert_eq!(boids.position.ncols(), arena.dimension());
+        let mut grid = arena.grid(parameters.view_distance);
+        for (i, position) in boids.position.genrows().into_iter().enumerate() {
+            grid.insert(i, position.to_owned());
+        }
+        Self {
+            boids,
+            parameters,
+            arena,
+            obstacles: Vec::new(),
+            goals: Vec::new(),
+            steering: SteeringParameters::default(),
+            integrator: None,
+            grid,
+            end_time,
+            delta_time,
+        }
+    }
+
+    pub fn set_obstacles(mut self, obstacles: Vec<Obstacle>) -> Self {
+        self.obstacles = obstacles;
+        self
+    }
+
+    pub fn set_goals(mut self, goals: Vec<Goal>) -> Self {
+        self.goals = goals;
+        self
+    }
+
+    pub fn set_steering_parameters(mut self, steering: SteeringParameters) -> Self {
+        self.steering = steering;
+        self
+    }
+
+    /// Moves the boids with one of the integrators of the particle system, which asks for the
+    /// steering forces again wherever it needs them.
+    pub fn set_integrator(mut self, integrator: Integrator) -> Self {
+        self.integrator = Some(integrator);
+        self
+    }
+
+    pub fn boids(&self) -> &BoidsArray {
+        &self.boids
+    }
+
+    pub fn arena(&self) -> &Arena {
+        &self.arena
+    }
+
+    pub fn obstacles(&self) -> &[Obstacle] {
+        &self.obstacles
+    }
+
+    pub fn goals(&self) -> &[Goal] {
+        &self.goals
+    }
+
+    /// The indices of, and offsets to, the boids that boid `i` can see.
+    pub fn neighbours(&self, i: usize) -> Vec<(usize, NumericVector)> {
+        let neighbourhood = Neighbourhood::new(
+            self.boids.velocity.row(i).to_owned(),
+            self.parameters.view_angle,
+            self.parameters.view_distance,
+        );
+        let position = self.boids.position.row(i);
+        self.grid
+            .within_radius(position, self.parameters.view_distance)
+            .into_iter()
+            .filter(|&j| j != i)
+            .map(|j| (j, self.arena.offset(position, self.boids.position.row(j))))
+            .filter(|(_, offset)| neighbourhood.contains(offset.view()))
+            .collect()
+    }
+
+    /// The unweighted steering of one rule, given the neighbours of boid `i`.
+    fn steering(
+        &self,
+        rule: FlockingRules,
+        i: usize,
+        neighbours: &[(usize, NumericVector)],
+    ) -> NumericVector {
+        let dimension = self.arena.dimension();
+        if neighbours.is_empty() {
+            return Array1::zeros(dimension);
+        }
+        let no_neighbours = neighbours.len() as Numeric;
+        match rule {
+            FlockingRules::Separation => neighbours
+                .iter()
+                .filter_map(|(_, offset)| {
+                    let distance = norm(offset.view());
+                    if 0. < distance && distance < self.parameters.separation_distance {
+                        // Repulsion grows as the inverse of the distance.
+                        Some(offset * (-1. / distance.powi(2)))
+                    } else {
+                        None
+                    }
+                })
+                .fold(Array1::zeros(dimension), |acc, x| acc + x),
+            FlockingRules::Cohesion => {
+                neighbours.iter().fold(
+                    Array1::zeros(dimension),
+                    |acc: NumericVector, (_, offset)| acc + offset,
+                ) / no_neighbours
+            }
+            FlockingRules::Alignment => {
+                neighbours
+                    .iter()
+                    .fold(Array1::zeros(dimension), |acc: NumericVector, &(j, _)| {
+                        acc + self.boids.velocity.row(j)
+                    })
+                    / no_neighbours
+                    - self.boids.velocity.row(i)
+            }
+        }
+    }
+
+    fn flocking_force(&self, i: usize) -> NumericVector {
+        let neighbours = self.neighbours(i);
+        let mut force = [
+            FlockingRules::Separation,
+            FlockingRules::Cohesion,
+            FlockingRules::Alignment,
+        ]
+        .iter()
+        .fold(Array1::zeros(self.arena.dimension()), |acc, &rule| {
+            acc + self.steering(rule, i, &neighbours) * self.parameters.weight(rule)
+        });
+        limit(&mut force, self.parameters.max_force);
+        force
+    }
+
+    /// The unweighted steering of one of the [`SteeringRules`] for boid `i`.
+    fn steering_towards(&self, rule: SteeringRules, i: usize) -> NumericVector {
+        let dimension = self.arena.dimension();
+        let position = self.boids.position.row(i);
+        let velocity = self.boids.velocity.row(i);
+        match rule {
+            SteeringRules::ObstacleAvoidance => {
+                const PROBES: usize = 8;
+                let speed = norm(velocity);
+                if speed == 0. || self.obstacles.is_empty() {
+                    return Array1::zeros(dimension);
+                }
+                let heading = &velocity / speed;
+                let look_ahead_distance = speed * self.steering.look_ahead;
+                for k in 0..=PROBES {
+                    let probe = &position
+                        + &(&heading * (look_ahead_distance * k as Numeric / PROBES as Numeric));
+                    let (distance, away) = self
+                        .obstacles
+                        .iter()
+                        .map(|obstacle| obstacle.signed_distance(probe.view()))
+                        .min_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap())
+                        .unwrap();
+                    if distance < self.steering.obstacle_margin {
+                        let towards = away.dot(&heading);
+                        let lateral = &away - &(&heading * towards);
+                        let lateral_norm = norm(lateral.view());
+                        let steer = if towards >= 0. {
+                            away
+                        } else if lateral_norm > 1e-6 {
+                            lateral / lateral_norm
+                        } else {
+                            // Heading straight at it; either side will do.
+                            perpendicular(heading.view())
+                        };
+                        // Obstacles found nearer are more urgent.
+                        return steer * (1. - k as Numeric / (PROBES + 1) as Numeric);
+                    }
+                }
+                Array1::zeros(dimension)
+            }
+            SteeringRules::GoalSeeking => {
+                let offset = self
+                    .goals
+                    .iter()
+                    .map(|goal| {
+                        // Pursuit: no need to look further ahead than it takes to get there.
+                        let offset = self.arena.offset(position, goal.position.view());
+                        let look_ahead = (norm(offset.view()) / self.parameters.max_speed)
+                            .min(self.steering.look_ahead);
+                        offset + &goal.velocity * look_ahead
+                    })
+                    .min_by(|a, b| norm(a.view()).partial_cmp(&norm(b.view())).unwrap());
+                match offset {
+                    Some(offset) if norm(offset.view()) > 0. => {
+                        let desired = &offset * (self.parameters.max_speed / norm(offset.view()));
+                        let mut steer = desired - velocity;
+                        limit(&mut steer, self.parameters.max_force);
+                        steer
+                    }
+                    _ => Array1::zeros(dimension),
+                }
+            }
+        }
+    }
+
+    /// Flocking and steering force on boid `i`.
+    fn force(&self, i: usize) -> NumericVector {
+        [SteeringRules::ObstacleAvoidance, SteeringRules::GoalSeeking]
+            .iter()
+            .fold(self.flocking_force(i), |acc, &rule| {
+                acc + self.steering_towards(rule, i) * self.steering.weight(rule)
+            })
+    }
+
+    fn update_grid(&mut self) {
+        for (i, position) in self.boids.position.genrows().into_iter().enumerate() {
+            self.grid.update(i, position.to_owned());
+        }
+    }
+
+    pub fn step(&mut self) {
+        match self.integrator {
+            None => {
+                for i in 0..self.boids.len() {
+                    let force = self.force(i);
+                    self.boids.force.row_mut(i).assign(&force);
+                }
+                self.boids.update(self.delta_time);
+            }
+            Some(integrator) => {
+                let mut particles = self.boids.particles();
+                integrator.step(&mut particles, self.delta_time, |particles| {
+                    self.boids.set_particles(particles);
+                    self.update_grid();
+                    particles
+                        .iter()
+                        .map(|particle| self.force(particle.id) / particle.mass)
+                        .collect()
+                });
+                self.boids.set_particles(&particles);
+                self.boids.time += self.delta_time;
+            }
+        }
+        for goal in &mut self.goals {
+            goal.position.scaled_add(self.delta_time, &goal.velocity);
+            self.arena
+                .confine(goal.position.view_mut(), goal.velocity.view_mut());
+        }
+
+        let FlockingParameters {
+            min_speed,
+            max_speed,
+            ..
+        } = self.parameters;
+        for (mut position, mut velocity) in self
+            .boids
+            .position
+            .genrows_mut()
+            .into_iter()
+            .zip(self.boids.velocity.genrows_mut())
+        {
+            let speed = norm(velocity.view());
+            if speed > max_speed {
+                velocity *= max_speed / speed;
+            } else if 0. < speed && speed < min_speed {
+                velocity *= min_speed / speed;
+            }
+            self.arena.confine(position.view_mut(), velocity.view_mut());
+            for obstacle in &self.obstacles {
+                obstacle.resolve(position.view_mut(), velocity.view_mut());
+            }
+        }
+        self.update_grid();
+    }
+
+    /// Steps until `end_time`.
+    pub fn run(&mut self) {
+        while self.boids.time + self.delta_time <= self.end_time + 1e-12 {
+            self.step();
+        }
+    }
+}
+
+impl Simulation {
+    pub fn statistics(&self, group_distance: Numeric) -> Statistics {
+        Statistics::new(&self.boids, &self.arena, group_distance)
+    }
+
+    /// Steps until `end_time`, measuring the flock before the first and after every step.
+    pub fn run_with_statistics(&mut self, group_distance: Numeric) -> Vec<Statistics> {
+        let mut statistics = vec![self.statistics(group_distance)];
+        while self.boids.time + self.delta_time <= self.end_time + 1e-12 {
+            self.step();
+            statistics.push(self.statistics(group_distance));
+        }
+        statistics
+    }
+}
+
+/// Order parameters of the flock at one point in time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statistics {
+    pub time: Numeric,
+    /// Norm of the mean heading; 1 when all boids head the same way, the Vicsek order parameter.
+    pub polarisation: Numeric,
+    /// Normalised angular momentum about the centre of the flock; 1 for a perfect mill.
+    pub milling: Numeric,
+    /// Distance from each boid to its nearest neighbour, in ascending order.
+    pub nearest_neighbour_distances: Vec<Numeric>,
+    /// Number of groups, where boids within `group_distance` of each other are in the same group.
+    pub no_groups: usize,
+    pub largest_group: usize,
+    /// Largest distance between two boids.
+    pub diameter: Numeric,
+}
+
+impl Statistics {
+    pub fn new(boids: &BoidsArray, arena: &Arena, group_distance: Numeric) -> Self {
+        let group_sizes = group_sizes(arena, &boids.position, group_distance);
+        Self {
+            time: boids.time,
+            polarisation: polarisation(&boids.velocity),
+            milling: milling(arena, &boids.position, &boids.velocity),
+            nearest_neighbour_distances: nearest_neighbour_distances(arena, &boids.position),
+            no_groups: group_sizes.len(),
+            largest_group: group_sizes.into_iter().max().unwrap_or(0),
+            diameter: diameter(arena, &boids.position),
+        }
+    }
+
+    pub fn mean_nearest_neighbour_distance(&self) -> Numeric {
+        self.nearest_neighbour_distances.iter().sum::<Numeric>()
+            / self.nearest_neighbour_distances.len() as Numeric
+    }
+
+    /// Counts of nearest neighbour distances in bins of `bin_width`, starting from zero.
+    pub fn nearest_neighbour_histogram(&self, bin_width: Numeric) -> Vec<usize> {
+        let mut histogram = Vec::new();
+        for distance in &self.nearest_neighbour_distances {
+            let bin = (distance / bin_width) as usize;
+            if bin >= histogram.len() {
+                histogram.resize(bin + 1, 0);
+            }
+            histogram[bin] += 1;
+        }
+        histogram
+    }
+}
+
+impl std::fmt::Display for Statistics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "time = {:>8.2} polarisation = {:<6.4} milling = {:<6.4} nearest = {:<8.4} \
+             groups = {:>3} largest = {:>4} diameter = {:<8.4}",
+            self.time,
+            self.polarisation,
+            self.milling,
+            self.mean_nearest_neighbour_distance(),
+            self.no_groups,
+            self.largest_group,
+            self.diameter
+        )
+    }
+}
+
+/// Norm of the mean unit velocity; rows of `velocity` at rest are left out.
+pub fn polarisation(velocity: &Array2<Numeric>) -> Numeric {
+    let mut mean_heading = Array1::zeros(velocity.ncols());
+    for velocity in velocity.genrows() {
+        let speed = norm(velocity);
+        if speed > 0. {
+            mean_heading.scaled_add(1. / speed, &velocity);
+        }
+    }
+    norm(mean_heading.view()) / velocity.nrows() as Numeric
+}
+
+/// Norm of the mean of `r̂ ∧ v̂`, where `r` is the position relative to the centre of the flock.
+/// In three dimensions this is the usual normalised angular momentum, and in two its absolute
+/// value. In a periodic arena the centre is only meaningful for a flock much smaller than the
+/// arena.
+pub fn milling(arena: &Arena, position: &Array2<Numeric>, velocity: &Array2<Numeric>) -> Numeric {
+    let (n, dimension) = position.dim();
+    if n == 0 {
+        return 0.;
+    }
+    let offsets: Vec<_> = position
+        .genrows()
+        .into_iter()
+        .map(|other| arena.offset(position.row(0), other))
+        .collect();
+    let centre = offsets
+        .iter()
+        .fold(Array1::zeros(dimension), |acc: NumericVector, offset| {
+            acc + offset
+        })
+        / n as Numeric;
+    // Components of the bivector, one for each pair of axes.
+    let mut angular_momentum = vec![0.; dimension * dimension.saturating_sub(1) / 2];
+    for (offset, velocity) in offsets.iter().zip(velocity.genrows()) {
+        let radius = offset - &centre;
+        let (radius_norm, speed) = (norm(radius.view()), norm(velocity));
+        if radius_norm == 0. || speed == 0. {
+            continue;
+        }
+        let mut component = angular_momentum.iter_mut();
+        for i in 0..dimension {
+            for j in i + 1..dimension {
+                *component.next().unwrap() +=
+                    (radius[i] * velocity[j] - radius[j] * velocity[i]) / (radius_norm * speed);
+            }
+        }
+    }
+    angular_momentum
+        .iter()
+        .map(|x| x * x)
+        .sum::<Numeric>()
+        .sqrt()
+        / n as Numeric
+}
+
+pub fn nearest_neighbour_distances(arena: &Arena, position: &Array2<Numeric>) -> Vec<Numeric> {
+    let mut distances: Vec<_> = (0..position.nrows())
+        .filter_map(|i| {
+            (0..position.nrows())
+                .filter(|&j| j != i)
+                .map(|j| norm(arena.offset(position.row(i), position.row(j)).view()))
+                .min_by(|a, b| a.partial_cmp(b).unwrap())
+        })
+        .collect();
+    distances.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    distances
+}
+
+/// Sizes of the groups found by single-linkage clustering at `group_distance`.
+pub fn group_sizes(
+    arena: &Arena,
+    position: &Array2<Numeric>,
+    group_distance: Numeric,
+) -> Vec<usize> {
+    let n = position.nrows();
+    let mut visited = vec![false; n];
+    let mut sizes = Vec::new();
+    for start in 0..n {
+        if visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        let mut stack = vec![start];
+        let mut size = 0;
+        while let Some(current) = stack.pop() {
+            size += 1;
+            for (other, seen) in visited.iter_mut().enumerate() {
+                if *seen {
+                    continue;
+                }
+                let distance = norm(
+                    arena
+                        .offset(position.row(current), position.row(other))
+                        .view(),
+                );
+                if distance <= group_distance {
+                    *seen = true;
+                    stack.push(other);
+                }
+            }
+        }
+        sizes.push(size);
+    }
+    sizes
+}
+
+/// Largest distance between any two rows of `position`.
+pub fn diameter(arena: &Arena, position: &Array2<Numeric>) -> Numeric {
+    let n = position.nrows();
+    (0..n)
+        .flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+        .map(|(i, j)| norm(arena.offset(position.row(i), position.row(j)).view()))
+        .fold(0., Numeric::max)
+}
+
+#[test]
+fn field_of_view() {
+    let neighbourhood =
+        Neighbourhood::new(ndarray::arr1(&[1., 0.]), std::f64::consts::FRAC_PI_2, 2.);
+    assert!(neighbourhood.contains(ndarray::arr1(&[1., 1.]).view()));
+    assert!(neighbourhood.contains(ndarray::arr1(&[0.1, -1.5]).view()));
+    assert!(!neighbourhood.contains(ndarray::arr1(&[-1., 0.1]).view()));
+    assert!(!neighbourhood.contains(ndarray::arr1(&[2., 1.]).view()));
+
+    let blind_behind = Neighbourhood::new(
+        ndarray::arr1(&[0., 0., 1.]),
+        0.75 * std::f64::consts::PI,
+        2.,
+    );
+    assert!(blind_behind.contains(ndarray::arr1(&[1., 0., 0.]).view()));
+    assert!(!blind_behind.contains(ndarray::arr1(&[0., 0.1, -1.]).view()));
+}
+
+#[test]
+fn periodic_offsets() {
+    let arena = Arena::Periodic {
+        size: ndarray::arr1(&[10., 10.]),
+    };
+    let offset = arena.offset(
+        ndarray::arr1(&[9.5, 1.]).view(),
+        ndarray::arr1(&[0.5, 9.]).view(),
+    );
+    assert_eq!(offset, ndarray::arr1(&[1., -2.]));
+}
+
+#[test]
+fn alignment_polarises_the_flock() {
+    let arena = Arena::Periodic {
+        size: ndarray::arr1(&[10., 10.]),
+    };
+    let boids = BoidsArray::random(40, &arena, 1.);
+    let initial_polarisation = polarisation(&boids.velocity);
+    let mut simulation = Simulation::new(boids, FlockingParameters::default(), arena, 60., 0.2);
+    simulation.run();
+
+    let polarisation = polarisation(&simulation.boids().velocity);
+    println!(
+        "polarisation: {:.3} -> {:.3}",
+        initial_polarisation, polarisation
+    );
+    assert!(polarisation > 0.8);
+    assert!((simulation.boids().time() - 60.).abs() < 1e-6);
+    assert!(simulation
+        .boids()
+        .position()
+        .iter()
+        .all(|x| (0. ..10.).contains(x)));
+}
+
+#[test]
+fn particle_integrators_steer_the_flock() {
+    let arena = Arena::Periodic {
+        size: ndarray::arr1(&[10., 10.]),
+    };
+    for &integrator in &[Integrator::Euler, Integrator::Verlet] {
+        let boids = BoidsArray::random(40, &arena, 1.);
+        let mut simulation = Simulation::new(
+            boids,
+            FlockingParameters::default(),
+            arena.clone(),
+            60.,
+            0.2,
+        )
+        .set_integrator(integrator);
+        simulation.run();
+
+        let polarisation = polarisation(&simulation.boids().velocity);
+        println!("{:?} polarisation: {:.3}", integrator, polarisation);
+        if integrator == Integrator::Verlet {
+            assert!(polarisation > 0.8);
+        }
+        assert!((simulation.boids().time() - 60.).abs() < 1e-6);
+        assert!(simulation
+            .boids()
+            .position()
+            .iter()
+            .all(|x| (0. ..10.).contains(x)));
+        for velocity in simulation.boids().velocity().genrows() {
+            let speed = norm(velocity);
+            assert!((0.5 - 1e-9..=2. + 1e-9).contains(&speed));
+        }
+    }
+}
+
+#[test]
+fn speed_limits_and_walls_in_3d() {
+    let arena = Arena::Walled {
+        size: ndarray::arr1(&[10., 10., 10.]),
+    };
+    let parameters = FlockingParameters {
+        max_speed: 1.5,
+        ..Default::default()
+    };
+    let boids = BoidsArray::random(50, &arena, 1.);
+    let mut simulation = Simulation::new(boids, parameters, arena, 30., 0.5);
+    simulation.run();
+
+    for velocity in simulation.boids().velocity().genrows() {
+        let speed = norm(velocity);
+        assert!((0.5 - 1e-9..=1.5 + 1e-9).contains(&speed));
+    }
+    assert!(simulation
+        .boids()
+        .position()
+        .iter()
+        .all(|x| (0. ..=10.).contains(x)));
+}
+
+#[test]
+fn separation_pushes_boids_apart() {
+    let boids = BoidsArray::from_boids(&[
+        Boid {
+            position: ndarray::arr1(&[0., 0.]),
+            velocity: ndarray::arr1(&[0., 1.]),
+            mass: 1.,
+        },
+        Boid {
+            position: ndarray::arr1(&[0.5, 0.]),
+            velocity: ndarray::arr1(&[0., 1.]),
+            mass: 1.,
+        },
+    ]);
+    let parameters = FlockingParameters {
+        cohesion_weight: 0.,
+        alignment_weight: 0.,
+        ..Default::default()
+    };
+    let arena = Arena::Open {
+        size: ndarray::arr1(&[1., 1.]),
+    };
+    let mut simulation = Simulation::new(boids, parameters, arena, 1., 0.1);
+    simulation.step();
+
+    assert!(simulation.boids().boid(0).position[0] < 0.);
+    assert!(simulation.boids().boid(1).position[0] > 0.5);
+}
+
+#[test]
+fn obstacle_geometry() {
+    let square = Obstacle::Polygon(vec![
+        ndarray::arr1(&[0., 0.]),
+        ndarray::arr1(&[2., 0.]),
+        ndarray::arr1(&[2., 2.]),
+        ndarray::arr1(&[0., 2.]),
+    ]);
+    let (distance, away) = square.signed_distance(ndarray::arr1(&[3., 1.]).view());
+    assert!((distance - 1.).abs() < 1e-12);
+    assert_eq!(away, ndarray::arr1(&[1., 0.]));
+    let (distance, away) = square.signed_distance(ndarray::arr1(&[1., 0.5]).view());
+    assert!((distance + 0.5).abs() < 1e-12);
+    assert_eq!(away, ndarray::arr1(&[0., -1.]));
+
+    let wall = Obstacle::Wall {
+        point: ndarray::arr1(&[0., 0., 1.]),
+        normal: ndarray::arr1(&[0., 0., 2.]),
+    };
+    let (distance, away) = wall.signed_distance(ndarray::arr1(&[5., -3., 0.5]).view());
+    assert!((distance + 0.5).abs() < 1e-12);
+    assert_eq!(away, ndarray::arr1(&[0., 0., 1.]));
+
+    let ball = Obstacle::Sphere(Sphere {
+        radius: 1.,
+        center: ndarray::arr1(&[1., 1.]),
+    });
+    let (distance, _) = ball.signed_distance(ndarray::arr1(&[4., 5.]).view());
+    assert!((distance - 4.).abs() < 1e-12);
+
+    let heading = ndarray::arr1(&[0.3, -0.2, 0.9]);
+    assert!(perpendicular(heading.view()).dot(&heading).abs() < 1e-12);
+}
+
+#[test]
+fn evacuation_around_a_pillar() {
+    let arena = Arena::Walled {
+        size: ndarray::arr1(&[20., 10.]),
+    };
+    let position = Array2::random((20, 2), Uniform::new(0., 1.)) * 3. + 1.;
+    let velocity = Array2::from_shape_fn((20, 2), |(_, k)| if k == 0 { 1. } else { 0. });
+    let boids = BoidsArray::new(position, velocity, Array1::ones(20));
+    let pillar = Obstacle::Sphere(Sphere {
+        radius: 2.,
+        center: ndarray::arr1(&[10., 5.]),
+    });
+    let barrier = Obstacle::Polygon(vec![
+        ndarray::arr1(&[6., 0.]),
+        ndarray::arr1(&[7., 0.]),
+        ndarray::arr1(&[7., 2.]),
+        ndarray::arr1(&[6., 2.]),
+    ]);
+    let exit = Goal::fixed(ndarray::arr1(&[19., 5.]));
+    let mut simulation = Simulation::new(boids, FlockingParameters::default(), arena, 40., 0.1)
+        .set_obstacles(vec![pillar, barrier])
+        .set_goals(vec![exit]);
+
+    while simulation.boids().time() < 40. {
+        simulation.step();
+        for position in simulation.boids().position().genrows() {
+            for obstacle in simulation.obstacles() {
+                assert!(obstacle.signed_distance(position).0 > -1e-9);
+            }
+        }
+    }
+    let mean_distance = simulation
+        .boids()
+        .position()
+        .genrows()
+        .into_iter()
+        .map(|position| norm((&position - &simulation.goals()[0].position).view()))
+        .sum::<Numeric>()
+        / 20.;
+    println!("mean distance to exit: {:.3}", mean_distance);
+    assert!(mean_distance < 4.);
+}
+
+#[test]
+fn pursuit_of_a_moving_goal() {
+    let boids = BoidsArray::from_boids(&[Boid {
+        position: ndarray::arr1(&[0., 0., 0.]),
+        velocity: ndarray::arr1(&[0., 0., 1.]),
+        mass: 1.,
+    }]);
+    let arena = Arena::Open {
+        size: ndarray::arr1(&[1., 1., 1.]),
+    };
+    let food = Goal {
+        position: ndarray::arr1(&[10., 5., 0.]),
+        velocity: ndarray::arr1(&[0., 1., 0.]),
+    };
+    let mut simulation = Simulation::new(boids, FlockingParameters::default(), arena, 30., 0.1)
+        .set_goals(vec![food])
+        .set_steering_parameters(SteeringParameters {
+            goal_seeking_weight: 1.,
+            ..Default::default()
+        });
+    simulation.run();
+
+    let distance =
+        norm((&simulation.boids().boid(0).position - &simulation.goals()[0].position).view());
+    println!("distance to goal: {:.3}", distance);
+    assert!(distance < 2.);
+}
+
+#[test]
+fn order_parameters_of_a_mill_and_a_school() {
+    let arena = Arena::Open {
+        size: ndarray::arr1(&[10., 10.]),
+    };
+    let n = 24;
+    let angle = |i: usize| 2. * std::f64::consts::PI * i as Numeric / n as Numeric;
+    let position = Array2::from_shape_fn((n, 2), |(i, k)| {
+        5. + 3.
+            * if k == 0 {
+                angle(i).cos()
+            } else {
+                angle(i).sin()
+            }
+    });
+    let rotating = Array2::from_shape_fn((n, 2), |(i, k)| {
+        if k == 0 {
+            -angle(i).sin()
+        } else {
+            angle(i).cos()
+        }
+    });
+    let mill = BoidsArray::new(position.clone(), rotating, Array1::ones(n));
+    let statistics = Statistics::new(&mill, &arena, 1.);
+    println!("{}", statistics);
+    assert!(statistics.polarisation < 1e-9);
+    assert!((statistics.milling - 1.).abs() < 1e-9);
+    assert!((statistics.diameter - 6.).abs() < 1e-9);
+    let chord = 2. * 3. * (std::f64::consts::PI / n as Numeric).sin();
+    assert!(statistics
+        .nearest_neighbour_distances
+        .iter()
+        .all(|distance| (distance - chord).abs() < 1e-9));
+    assert_eq!((statistics.no_groups, statistics.largest_group), (1, n));
+    assert_eq!(
+        statistics.nearest_neighbour_histogram(chord / 2.5),
+        vec![0, 0, n]
+    );
+
+    let parallel = Array2::from_shape_fn((n, 2), |(_, k)| if k == 0 { 1. } else { 0. });
+    let school = BoidsArray::new(position, parallel, Array1::ones(n));
+    let statistics = Statistics::new(&school, &arena, 0.5 * chord);
+    assert!((statistics.polarisation - 1.).abs() < 1e-9);
+    assert!(statistics.milling < 1e-9);
+    assert_eq!((statistics.no_groups, statistics.largest_group), (n, 1));
+}
+
+#[test]
+fn groups_across_periodic_edges() {
+    let arena = Arena::Periodic {
+        size: ndarray::arr1(&[10., 10., 10.]),
+    };
+    let position = ndarray::arr2(&[
+        [0.2, 5., 5.],
+        [9.8, 5., 5.],
+        [5., 5., 5.],
+        [5., 5.5, 5.],
+        [5., 5., 6.],
+    ]);
+    let mut sizes = group_sizes(&arena, &position, 1.);
+    sizes.sort();
+    assert_eq!(sizes, vec![2, 3]);
+    assert!((diameter(&arena, &position) - (4.8f64.powi(2) + 1.).sqrt()).abs() < 1e-9);
+
+    let boids = BoidsArray::random(30, &arena, 1.);
+    let mut simulation = Simulation::new(boids, FlockingParameters::default(), arena, 5., 0.5);
+    let statistics = simulation.run_with_statistics(2.);
+    assert_eq!(statistics.len(), 11);
+    assert!((statistics.last().unwrap().time - 5.).abs() < 1e-9);
+}
 
-        time += delta;
+#[test]
+fn grid_neighbours_match_brute_force() {
+    let arena = Arena::Periodic {
+        size: ndarray::arr1(&[12., 12.]),
+    };
+    let boids = BoidsArray::random(60, &arena, 1.);
+    let mut simulation = Simulation::new(boids, FlockingParameters::default(), arena, 3., 0.5);
+    simulation.run();
+    for i in 0..60 {
+        let neighbourhood = Neighbourhood::new(
+            simulation.boids().velocity.row(i).to_owned(),
+            simulation.parameters.view_angle,
+            simulation.parameters.view_distance,
+        );
+        let mut expected: Vec<_> = neighbours(
+            simulation.arena(),
+            simulation.boids().position(),
+            i,
+            &neighbourhood,
+        )
+        .into_iter()
+        .map(|(j, _)| j)
+        .collect();
+        let mut found: Vec<_> = simulation
+            .neighbours(i)
+            .into_iter()
+            .map(|(j, _)| j)
+            .collect();
+        expected.sort();
+        found.sort();
+        assert_eq!(found, expected);
     }
 }
@@ -0,0 +1,141 @@
+//! Calibration of simulation models by Bayesian optimisation: the parameters are chosen to
+//! minimise a loss between simulated and observed trajectories. A stochastic model is calibrated
+//! on its loss averaged over replicates, which run in parallel.
+use crate::bayesian_optimisation::{Model, Objective};
+use crate::nagel_schreckenberg::{fundamental_diagram, Velocity};
+use crate::population::{Count, DiseaseCompartment, Population};
+use ndarray::{Array, Array1, Array2, ArrayView1, Dimension};
+use rayon::prelude::*;
+
+type Numeric = f64;
+
+/// The loss of a single simulation run with the given parameters.
+pub type Loss = Box<dyn Fn(ArrayView1<Numeric>) -> Numeric + Send + Sync>;
+
+pub fn mean_squared_error<D: Dimension>(
+    simulated: &Array<Numeric, D>,
+    observed: &Array<Numeric, D>,
+) -> Numeric {
+    assert_eq!(
+        simulated.shape(),
+        observed.shape(),
+        "trajectories must have the same shape"
+    );
+    simulated
+        .iter()
+        .zip(observed)
+        .map(|(s, o)| (s - o).powi(2))
+        .sum::<Numeric>()
+        / simulated.len() as Numeric
+}
+
+/// Mean of `replicates` independent evaluations of `f`, evaluated in parallel.
+pub fn replicate_mean<F>(replicates: usize, f: F) -> Numeric
+where
+    F: Fn() -> Numeric + Send + Sync,
+{
+    assert!(replicates > 0, "at least one replicate is needed");
+    (0..replicates)
+        .into_par_iter()
+        .map(|_| f())
+        .sum::<Numeric>()
+        / replicates as Numeric
+}
+
+/// The objective that is maximised in place of `loss`: the negative logarithm of the loss
+/// averaged over `replicates`. On the logarithmic scale losses of any magnitude suit the unit
+/// variance of the default kernel.
+pub fn objective(loss: Loss, replicates: usize) -> Objective {
+    Box::new(move |parameters| {
+        let loss = replicate_mean(replicates, || loss(parameters));
+        -(loss + Numeric::EPSILON).ln()
+    })
+}
+
+/// The loss corresponding to a value of the [`objective`].
+pub fn to_loss(value: Numeric) -> Numeric {
+    (-value).exp() - Numeric::EPSILON
+}
+
+/// Bayesian optimisation of the parameters within `bounds` that minimise `loss`, averaged over
+/// `replicates`. Use [`to_loss`] on the values of the samples to recover the loss.
+pub fn calibrate(loss: Loss, bounds: Vec<(Numeric, Numeric)>, replicates: usize) -> Model {
+    Model::new(objective(loss, replicates), bounds)
+}
+
+/// Loss of the SIR model of [`Population::sir`], with parameters `[infection_rate,
+/// recovery_rate]`, against the observed susceptible, infected and recovered counts, one row
+/// per time step.
+pub fn sir_loss(observed: Array2<Count>, susceptible: Count, infected: Count) -> Loss {
+    use DiseaseCompartment::*;
+
+    Box::new(move |parameters| {
+        let simulated = Population::sir(susceptible, infected, parameters[0], parameters[1])
+            .trajectory(&[Susceptible, Infected, Recovered], observed.nrows() - 1);
+        mean_squared_error(&simulated, &observed)
+    })
+}
+
+/// Loss of the Nagel-Schreckenberg model, with parameters `[randomisation_probability]`, against
+/// an observed fundamental diagram of `(density, flow)` pairs.
+pub fn fundamental_diagram_loss(
+    observed: Vec<(Numeric, Numeric)>,
+    road_length: usize,
+    max_velocity: Velocity,
+    transient: usize,
+    measurements: usize,
+) -> Loss {
+    let (densities, flows): (Vec<_>, Vec<_>) = observed.into_iter().unzip();
+    let flows: Array1<Numeric> = flows.into_iter().collect();
+    Box::new(move |parameters| {
+        let simulated = fundamental_diagram(
+            road_length,
+            max_velocity,
+            parameters[0],
+            &densities,
+            transient,
+            measurements,
+        );
+        let simulated: Array1<Numeric> = simulated.into_iter().map(|(_, flow)| flow).collect();
+        mean_squared_error(&simulated, &flows)
+    })
+}
+
+#[test]
+fn averaging_replicates() {
+    use rand::Rng;
+
+    let mean = replicate_mean(4000, || rand::thread_rng().gen::<Numeric>());
+    assert!((mean - 0.5).abs() < 0.03);
+    let loss: Loss = Box::new(|parameters| parameters[0].powi(2));
+    let value = objective(loss, 3)(ndarray::arr1(&[2.]).view());
+    assert!((to_loss(value) - 4.).abs() < 1e-9);
+}
+
+#[test]
+fn calibrating_the_sir_model() {
+    use DiseaseCompartment::*;
+
+    let observed =
+        Population::sir(50., 1., 0.02, 0.5).trajectory(&[Susceptible, Infected, Recovered], 30);
+    let loss = sir_loss(observed, 50., 1.);
+    let (parameters, value) = calibrate(loss, vec![(0.005, 0.04), (0.1, 0.9)], 1)
+        .set_max_iterations(40)
+        .run();
+    println!("parameters {:.4} loss {:.6}", parameters, to_loss(value));
+    assert!((parameters[0] - 0.02).abs() < 0.004);
+    assert!((parameters[1] - 0.5).abs() < 0.1);
+}
+
+#[test]
+fn calibrating_the_randomisation_probability() {
+    let densities = [0.1, 0.2, 0.4, 0.6];
+    let observed = fundamental_diagram(100, 5, 0.3, &densities, 100, 100);
+    let loss = fundamental_diagram_loss(observed, 100, 5, 100, 100);
+    let (parameters, value) = calibrate(loss, vec![(0., 0.9)], 4)
+        .set_noise(1e-2)
+        .set_max_iterations(15)
+        .run();
+    println!("parameters {:.4} loss {:.6}", parameters, to_loss(value));
+    assert!((parameters[0] - 0.3).abs() < 0.1);
+}
@@ -0,0 +1,45 @@
+//! Reading numeric columns from comma-separated files with a header line, as exported by
+//! spreadsheets and public health dashboards.
+use std::path::Path;
+
+type Numeric = f64;
+
+/// The column with the header `column` in the comma-separated `contents`. Empty lines are
+/// skipped.
+pub fn parse_csv_column(contents: &str, column: &str) -> Result<Vec<Numeric>, String> {
+    let mut lines = contents.lines().filter(|line| !line.trim().is_empty());
+    let header = lines.next().ok_or_else(|| "missing header".to_string())?;
+    let index = header
+        .split(',')
+        .position(|name| name.trim() == column)
+        .ok_or_else(|| format!("no column named `{}`", column))?;
+    lines
+        .enumerate()
+        .map(|(row, line)| {
+            line.split(',')
+                .nth(index)
+                .ok_or_else(|| format!("row {} has no column `{}`", row + 1, column))?
+                .trim()
+                .parse::<Numeric>()
+                .map_err(|error| format!("row {}: {}", row + 1, error))
+        })
+        .collect()
+}
+
+pub fn read_csv_column<P: AsRef<Path>>(path: P, column: &str) -> Result<Vec<Numeric>, String> {
+    let contents = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+    parse_csv_column(&contents, column)
+}
+
+#[test]
+fn reading_incidence_from_csv() {
+    let contents = "day,cases\n0, 3\n1,5\n\n2,8\n";
+    assert_eq!(parse_csv_column(contents, "cases"), Ok(vec![3., 5., 8.]));
+    assert!(parse_csv_column(contents, "deaths").is_err());
+    assert!(parse_csv_column("day,cases\n0,three\n", "cases").is_err());
+
+    let path = std::env::temp_dir().join("epibox_incidence.csv");
+    std::fs::write(&path, contents).unwrap();
+    assert_eq!(read_csv_column(&path, "day"), Ok(vec![0., 1., 2.]));
+    std::fs::remove_file(&path).unwrap();
+}
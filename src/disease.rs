@@ -1,10 +1,11 @@
+use crate::interventions::{Interventions, Observation, Transition};
 use std::fmt::{Display, Error, Formatter};
 
 //type Count = u64;
 type Count = f64;
 type Rate = f64;
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Population {
     disease_states: DiseaseStates,
     infection_rate: Rate,
@@ -12,16 +13,16 @@ pub struct Population {
     immunity_decay_rate: Rate,
 }
 
-#[derive(Default)]
-struct DiseaseCompartments<T: Default> {
-    susceptible: T,
-    exposed: T,
-    infectious: T,
-    removed: T,
-    recovered: T,
+#[derive(Default, Debug, Clone)]
+pub(crate) struct DiseaseCompartments<T: Default> {
+    pub(crate) susceptible: T,
+    pub(crate) exposed: T,
+    pub(crate) infectious: T,
+    pub(crate) removed: T,
+    pub(crate) recovered: T,
 }
 
-type DiseaseStates = DiseaseCompartments<Count>;
+pub(crate) type DiseaseStates = DiseaseCompartments<Count>;
 type DiseaseRates = DiseaseCompartments<Rate>;
 
 impl DiseaseStates {
@@ -40,8 +41,10 @@ impl DiseaseStates {
         self.recovered += recovered as Rate;
     }
 
+    /// `removed` is left out, as the models here move the same individuals into it as into
+    /// `recovered`.
     pub fn total(&self) -> Count {
-        self.susceptible + self.exposed + self.infectious + self.removed + self.recovered
+        self.susceptible + self.exposed + self.infectious + self.recovered
     }
 }
 
@@ -60,14 +63,89 @@ impl Population {
         }
     }
 
+    pub(crate) fn disease_states(&self) -> &DiseaseStates {
+        &self.disease_states
+    }
+
+    pub(crate) fn disease_states_mut(&mut self) -> &mut DiseaseStates {
+        &mut self.disease_states
+    }
+
+    pub fn susceptible(&self) -> Count {
+        self.disease_states.susceptible
+    }
+
+    pub fn infectious(&self) -> Count {
+        self.disease_states.infectious
+    }
+
+    pub fn removed(&self) -> Count {
+        self.disease_states.removed
+    }
+
+    pub fn recovered(&self) -> Count {
+        self.disease_states.recovered
+    }
+
+    pub fn total_population(&self) -> Count {
+        self.disease_states.total()
+    }
+
+    pub fn infection_rate(&self) -> Rate {
+        self.infection_rate
+    }
+
+    pub fn recovery_rate(&self) -> Rate {
+        self.recovery_rate
+    }
+
+    pub fn immunity_decay_rate(&self) -> Rate {
+        self.immunity_decay_rate
+    }
+
+    /// Number of new infections in the next update.
+    pub fn incidence(&self) -> Count {
+        self.infection_rate * self.disease_states.susceptible * self.disease_states.infectious
+    }
+
     /// Reproduction number according to SIR-model. Should be taken with a grain of salt.
-    fn reproduction_number(&self) -> Rate {
+    pub fn reproduction_number(&self) -> Rate {
         (self.infection_rate * (self.disease_states.susceptible as Rate)) / self.recovery_rate
     }
 
-    fn update_disease_state(&mut self) {
+    pub fn update_disease_state(&mut self) {
         self.disease_states.update_disease(self.disease_rate());
     }
+
+    /// Like [`Population::update_disease_state`] at `time`, with the rates set by the schedules
+    /// of `interventions`. The vaccinations and isolations of their response are made at the
+    /// start of the update.
+    pub fn update_with(&mut self, interventions: &mut Interventions, time: usize) {
+        let response = interventions.respond(Observation {
+            time,
+            susceptible: self.susceptible(),
+            infectious: self.infectious(),
+        });
+        let states = &mut self.disease_states;
+        let isolated = response.isolation * states.infectious;
+        states.susceptible -= response.vaccinations;
+        states.infectious -= isolated;
+        states.removed += response.vaccinations + isolated;
+        states.recovered += response.vaccinations + isolated;
+
+        let scheduled = Population {
+            disease_states: self.disease_states.clone(),
+            infection_rate: interventions.rate(Transition::Infection, self.infection_rate, time)
+                * response.infection_factor,
+            recovery_rate: interventions.rate(Transition::Recovery, self.recovery_rate, time),
+            immunity_decay_rate: interventions.rate(
+                Transition::ImmunityDecay,
+                self.immunity_decay_rate,
+                time,
+            ),
+        };
+        self.disease_states.update_disease(scheduled.disease_rate());
+    }
 }
 
 impl Display for Population {
@@ -127,7 +205,7 @@ fn numerical_example_2() {
     }
 }
 
-fn create_sirs_population(
+pub fn create_sirs_population(
     initial_susceptible_population: Count,
     initial_infected: Count,
     infection_rate: Rate,
@@ -349,3 +427,40 @@ fn numerical_example_4() {
 //        }
 //    }
 //}
+
+#[test]
+fn lockdown_flattens_the_curve() {
+    use crate::interventions::Policy;
+
+    let peak = |interventions: &mut Interventions| {
+        let mut population = create_sir_population(999., 1., 0.0006, 0.2);
+        (0..200).fold(0., |peak: Count, time| {
+            population.update_with(interventions, time);
+            peak.max(population.infectious())
+        })
+    };
+    let unmitigated = peak(&mut Interventions::new());
+    let mut lockdown = Interventions::new().add_policy(Policy::Lockdown {
+        start: 50.,
+        end: 10.,
+        factor: 0.2,
+    });
+    let mitigated = peak(&mut lockdown);
+    println!("peak {:.2} with lockdown {:.2}", unmitigated, mitigated);
+    assert!(mitigated < 0.5 * unmitigated);
+    assert!(!lockdown.lockdown_times().is_empty());
+}
+
+/// The same individuals are moved into `removed` and `recovered`, so the total counts them once.
+#[test]
+fn total_population_is_conserved() {
+    let mut population = create_sir_population(50., 1., 0.02, 0.5);
+    for _ in 0..20 {
+        population.update_disease_state();
+        assert_eq!(population.removed(), population.recovered());
+        let counted = population.susceptible() + population.infectious() + population.recovered();
+        assert!((population.total_population() - counted).abs() < 1e-12);
+        assert!((population.total_population() - 51.).abs() < 1e-9);
+    }
+    assert!(population.recovered() > 40.);
+}
@@ -0,0 +1,379 @@
+//! Summary quantities of an epidemic in a compartment graph declared with
+//! [`population::Population`], or converted from a [`disease::Population`]: the basic
+//! reproduction number from the next-generation matrix, the effective reproduction number over a
+//! trajectory, the final epidemic size, the peak of the infected and the herd immunity threshold.
+//!
+//! Source: [Diekmann, Heesterbeek & Roberts (2010)](https://doi.org/10.1098/rsif.2009.0386)
+//!
+//! [`disease::Population`]: crate::disease::Population
+//! [`population::Population`]: crate::population::Population
+use crate::linalg::inverse;
+use crate::population::{Count, DiseaseCompartment, Flow, Population, Rate};
+use ndarray::{Array1, Array2};
+use std::collections::HashMap;
+use std::fmt::{Display, Error, Formatter};
+
+type Numeric = f64;
+
+/// Step of the finite differences that linearise the flows around the disease-free state.
+const STEP: Numeric = 1e-6;
+
+/// The infected compartments present in `population`, in a fixed order.
+pub fn infected_compartments(population: &Population) -> Vec<DiseaseCompartment> {
+    [DiseaseCompartment::Exposed, DiseaseCompartment::Infected]
+        .iter()
+        .copied()
+        .filter(|compartment| population.counts().contains_key(compartment))
+        .collect()
+}
+
+/// Total count of the infected compartments.
+pub fn infected(population: &Population) -> Count {
+    infected_compartments(population)
+        .into_iter()
+        .map(|compartment| population.count(compartment))
+        .sum()
+}
+
+/// New infections into, and the remaining net outflow from, each of the `infected` compartments.
+fn infection_and_transfer(
+    population: &Population,
+    count: &HashMap<DiseaseCompartment, Count>,
+    infected: &[DiseaseCompartment],
+) -> (Array1<Rate>, Array1<Rate>) {
+    let position = |compartment| infected.iter().position(|&c| c == compartment);
+    let mut infection = Array1::zeros(infected.len());
+    let mut transfer = Array1::zeros(infected.len());
+    for flow in population.flows(count) {
+        match flow {
+            Flow::Transition { to, amount, .. } if flow.is_infection() => {
+                if let Some(i) = position(to) {
+                    infection[i] += amount;
+                }
+            }
+            Flow::Transition { from, to, amount } => {
+                if let Some(i) = position(from) {
+                    transfer[i] += amount;
+                }
+                if let Some(i) = position(to) {
+                    transfer[i] -= amount;
+                }
+            }
+            Flow::Term {
+                compartment,
+                amount,
+            } => {
+                if let Some(i) = position(compartment) {
+                    transfer[i] -= amount;
+                }
+            }
+        }
+    }
+    (infection, transfer)
+}
+
+/// The next-generation matrix `K = F V⁻¹` at the disease-free state with `susceptible`
+/// individuals, ordered as [`infected_compartments`]. `F` is the linearised rate of new
+/// infections and `V` that of every other transfer out of the infected compartments; both are
+/// approximated by finite differences.
+pub fn next_generation_matrix(population: &Population, susceptible: Count) -> Array2<Numeric> {
+    let infected = infected_compartments(population);
+    let n = infected.len();
+    assert!(n > 0, "the population has no infected compartments");
+
+    let disease_free: HashMap<_, _> = population
+        .counts()
+        .keys()
+        .map(|&compartment| {
+            let count = if compartment == DiseaseCompartment::Susceptible {
+                susceptible
+            } else {
+                0.
+            };
+            (compartment, count)
+        })
+        .collect();
+    let (infection_0, transfer_0) = infection_and_transfer(population, &disease_free, &infected);
+
+    let mut f = Array2::zeros((n, n));
+    let mut v = Array2::zeros((n, n));
+    for (j, compartment) in infected.iter().enumerate() {
+        let mut perturbed = disease_free.clone();
+        *perturbed.entry(*compartment).or_default() += STEP;
+        let (infection, transfer) = infection_and_transfer(population, &perturbed, &infected);
+        f.column_mut(j).assign(&((infection - &infection_0) / STEP));
+        v.column_mut(j).assign(&((transfer - &transfer_0) / STEP));
+    }
+
+    let v_inverse = inverse(&v).expect("the infected compartments are never left");
+    f.dot(&v_inverse)
+}
+
+/// The largest eigenvalue of the non-negative matrix `k`, by power iteration on `k + I`, whose
+/// dominant eigenvalue is unique even when that of `k` is not.
+pub fn spectral_radius(k: &Array2<Numeric>) -> Numeric {
+    let shifted = k + &Array2::<Numeric>::eye(k.nrows());
+    let mut vector = Array1::from_elem(k.nrows(), 1. / k.nrows() as Numeric);
+    let mut eigenvalue = 0.;
+    for _ in 0..10_000 {
+        let next = shifted.dot(&vector);
+        let norm = next.sum();
+        if norm == 0. {
+            return 0.;
+        }
+        vector = next / norm;
+        let converged = (norm - eigenvalue).abs() < 1e-12 * norm;
+        eigenvalue = norm;
+        if converged {
+            break;
+        }
+    }
+    eigenvalue - 1.
+}
+
+/// The basic reproduction number, with the whole population susceptible.
+pub fn basic_reproduction_number(population: &Population) -> Rate {
+    spectral_radius(&next_generation_matrix(
+        population,
+        population.total_population(),
+    ))
+}
+
+/// The effective reproduction number at the current number of susceptible individuals.
+pub fn effective_reproduction_number(population: &Population) -> Rate {
+    spectral_radius(&next_generation_matrix(
+        population,
+        population.count(DiseaseCompartment::Susceptible),
+    ))
+}
+
+/// The share of the population that must be immune for an epidemic to decline.
+pub fn herd_immunity_threshold(reproduction_number: Rate) -> Numeric {
+    (1. - 1. / reproduction_number).max(0.)
+}
+
+/// The share of the population ever infected in the SIR model, i.e. the root of
+/// `z = 1 - exp(-R0 z)` in `(0, 1]`, or zero when there is no epidemic.
+pub fn sir_final_size(reproduction_number: Rate) -> Numeric {
+    if reproduction_number <= 1. {
+        return 0.;
+    }
+    // the right hand side is a contraction near the positive root, when starting from 1.
+    let mut z = 1.;
+    for _ in 0..1_000 {
+        let next = 1. - (-reproduction_number * z).exp();
+        if (next - z).abs() < 1e-14 {
+            return next;
+        }
+        z = next;
+    }
+    z
+}
+
+/// Whether the only flows are `Susceptible -> Infected -> Recovered` (or `Removed`).
+fn is_sir(population: &Population) -> bool {
+    use DiseaseCompartment::*;
+
+    let flows = population.flows(population.counts());
+    flows.len() == 2
+        && flows.iter().all(|flow| match flow {
+            Flow::Transition { from, to, .. } => matches!(
+                (from, to),
+                (Susceptible, Infected) | (Infected, Recovered) | (Infected, Removed)
+            ),
+            Flow::Term { .. } => false,
+        })
+}
+
+#[derive(Debug, Clone)]
+pub struct Summary {
+    basic_reproduction_number: Rate,
+    effective_reproduction_number: Vec<Rate>,
+    infected: Vec<Count>,
+    simulated_final_size: Numeric,
+    final_size: Numeric,
+}
+
+impl Summary {
+    /// Summarises the epidemic in `population`, which is updated until the infected fall below
+    /// a millionth of the population or `max_steps` updates have been made.
+    pub fn new(population: &mut Population, max_steps: usize) -> Self {
+        let initial_population = population.total_population();
+        let basic_reproduction_number = basic_reproduction_number(population);
+
+        let mut effective_reproduction_number = vec![effective_reproduction_number(population)];
+        let mut infected = vec![self::infected(population)];
+        let mut infections = 0.;
+        for _ in 0..max_steps {
+            if *infected.last().unwrap() < 1e-6 * initial_population {
+                break;
+            }
+            infections += population
+                .flows(population.counts())
+                .iter()
+                .filter(|flow| flow.is_infection())
+                .map(|flow| match flow {
+                    Flow::Transition { amount, .. } => amount,
+                    Flow::Term { amount, .. } => amount,
+                })
+                .sum::<Count>();
+            population.update_disease_states();
+            effective_reproduction_number.push(self::effective_reproduction_number(population));
+            infected.push(self::infected(population));
+        }
+
+        let simulated_final_size = infections / initial_population;
+        let final_size = if is_sir(population) {
+            sir_final_size(basic_reproduction_number)
+        } else {
+            simulated_final_size
+        };
+        Self {
+            basic_reproduction_number,
+            effective_reproduction_number,
+            infected,
+            simulated_final_size,
+            final_size,
+        }
+    }
+
+    pub fn basic_reproduction_number(&self) -> Rate {
+        self.basic_reproduction_number
+    }
+
+    /// The effective reproduction number at each time, starting with the initial state.
+    pub fn effective_reproduction_number(&self) -> &[Rate] {
+        &self.effective_reproduction_number
+    }
+
+    /// The total of the infected compartments at each time, starting with the initial state.
+    pub fn infected(&self) -> &[Count] {
+        &self.infected
+    }
+
+    pub fn herd_immunity_threshold(&self) -> Numeric {
+        herd_immunity_threshold(self.basic_reproduction_number)
+    }
+
+    /// The time and the number of the infected at their peak.
+    pub fn peak(&self) -> (usize, Count) {
+        self.infected
+            .iter()
+            .copied()
+            .enumerate()
+            .fold((0, Count::MIN), |peak, (time, count)| {
+                if count > peak.1 {
+                    (time, count)
+                } else {
+                    peak
+                }
+            })
+    }
+
+    /// The share of the initial population infected during the epidemic, from
+    /// [`sir_final_size`] for the SIR model and from the simulation otherwise.
+    pub fn final_size(&self) -> Numeric {
+        self.final_size
+    }
+
+    /// The infections during the simulated updates per individual of the initial population,
+    /// which exceeds one when immunity wanes and individuals are reinfected.
+    pub fn simulated_final_size(&self) -> Numeric {
+        self.simulated_final_size
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        let (peak_time, peak_height) = self.peak();
+        writeln!(f, "R0: {:.4}", self.basic_reproduction_number)?;
+        writeln!(
+            f,
+            "herd immunity threshold: {:.4}",
+            self.herd_immunity_threshold()
+        )?;
+        writeln!(f, "peak: {:.4} at time {}", peak_height, peak_time)?;
+        write!(f, "final size: {:.4}", self.final_size)?;
+
+        Ok(())
+    }
+}
+
+#[test]
+fn sir_reproduction_number() {
+    use crate::disease::create_sir_population;
+
+    let population = Population::sir(999., 1., 0.0004, 0.2);
+    assert!((basic_reproduction_number(&population) - 2.).abs() < 1e-6);
+    assert!((herd_immunity_threshold(2.) - 0.5).abs() < 1e-12);
+
+    let converted = Population::from(&create_sir_population(999., 1., 0.0004, 0.2));
+    assert!((basic_reproduction_number(&converted) - 2.).abs() < 1e-6);
+}
+
+#[test]
+fn seir_reproduction_number() {
+    use DiseaseCompartment::*;
+
+    // only the latent compartment contributes to the force of infection
+    let population = Population::new()
+        .add_compartment(Susceptible, 1000.)
+        .add_compartment(Exposed, 1.)
+        .add_compartment(Infected, 0.)
+        .add_compartment(Recovered, 0.)
+        .add_transition(
+            Susceptible,
+            Exposed,
+            Box::new(|sus, exp| 0.0001 * sus * exp),
+        )
+        .add_transition(Exposed, Infected, Box::new(|exp, _inf| 0.25 * exp))
+        .add_transition(Infected, Recovered, Box::new(|inf, _rec| 0.2 * inf));
+    let k = next_generation_matrix(&population, 1001.);
+    println!("{:.6}", k);
+    assert!((basic_reproduction_number(&population) - 0.0001 * 1001. / 0.25).abs() < 1e-6);
+}
+
+#[test]
+fn sir_epidemic_summary() {
+    let mut population = Population::sir(999., 1., 0.0004, 0.2);
+    let summary = Summary::new(&mut population, 1_000);
+    println!("{}", summary);
+
+    assert!(
+        (summary.final_size() - sir_final_size(summary.basic_reproduction_number())).abs() < 1e-12
+    );
+    assert!((summary.final_size() - 0.7968).abs() < 1e-3);
+    assert!((summary.simulated_final_size() - summary.final_size()).abs() < 0.03);
+
+    let (peak_time, peak_height) = summary.peak();
+    assert!(peak_time > 0 && peak_height > 1.);
+    let rt = summary.effective_reproduction_number();
+    assert!(rt[peak_time] <= 1. && rt[peak_time - 1] > 1.);
+}
+
+#[test]
+fn converted_sir_epidemic_summary() {
+    use crate::disease::create_sir_population;
+
+    let mut population = Population::from(&create_sir_population(999., 1., 0.0004, 0.2));
+    assert!(is_sir(&population));
+    let summary = Summary::new(&mut population, 1_000);
+    println!("{}", summary);
+    // the analytic final size, not the simulated one
+    assert_eq!(
+        summary.final_size(),
+        sir_final_size(summary.basic_reproduction_number())
+    );
+    assert!((summary.simulated_final_size() - summary.final_size()).abs() < 0.03);
+}
+
+#[test]
+fn sirs_epidemic_summary() {
+    use crate::disease::create_sirs_population;
+
+    let mut population = Population::from(&create_sirs_population(999., 1., 0.0004, 0.2, 0.01));
+    let summary = Summary::new(&mut population, 200);
+    println!("{}", summary);
+    // waning immunity lets individuals be infected more than once
+    assert!(summary.final_size() > sir_final_size(2.));
+}
@@ -0,0 +1,382 @@
+//! Equilibria of systems of ordinary differential equations `dx/dt = f(x)` and their local
+//! stability. Equilibria are found by Newton iteration, the Jacobian there is approximated by
+//! central differences and the equilibrium is classified by the eigenvalues of the Jacobian.
+//! A one-parameter continuation follows an equilibrium as a parameter varies, which traces a
+//! branch of a bifurcation diagram.
+//!
+//! Source: [Strogatz, Nonlinear Dynamics and Chaos](https://doi.org/10.1201/9780429492563)
+use crate::linalg::inverse;
+use ndarray::{Array1, Array2, ArrayView1};
+use num_complex::Complex;
+use std::fmt::{Display, Error, Formatter};
+
+type Numeric = f64;
+
+const TOLERANCE: Numeric = 1e-10;
+const MAX_ITERATIONS: usize = 100;
+
+/// Jacobian of `f` at `x`, by central differences.
+pub fn jacobian<F>(f: F, x: ArrayView1<Numeric>) -> Array2<Numeric>
+where
+    F: Fn(ArrayView1<Numeric>) -> Array1<Numeric>,
+{
+    let n = x.len();
+    let mut jacobian = Array2::zeros((n, n));
+    for j in 0..n {
+        let step = 1e-6 * x[j].abs().max(1.);
+        let mut forward = x.to_owned();
+        let mut backward = x.to_owned();
+        forward[j] += step;
+        backward[j] -= step;
+        jacobian
+            .column_mut(j)
+            .assign(&((f(forward.view()) - f(backward.view())) / (2. * step)));
+    }
+    jacobian
+}
+
+fn max_norm(x: &Array1<Numeric>) -> Numeric {
+    x.iter().fold(0., |norm, x| norm.max(x.abs()))
+}
+
+/// A root of `f` by Newton iteration from `initial`, halving the step while it does not decrease
+/// the residual. Returns `None` if the Jacobian becomes singular or the iteration does not
+/// converge within `max_iterations`.
+pub fn newton<F>(
+    f: F,
+    initial: ArrayView1<Numeric>,
+    tolerance: Numeric,
+    max_iterations: usize,
+) -> Option<Array1<Numeric>>
+where
+    F: Fn(ArrayView1<Numeric>) -> Array1<Numeric>,
+{
+    let mut x = initial.to_owned();
+    let mut residual = f(x.view());
+    for _ in 0..max_iterations {
+        if max_norm(&residual) < tolerance {
+            return Some(x);
+        }
+        let step = inverse(&jacobian(&f, x.view()))?.dot(&residual);
+        let mut scale = 1.;
+        loop {
+            let next = &x - &(scale * &step);
+            let next_residual = f(next.view());
+            if max_norm(&next_residual) < max_norm(&residual) || scale < 1e-3 {
+                x = next;
+                residual = next_residual;
+                break;
+            }
+            scale /= 2.;
+        }
+    }
+    if max_norm(&residual) < tolerance {
+        Some(x)
+    } else {
+        None
+    }
+}
+
+/// Roots closer than this, relative to the largest root, are taken as one repeated root. It is
+/// about the cube root of the machine epsilon, the accuracy of a triple root.
+const CLUSTER_TOLERANCE: Numeric = 1e-5;
+
+/// `z` without its imaginary part if that is below the square root of the machine epsilon,
+/// relative to `scale`.
+fn real_if_close(z: Complex<Numeric>, scale: Numeric) -> Complex<Numeric> {
+    if z.im.abs() < Numeric::EPSILON.sqrt() * scale {
+        Complex::new(z.re, 0.)
+    } else {
+        z
+    }
+}
+
+/// Coefficients of the characteristic polynomial `det(λI - a)`, lowest degree first, by the
+/// Faddeev–LeVerrier algorithm.
+fn characteristic_polynomial(a: &Array2<Numeric>) -> Vec<Numeric> {
+    let n = a.nrows();
+    let mut coefficients = vec![0.; n + 1];
+    coefficients[n] = 1.;
+    let mut m = Array2::<Numeric>::zeros((n, n));
+    for k in 1..=n {
+        m = a.dot(&m) + &(coefficients[n - k + 1] * &Array2::<Numeric>::eye(n));
+        coefficients[n - k] = -a.dot(&m).diag().sum() / k as Numeric;
+    }
+    coefficients
+}
+
+/// The polynomial with `coefficients`, lowest degree first, at `z`.
+fn evaluate(coefficients: &[Numeric], z: Complex<Numeric>) -> Complex<Numeric> {
+    coefficients
+        .iter()
+        .rev()
+        .fold(Complex::new(0., 0.), |value, &c| value * z + c)
+}
+
+fn derivative(coefficients: &[Numeric]) -> Vec<Numeric> {
+    coefficients
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(k, c)| k as Numeric * c)
+        .collect()
+}
+
+/// Eigenvalues of the square matrix `a`, as the roots of its characteristic polynomial found by
+/// the Durand–Kerner method, ordered by decreasing real part. Meant for the small systems of
+/// compartment and population models.
+pub fn eigenvalues(a: &Array2<Numeric>) -> Vec<Complex<Numeric>> {
+    assert!(a.is_square(), "eigenvalues of a non-square matrix");
+    let coefficients = characteristic_polynomial(a);
+    let n = a.nrows();
+    let polynomial = |z| evaluate(&coefficients, z);
+
+    // the roots lie within this radius
+    let radius = 1.
+        + coefficients[..n]
+            .iter()
+            .fold(0., |r: Numeric, c| r.max(c.abs()));
+    let mut roots: Vec<_> = (0..n)
+        .map(|k| radius * Complex::new(0.4, 0.9).powf(k as Numeric))
+        .collect();
+    for _ in 0..10_000 {
+        let mut change: Numeric = 0.;
+        for i in 0..n {
+            let denominator = (0..n)
+                .filter(|&j| j != i)
+                .fold(Complex::new(1., 0.), |d, j| d * (roots[i] - roots[j]));
+            let correction = polynomial(roots[i]) / denominator;
+            roots[i] -= correction;
+            change = change.max(correction.norm());
+        }
+        if change < 1e-14 * radius {
+            break;
+        }
+    }
+
+    // The iteration only finds a root of multiplicity k to about the k-th root of the rounding
+    // error, so a repeated real root comes out as a cluster of nearly equal roots with small
+    // imaginary parts. It is a simple root of the (k - 1)-th derivative, where Newton iteration
+    // from the mean of the cluster finds it to the rounding error.
+    let scale = roots.iter().fold(1., |s: Numeric, z| s.max(z.norm()));
+    let mut clusters: Vec<Vec<Complex<Numeric>>> = Vec::new();
+    for z in roots {
+        match clusters.iter_mut().find(|cluster| {
+            cluster
+                .iter()
+                .any(|w| (z - w).norm() < CLUSTER_TOLERANCE * scale)
+        }) {
+            Some(cluster) => cluster.push(z),
+            None => clusters.push(vec![z]),
+        }
+    }
+    let mut roots: Vec<_> = clusters
+        .into_iter()
+        .flat_map(|cluster| {
+            let multiplicity = cluster.len();
+            let mut z = cluster.iter().sum::<Complex<Numeric>>() / multiplicity as Numeric;
+            if multiplicity > 1 {
+                let mut derivatives = coefficients.clone();
+                for _ in 1..multiplicity {
+                    derivatives = derivative(&derivatives);
+                }
+                let slope = derivative(&derivatives);
+                for _ in 0..10 {
+                    let denominator = evaluate(&slope, z);
+                    if denominator.norm() == 0. {
+                        break;
+                    }
+                    z -= evaluate(&derivatives, z) / denominator;
+                }
+            }
+            vec![real_if_close(z, scale); multiplicity]
+        })
+        .collect();
+    roots.sort_by(|a, b| b.re.partial_cmp(&a.re).unwrap());
+    roots
+}
+
+/// Local stability of an equilibrium, from the real parts of the eigenvalues of the Jacobian.
+/// A focus has complex eigenvalues, so trajectories spiral around it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stability {
+    StableNode,
+    StableFocus,
+    UnstableNode,
+    UnstableFocus,
+    Saddle,
+    /// An eigenvalue has a zero real part, e.g. a centre, and linearisation is inconclusive.
+    NonHyperbolic,
+}
+
+impl Stability {
+    pub fn classify(eigenvalues: &[Complex<Numeric>]) -> Self {
+        let scale = eigenvalues.iter().fold(1., |s: Numeric, z| s.max(z.norm()));
+        let tolerance = 1e-7 * scale;
+        let oscillating = eigenvalues
+            .iter()
+            .any(|&z| real_if_close(z, scale).im != 0.);
+        if eigenvalues.iter().any(|z| z.re.abs() < tolerance) {
+            Stability::NonHyperbolic
+        } else if eigenvalues.iter().all(|z| z.re < 0.) {
+            if oscillating {
+                Stability::StableFocus
+            } else {
+                Stability::StableNode
+            }
+        } else if eigenvalues.iter().all(|z| z.re > 0.) {
+            if oscillating {
+                Stability::UnstableFocus
+            } else {
+                Stability::UnstableNode
+            }
+        } else {
+            Stability::Saddle
+        }
+    }
+
+    pub fn is_stable(&self) -> bool {
+        matches!(self, Stability::StableNode | Stability::StableFocus)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Equilibrium {
+    state: Array1<Numeric>,
+    jacobian: Array2<Numeric>,
+    eigenvalues: Vec<Complex<Numeric>>,
+    stability: Stability,
+}
+
+impl Equilibrium {
+    /// The equilibrium of `f` that Newton iteration reaches from `initial`, if any.
+    pub fn find<F>(f: F, initial: ArrayView1<Numeric>) -> Option<Self>
+    where
+        F: Fn(ArrayView1<Numeric>) -> Array1<Numeric>,
+    {
+        let state = newton(&f, initial, TOLERANCE, MAX_ITERATIONS)?;
+        Some(Self::at(f, state))
+    }
+
+    /// Linearisation of `f` at `state`, which is assumed to be an equilibrium.
+    pub fn at<F>(f: F, state: Array1<Numeric>) -> Self
+    where
+        F: Fn(ArrayView1<Numeric>) -> Array1<Numeric>,
+    {
+        let jacobian = jacobian(f, state.view());
+        let eigenvalues = eigenvalues(&jacobian);
+        let stability = Stability::classify(&eigenvalues);
+        Self {
+            state,
+            jacobian,
+            eigenvalues,
+            stability,
+        }
+    }
+
+    pub fn state(&self) -> &Array1<Numeric> {
+        &self.state
+    }
+
+    pub fn jacobian(&self) -> &Array2<Numeric> {
+        &self.jacobian
+    }
+
+    pub fn eigenvalues(&self) -> &[Complex<Numeric>] {
+        &self.eigenvalues
+    }
+
+    pub fn stability(&self) -> Stability {
+        self.stability
+    }
+}
+
+impl Display for Equilibrium {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "{:.6} {:?} eigenvalues", self.state, self.stability)?;
+        for z in &self.eigenvalues {
+            write!(f, " {:.6}", z)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Natural-parameter continuation: follows the equilibrium of `f(parameter, x)` reached from
+/// `initial` at the first of `parameters` through the rest of them, predicting each equilibrium
+/// by linear extrapolation from the previous two. Stops where the branch is lost, e.g. at a fold.
+pub fn continuation<F>(
+    f: F,
+    initial: ArrayView1<Numeric>,
+    parameters: &[Numeric],
+) -> Vec<(Numeric, Equilibrium)>
+where
+    F: Fn(Numeric, ArrayView1<Numeric>) -> Array1<Numeric>,
+{
+    let mut branch: Vec<(Numeric, Equilibrium)> = Vec::with_capacity(parameters.len());
+    for &parameter in parameters {
+        let prediction = match branch.as_slice() {
+            [] => initial.to_owned(),
+            [(_, last)] => last.state.clone(),
+            [.., (p0, e0), (p1, e1)] => {
+                let slope = (&e1.state - &e0.state) / (p1 - p0);
+                &e1.state + &(slope * (parameter - p1))
+            }
+        };
+        match Equilibrium::find(|x| f(parameter, x), prediction.view()) {
+            Some(equilibrium) => branch.push((parameter, equilibrium)),
+            None => break,
+        }
+    }
+    branch
+}
+
+#[test]
+fn eigenvalues_of_small_matrices() {
+    let a = ndarray::arr2(&[[2., 1.], [1., 2.]]);
+    let values = eigenvalues(&a);
+    assert!((values[0] - Complex::new(3., 0.)).norm() < 1e-9);
+    assert!((values[1] - Complex::new(1., 0.)).norm() < 1e-9);
+
+    let rotation = ndarray::arr2(&[[-0.1, -2., 0.], [2., -0.1, 0.], [0., 0., -3.]]);
+    let values = eigenvalues(&rotation);
+    assert!((values[0].re + 0.1).abs() < 1e-9 && (values[0].im.abs() - 2.).abs() < 1e-9);
+    assert!((values[2] - Complex::new(-3., 0.)).norm() < 1e-9);
+    assert_eq!(Stability::classify(&values), Stability::StableFocus);
+
+    let repeated = ndarray::arr2(&[[-1., 0.], [0., -1.]]);
+    let values = eigenvalues(&repeated);
+    assert!(values
+        .iter()
+        .all(|z| z.im == 0. && (z.re + 1.).abs() < 1e-12));
+    assert_eq!(Stability::classify(&values), Stability::StableNode);
+    let jordan = ndarray::arr2(&[[2., 1., 0.], [0., 2., 0.], [0., 0., 2.]]);
+    let values = eigenvalues(&jordan);
+    assert!(values
+        .iter()
+        .all(|z| z.im == 0. && (z.re - 2.).abs() < 1e-12));
+    assert_eq!(Stability::classify(&values), Stability::UnstableNode);
+    // rounding in the eigenvalues doesn't make a node a focus
+    let noisy = [Complex::new(-1., 1e-12), Complex::new(-1., -1e-12)];
+    assert_eq!(Stability::classify(&noisy), Stability::StableNode);
+}
+
+#[test]
+fn saddle_node_branch() {
+    use ndarray::arr1;
+
+    // dx/dt = r + x^2 has the equilibria ±sqrt(-r), which meet and vanish at r = 0
+    let f = |r: Numeric, x: ArrayView1<Numeric>| arr1(&[r + x[0] * x[0]]);
+    let stable = Equilibrium::find(|x| f(-1., x), arr1(&[-2.]).view()).unwrap();
+    assert!((stable.state()[0] + 1.).abs() < 1e-9);
+    assert_eq!(stable.stability(), Stability::StableNode);
+    let unstable = Equilibrium::find(|x| f(-1., x), arr1(&[2.]).view()).unwrap();
+    assert_eq!(unstable.stability(), Stability::UnstableNode);
+
+    let parameters: Vec<_> = (0..20).map(|k| -1. + 0.1 * k as Numeric).collect();
+    let branch = continuation(f, arr1(&[-1.]).view(), &parameters);
+    assert!(branch.len() < parameters.len());
+    for (r, equilibrium) in &branch {
+        assert!((equilibrium.state()[0] + (-r).sqrt()).abs() < 1e-4);
+    }
+}
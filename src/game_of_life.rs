@@ -1,26 +1,449 @@
 //! Source: [Assignment 3](http://prac.im.pwr.wroc.pl/~szwabin/assets/abm/labs/l3.pdf)
-struct Grid2D;
+//!
+//! A life-like cellular automaton. The classic Game of Life is the rule `B3/S23`, but any
+//! rule in the B/S notation is accepted, e.g. HighLife (`B36/S23`) or Day & Night
+//! (`B3678/S34678`).
+use ndarray::Array2;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Error, Formatter};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
 
-type Grid2DIdx = i32;
+pub type Grid2DIdx = i32;
 
-struct Universe {
-    grid2d: Grid2D,
+/// Signature shared by the constructors in [`patterns`].
+pub type PatternConstructor = fn(&mut Universe, Grid2DIdx, Grid2DIdx);
+
+/// Birth and survival conditions, indexed by the number of live neighbours (0 to 8).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rule {
+    birth: [bool; 9],
+    survival: [bool; 9],
 }
 
-enum State {
+impl Rule {
+    /// Conway's Game of Life, `B3/S23`.
+    pub fn conway() -> Self {
+        "B3/S23".parse().unwrap()
+    }
+
+    /// `B36/S23`
+    pub fn highlife() -> Self {
+        "B36/S23".parse().unwrap()
+    }
+
+    /// `B3678/S34678`
+    pub fn day_and_night() -> Self {
+        "B3678/S34678".parse().unwrap()
+    }
+
+    fn next_state(&self, state: State, live_neighbours: usize) -> State {
+        match self.transition(state, live_neighbours) {
+            Some(Transitions::Reproduction) => State::Alive,
+            Some(Transitions::Underpopulation) | Some(Transitions::Overpopulation) => State::Dead,
+            None => state,
+        }
+    }
+
+    fn transition(&self, state: State, live_neighbours: usize) -> Option<Transitions> {
+        match state {
+            State::Dead if self.birth[live_neighbours] => Some(Transitions::Reproduction),
+            State::Dead => None,
+            State::Alive if self.survival[live_neighbours] => None,
+            State::Alive => {
+                // A cell dies of underpopulation if it had fewer neighbours than any
+                // count that would have let it survive.
+                if self.survival[live_neighbours..].iter().any(|&x| x) {
+                    Some(Transitions::Underpopulation)
+                } else {
+                    Some(Transitions::Overpopulation)
+                }
+            }
+        }
+    }
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Self::conway()
+    }
+}
+
+/// Parses rule strings such as `B3/S23` (case-insensitive, either order of the two parts) or
+/// the older `S/B` form, such as `23/3`.
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Golly appends the grid topology after a colon, e.g. `B3/S23:T100,100`.
+        let s = s.trim().split(':').next().unwrap_or_default();
+        // The older notation, e.g. `23/3`, lists survival before birth and has no letters.
+        if !s.chars().any(char::is_alphabetic) {
+            let mut parts = s.split('/');
+            if let (Some(survival), Some(birth), None) = (parts.next(), parts.next(), parts.next())
+            {
+                return format!("B{}/S{}", birth, survival).parse();
+            }
+        }
+
+        let mut birth = None;
+        let mut survival = None;
+        for part in s.trim().split('/') {
+            let mut chars = part.chars();
+            let counts = match chars.next() {
+                Some('B') | Some('b') => &mut birth,
+                Some('S') | Some('s') => &mut survival,
+                _ => return Err(format!("invalid rule component `{}` in `{}`", part, s)),
+            };
+            let mut neighbour_counts = [false; 9];
+            for digit in chars {
+                match digit.to_digit(10) {
+                    Some(count) if count <= 8 => neighbour_counts[count as usize] = true,
+                    _ => return Err(format!("invalid neighbour count `{}` in `{}`", digit, s)),
+                }
+            }
+            if counts.replace(neighbour_counts).is_some() {
+                return Err(format!("rule component given twice in `{}`", s));
+            }
+        }
+
+        match (birth, survival) {
+            (Some(birth), Some(survival)) => Ok(Self { birth, survival }),
+            _ => Err(format!("rule `{}` must have both a B and an S part", s)),
+        }
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "B")?;
+        for (count, _) in self.birth.iter().enumerate().filter(|(_, &x)| x) {
+            write!(f, "{}", count)?;
+        }
+        write!(f, "/S")?;
+        for (count, _) in self.survival.iter().enumerate().filter(|(_, &x)| x) {
+            write!(f, "{}", count)?;
+        }
+        Ok(())
+    }
+}
+
+/// How the edges of the grid behave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Boundary {
+    /// The grid wraps around in both directions.
+    Toroidal,
+    /// Cells outside of the grid are always dead.
+    Bounded,
+}
+
+/// Cells are stored row-major, i.e. `cells[(y, x)]`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Grid2D {
+    cells: Array2<State>,
+    boundary: Boundary,
+}
+
+impl Grid2D {
+    pub fn new(width: usize, height: usize, boundary: Boundary) -> Self {
+        Self {
+            cells: Array2::from_elem((height, width), State::Dead),
+            boundary,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.cells.ncols()
+    }
+
+    pub fn height(&self) -> usize {
+        self.cells.nrows()
+    }
+
+    pub fn cells(&self) -> &Array2<State> {
+        &self.cells
+    }
+
+    /// Maps `(x, y)` onto the grid; `None` if it falls outside a bounded grid.
+    fn index(&self, x: Grid2DIdx, y: Grid2DIdx) -> Option<(usize, usize)> {
+        let (width, height) = (self.width() as Grid2DIdx, self.height() as Grid2DIdx);
+        match self.boundary {
+            Boundary::Toroidal => {
+                Some((y.rem_euclid(height) as usize, x.rem_euclid(width) as usize))
+            }
+            Boundary::Bounded if 0 <= x && x < width && 0 <= y && y < height => {
+                Some((y as usize, x as usize))
+            }
+            Boundary::Bounded => None,
+        }
+    }
+
+    pub fn get(&self, x: Grid2DIdx, y: Grid2DIdx) -> State {
+        self.index(x, y)
+            .map(|idx| self.cells[idx])
+            .unwrap_or(State::Dead)
+    }
+
+    fn live_neighbours(&self, x: Grid2DIdx, y: Grid2DIdx) -> usize {
+        let mut count = 0;
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                if (dx, dy) != (0, 0) && self.get(x + dx, y + dy) == State::Alive {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+}
+
+/// Only the live cells are stored, so the cost of an update scales with the population rather
+/// than with the area of the grid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SparseGrid {
+    live_cells: HashSet<(Grid2DIdx, Grid2DIdx)>,
+    /// `None` on an unbounded plane.
+    bounds: Option<(usize, usize, Boundary)>,
+}
+
+impl SparseGrid {
+    fn new(bounds: Option<(usize, usize, Boundary)>) -> Self {
+        Self {
+            live_cells: HashSet::new(),
+            bounds,
+        }
+    }
+
+    /// Same convention as [`Grid2D::index`], but in grid coordinates.
+    fn index(&self, x: Grid2DIdx, y: Grid2DIdx) -> Option<(Grid2DIdx, Grid2DIdx)> {
+        match self.bounds {
+            None => Some((x, y)),
+            Some((width, height, Boundary::Toroidal)) => Some((
+                x.rem_euclid(width as Grid2DIdx),
+                y.rem_euclid(height as Grid2DIdx),
+            )),
+            Some((width, height, Boundary::Bounded))
+                if 0 <= x && x < width as Grid2DIdx && 0 <= y && y < height as Grid2DIdx =>
+            {
+                Some((x, y))
+            }
+            Some((_, _, Boundary::Bounded)) => None,
+        }
+    }
+
+    fn get(&self, x: Grid2DIdx, y: Grid2DIdx) -> State {
+        match self.index(x, y) {
+            Some(cell) if self.live_cells.contains(&cell) => State::Alive,
+            _ => State::Dead,
+        }
+    }
+
+    fn set(&mut self, x: Grid2DIdx, y: Grid2DIdx, state: State) {
+        if let Some(cell) = self.index(x, y) {
+            match state {
+                State::Alive => self.live_cells.insert(cell),
+                State::Dead => self.live_cells.remove(&cell),
+            };
+        }
+    }
+
+    /// Only cells next to a live cell can change, unless the rule has `B0`.
+    fn next_generation(&self, rule: &Rule) -> Self {
+        let mut neighbour_counts: HashMap<(Grid2DIdx, Grid2DIdx), usize> = HashMap::new();
+        for &(x, y) in &self.live_cells {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    if (dx, dy) == (0, 0) {
+                        continue;
+                    }
+                    if let Some(cell) = self.index(x + dx, y + dy) {
+                        *neighbour_counts.entry(cell).or_insert(0) += 1;
+                    }
+                }
+            }
+        }
+
+        let mut live_cells: HashSet<_> = neighbour_counts
+            .into_iter()
+            .filter(|(cell, live_neighbours)| {
+                let state = if self.live_cells.contains(cell) {
+                    State::Alive
+                } else {
+                    State::Dead
+                };
+                rule.next_state(state, *live_neighbours) == State::Alive
+            })
+            .map(|(cell, _)| cell)
+            .collect();
+        if rule.survival[0] {
+            live_cells.extend(self.live_cells.iter().filter(|&&(x, y)| {
+                (-1..=1)
+                    .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                    .filter(|&offset| offset != (0, 0))
+                    .all(|(dx, dy)| self.get(x + dx, y + dy) == State::Dead)
+            }));
+        }
+
+        Self {
+            live_cells,
+            bounds: self.bounds,
+        }
+    }
+}
+
+/// The data structure a [`Universe`] keeps its cells in. All backends evolve identically, and
+/// differ only in which patterns they are fast for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Backend {
+    /// An [`Array2`] of every cell, see [`Grid2D`]. Only for finite grids.
+    Dense,
+    /// A hash set of the live cells, see [`SparseGrid`].
+    Sparse,
+    /// A memoised quadtree, see [`hashlife`]. Only for the unbounded plane, and runs of many
+    /// generations at once.
+    Hashlife,
+}
+
+#[derive(Debug, Clone)]
+enum Cells {
+    Dense(Grid2D),
+    Sparse(SparseGrid),
+    Hashlife(hashlife::Quadtree),
+}
+
+#[derive(Debug, Clone)]
+pub struct Universe {
+    cells: Cells,
+    rule: Rule,
+    generation: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum State {
     Alive,
+    #[default]
     Dead,
 }
 
-enum Transitions {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transitions {
     Underpopulation,
     Overpopulation,
     Reproduction,
 }
 
 impl Universe {
+    pub fn new(width: usize, height: usize, boundary: Boundary, rule: Rule) -> Self {
+        Self::with_backend(width, height, boundary, rule, Backend::Dense)
+            .expect("a dense grid supports every rule")
+    }
+
+    /// A finite grid kept in `backend`, which must be either [`Backend::Dense`] or
+    /// [`Backend::Sparse`]. The sparse grid does not support rules with `B0`.
+    pub fn with_backend(
+        width: usize,
+        height: usize,
+        boundary: Boundary,
+        rule: Rule,
+        backend: Backend,
+    ) -> Result<Self, String> {
+        let cells = match backend {
+            Backend::Dense => Cells::Dense(Grid2D::new(width, height, boundary)),
+            Backend::Sparse if rule.birth[0] => {
+                return Err("rules with B0 need a dense grid".to_string())
+            }
+            Backend::Sparse => Cells::Sparse(SparseGrid::new(Some((width, height, boundary)))),
+            Backend::Hashlife => {
+                return Err("the hashlife backend only supports the unbounded plane".to_string())
+            }
+        };
+        Ok(Self {
+            cells,
+            rule,
+            generation: 0,
+        })
+    }
+
+    /// An infinite plane kept in `backend`, which must be either [`Backend::Sparse`] or
+    /// [`Backend::Hashlife`]. Rules with `B0` are not supported, as they would fill the plane.
+    pub fn unbounded(rule: Rule, backend: Backend) -> Result<Self, String> {
+        if rule.birth[0] {
+            return Err("rules with B0 need a finite grid".to_string());
+        }
+        let cells = match backend {
+            Backend::Dense => {
+                return Err("the dense backend only supports finite grids".to_string())
+            }
+            Backend::Sparse => Cells::Sparse(SparseGrid::new(None)),
+            Backend::Hashlife => Cells::Hashlife(hashlife::Quadtree::new(rule)),
+        };
+        Ok(Self {
+            cells,
+            rule,
+            generation: 0,
+        })
+    }
+
+    pub fn backend(&self) -> Backend {
+        match self.cells {
+            Cells::Dense(_) => Backend::Dense,
+            Cells::Sparse(_) => Backend::Sparse,
+            Cells::Hashlife(_) => Backend::Hashlife,
+        }
+    }
+
+    /// Width, height and boundary of the grid; `None` on the unbounded plane.
+    pub fn bounds(&self) -> Option<(usize, usize, Boundary)> {
+        match &self.cells {
+            Cells::Dense(grid) => Some((grid.width(), grid.height(), grid.boundary)),
+            Cells::Sparse(grid) => grid.bounds,
+            Cells::Hashlife(_) => None,
+        }
+    }
+
+    /// The grid of a [`Backend::Dense`] universe.
+    pub fn grid(&self) -> Option<&Grid2D> {
+        match &self.cells {
+            Cells::Dense(grid) => Some(grid),
+            _ => None,
+        }
+    }
+
+    pub fn rule(&self) -> Rule {
+        self.rule
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn population(&self) -> usize {
+        match &self.cells {
+            Cells::Dense(grid) => grid.cells.iter().filter(|&&x| x == State::Alive).count(),
+            Cells::Sparse(grid) => grid.live_cells.len(),
+            Cells::Hashlife(quadtree) => quadtree.population() as usize,
+        }
+    }
+
+    /// Coordinates of the live cells, sorted row by row.
+    pub fn live_cells(&self) -> Vec<(Grid2DIdx, Grid2DIdx)> {
+        let mut live_cells: Vec<_> = match &self.cells {
+            Cells::Dense(grid) => grid
+                .cells
+                .indexed_iter()
+                .filter(|(_, &cell)| cell == State::Alive)
+                .map(|((y, x), _)| (x as Grid2DIdx, y as Grid2DIdx))
+                .collect(),
+            Cells::Sparse(grid) => grid.live_cells.iter().cloned().collect(),
+            Cells::Hashlife(quadtree) => quadtree.live_cells(),
+        };
+        live_cells.sort_by_key(|&(x, y)| (y, x));
+        live_cells
+    }
+
     /// Source: [Lecture 3](http://prac.im.pwr.wroc.pl/~szwabin/assets/abm/lec/3.pdf)
-    fn create_block(&self, x_start: Grid2DIdx, y_start: Grid2DIdx) {
+    pub fn create_block(&mut self, x_start: Grid2DIdx, y_start: Grid2DIdx) {
         for x in x_start..x_start + 2 {
             for y in y_start..y_start + 2 {
                 self.set_state(x, y, State::Alive)
@@ -28,67 +451,1667 @@ impl Universe {
         }
     }
 
-    fn set_state(x: Grid2DIdx, y: Grid2DIdx, state: State) {
-        todo!()
+    /// On a bounded grid, cells outside of the grid are silently dropped.
+    pub fn set_state(&mut self, x: Grid2DIdx, y: Grid2DIdx, state: State) {
+        match &mut self.cells {
+            Cells::Dense(grid) => {
+                if let Some(idx) = grid.index(x, y) {
+                    grid.cells[idx] = state;
+                }
+            }
+            Cells::Sparse(grid) => grid.set(x, y, state),
+            Cells::Hashlife(quadtree) => quadtree.set(x, y, state),
+        }
+    }
+
+    pub fn get_state(&self, x: Grid2DIdx, y: Grid2DIdx) -> State {
+        match &self.cells {
+            Cells::Dense(grid) => grid.get(x, y),
+            Cells::Sparse(grid) => grid.get(x, y),
+            Cells::Hashlife(quadtree) => quadtree.get(x, y),
+        }
+    }
+
+    /// Sets every cell in `offsets`, relative to `(x_start, y_start)`, to [`State::Alive`].
+    pub fn set_alive_cells(
+        &mut self,
+        x_start: Grid2DIdx,
+        y_start: Grid2DIdx,
+        offsets: &[(Grid2DIdx, Grid2DIdx)],
+    ) {
+        for (dx, dy) in offsets {
+            self.set_state(x_start + dx, y_start + dy, State::Alive);
+        }
+    }
+
+    pub fn update(&mut self) {
+        match &mut self.cells {
+            Cells::Dense(grid) => {
+                let mut new_cells = grid.cells.clone();
+                for ((y, x), cell) in new_cells.indexed_iter_mut() {
+                    let live_neighbours = grid.live_neighbours(x as Grid2DIdx, y as Grid2DIdx);
+                    *cell = self.rule.next_state(*cell, live_neighbours);
+                }
+                grid.cells = new_cells;
+            }
+            Cells::Sparse(grid) => *grid = grid.next_generation(&self.rule),
+            Cells::Hashlife(quadtree) => quadtree.advance(1),
+        }
+        self.generation += 1;
+    }
+
+    /// With [`Backend::Hashlife`], the generations are taken in power-of-two leaps.
+    pub fn run(&mut self, generations: u64) {
+        if let Cells::Hashlife(quadtree) = &mut self.cells {
+            quadtree.advance(generations);
+            self.generation += generations;
+            return;
+        }
+        for _ in 0..generations {
+            self.update();
+        }
+    }
+
+    /// Places `pattern` with the top-left corner of its bounding box at `(x_start, y_start)`.
+    pub fn place_pattern(&mut self, pattern: &Pattern, x_start: Grid2DIdx, y_start: Grid2DIdx) {
+        self.set_alive_cells(x_start, y_start, &pattern.cells);
+    }
+
+    /// The live cells, cropped to their bounding box.
+    pub fn to_pattern(&self) -> Pattern {
+        let mut pattern = Pattern::from_cells(&self.live_cells());
+        pattern.rule = Some(self.rule);
+        pattern
+    }
+}
+
+/// Universes are equal if they have the same rule, generation, bounds and live cells,
+/// regardless of their backend.
+impl PartialEq for Universe {
+    fn eq(&self, other: &Self) -> bool {
+        self.rule == other.rule
+            && self.generation == other.generation
+            && self.bounds() == other.bounds()
+            && self.live_cells() == other.live_cells()
+    }
+}
+
+impl Eq for Universe {}
+
+impl Display for State {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(
+            f,
+            "{}",
+            match self {
+                State::Alive => 'O',
+                State::Dead => '.',
+            }
+        )
+    }
+}
+
+/// Shows the whole grid, or the bounding box of the live cells on the unbounded plane.
+impl Display for Universe {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        let (x_range, y_range) = match self.bounds() {
+            Some((width, height, _)) => (0..width as Grid2DIdx, 0..height as Grid2DIdx),
+            None => {
+                let live_cells = self.live_cells();
+                let x_min = live_cells.iter().map(|&(x, _)| x).min().unwrap_or(0);
+                let x_max = live_cells.iter().map(|&(x, _)| x).max().unwrap_or(-1);
+                let y_min = live_cells.first().map(|&(_, y)| y).unwrap_or(0);
+                let y_max = live_cells.last().map(|&(_, y)| y).unwrap_or(-1);
+                (x_min..x_max + 1, y_min..y_max + 1)
+            }
+        };
+        for y in y_range {
+            for x in x_range.clone() {
+                write!(f, "{}", self.get_state(x, y))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
     }
 }
 
-mod patterns {
+/// [Hashlife](https://en.wikipedia.org/wiki/Hashlife), after Gosper (1984).
+///
+/// The plane is a quadtree in which identical squares are stored once, and the future of every
+/// square is memoised. Repetitive patterns, such as guns and their streams of gliders, can
+/// therefore be advanced by millions of generations in a handful of steps.
+pub mod hashlife {
+    use crate::game_of_life::{Grid2DIdx, Rule, State};
+    use std::collections::HashMap;
+    use std::fmt::{Debug, Error, Formatter};
 
-    mod still_life {
+    type NodeId = usize;
 
-        fn create_beehive() {
-            todo!()
+    const DEAD: NodeId = 0;
+    const ALIVE: NodeId = 1;
+    /// The memoisation tables are rebuilt from the current root once they hold this many nodes.
+    const MAX_NODES: usize = 1 << 22;
+
+    /// A square of side `2^level`. Level 0 are the single cells [`DEAD`] and [`ALIVE`].
+    #[derive(Debug, Clone, Copy)]
+    struct Node {
+        level: u32,
+        population: u64,
+        /// North-west, north-east, south-west and south-east quadrants.
+        children: [NodeId; 4],
+    }
+
+    #[derive(Clone)]
+    pub struct Quadtree {
+        rule: Rule,
+        nodes: Vec<Node>,
+        canonical: HashMap<[NodeId; 4], NodeId>,
+        /// Centre of a node, advanced by `2^j` generations, keyed on `(node, j)`.
+        successors: HashMap<(NodeId, u32), NodeId>,
+        /// The empty node of each level.
+        empty: Vec<NodeId>,
+        root: NodeId,
+        /// Coordinates of the top-left cell of `root`.
+        origin: (i64, i64),
+    }
+
+    impl Quadtree {
+        pub fn new(rule: Rule) -> Self {
+            let leaf = |population| Node {
+                level: 0,
+                population,
+                children: [DEAD; 4],
+            };
+            let mut quadtree = Self {
+                rule,
+                nodes: vec![leaf(0), leaf(1)],
+                canonical: HashMap::new(),
+                successors: HashMap::new(),
+                empty: vec![DEAD],
+                root: DEAD,
+                origin: (-4, -4),
+            };
+            quadtree.root = quadtree.empty(3);
+            quadtree
         }
 
-        fn create_loaf() {
-            todo!()
+        pub fn population(&self) -> u64 {
+            self.nodes[self.root].population
         }
 
-        fn create_boat() {
-            todo!()
+        fn level(&self, node: NodeId) -> u32 {
+            self.nodes[node].level
         }
 
-        fn create_tube() {
-            todo!()
+        fn children(&self, node: NodeId) -> [NodeId; 4] {
+            self.nodes[node].children
+        }
+
+        /// The unique node with the given quadrants.
+        fn node(&mut self, children: [NodeId; 4]) -> NodeId {
+            if let Some(&node) = self.canonical.get(&children) {
+                return node;
+            }
+            let node = Node {
+                level: self.level(children[0]) + 1,
+                population: children.iter().map(|&x| self.nodes[x].population).sum(),
+                children,
+            };
+            self.nodes.push(node);
+            self.canonical.insert(children, self.nodes.len() - 1);
+            self.nodes.len() - 1
+        }
+
+        fn empty(&mut self, level: u32) -> NodeId {
+            while self.empty.len() <= level as usize {
+                let empty = *self.empty.last().unwrap();
+                let next_empty = self.node([empty; 4]);
+                self.empty.push(next_empty);
+            }
+            self.empty[level as usize]
+        }
+
+        fn side(&self) -> i64 {
+            1 << self.level(self.root)
+        }
+
+        fn contains(&self, x: i64, y: i64) -> bool {
+            let (x_origin, y_origin) = self.origin;
+            let side = self.side();
+            x_origin <= x && x < x_origin + side && y_origin <= y && y < y_origin + side
+        }
+
+        /// Doubles the side of the root, keeping the current root in the centre.
+        fn expand(&mut self) {
+            let level = self.level(self.root);
+            let empty = self.empty(level - 1);
+            let [nw, ne, sw, se] = self.children(self.root);
+            let children = [
+                self.node([empty, empty, empty, nw]),
+                self.node([empty, empty, ne, empty]),
+                self.node([empty, sw, empty, empty]),
+                self.node([se, empty, empty, empty]),
+            ];
+            self.root = self.node(children);
+            let half = 1 << (level - 1);
+            self.origin = (self.origin.0 - half, self.origin.1 - half);
+        }
+
+        pub fn get(&self, x: Grid2DIdx, y: Grid2DIdx) -> State {
+            let (mut x, mut y) = (x as i64, y as i64);
+            if !self.contains(x, y) {
+                return State::Dead;
+            }
+            x -= self.origin.0;
+            y -= self.origin.1;
+            let mut node = self.root;
+            while self.level(node) > 0 {
+                let half = 1 << (self.level(node) - 1);
+                let quadrant = 2 * (y >= half) as usize + (x >= half) as usize;
+                node = self.children(node)[quadrant];
+                x %= half;
+                y %= half;
+            }
+            if node == ALIVE {
+                State::Alive
+            } else {
+                State::Dead
+            }
+        }
+
+        pub fn set(&mut self, x: Grid2DIdx, y: Grid2DIdx, state: State) {
+            let (x, y) = (x as i64, y as i64);
+            while !self.contains(x, y) {
+                self.expand();
+            }
+            let leaf = match state {
+                State::Alive => ALIVE,
+                State::Dead => DEAD,
+            };
+            self.root = self.set_in(self.root, x - self.origin.0, y - self.origin.1, leaf);
+        }
+
+        fn set_in(&mut self, node: NodeId, x: i64, y: i64, leaf: NodeId) -> NodeId {
+            if self.level(node) == 0 {
+                return leaf;
+            }
+            let half = 1 << (self.level(node) - 1);
+            let quadrant = 2 * (y >= half) as usize + (x >= half) as usize;
+            let mut children = self.children(node);
+            children[quadrant] = self.set_in(children[quadrant], x % half, y % half, leaf);
+            self.node(children)
+        }
+
+        pub fn live_cells(&self) -> Vec<(Grid2DIdx, Grid2DIdx)> {
+            let mut live_cells = Vec::with_capacity(self.population() as usize);
+            self.collect_live_cells(self.root, self.origin, &mut live_cells);
+            live_cells
+        }
+
+        fn collect_live_cells(
+            &self,
+            node: NodeId,
+            (x, y): (i64, i64),
+            live_cells: &mut Vec<(Grid2DIdx, Grid2DIdx)>,
+        ) {
+            if self.nodes[node].population == 0 {
+                return;
+            }
+            if node == ALIVE {
+                live_cells.push((x as Grid2DIdx, y as Grid2DIdx));
+                return;
+            }
+            let half = 1 << (self.level(node) - 1);
+            for (quadrant, &child) in self.children(node).iter().enumerate() {
+                let offset = ((quadrant % 2) as i64 * half, (quadrant / 2) as i64 * half);
+                self.collect_live_cells(child, (x + offset.0, y + offset.1), live_cells);
+            }
+        }
+
+        /// The middle half of a node of level 2 or higher.
+        fn centre(&mut self, node: NodeId) -> NodeId {
+            let [nw, ne, sw, se] = self.children(node);
+            self.node([
+                self.children(nw)[3],
+                self.children(ne)[2],
+                self.children(sw)[1],
+                self.children(se)[0],
+            ])
+        }
+
+        /// Advances the 4 x 4 cells of a level 2 node by one generation, and returns the centre
+        /// 2 x 2 cells.
+        fn base_successor(&mut self, node: NodeId) -> NodeId {
+            let mut cells = [[false; 4]; 4];
+            for (quadrant, &child) in self.children(node).iter().enumerate() {
+                for (sub_quadrant, &leaf) in self.children(child).iter().enumerate() {
+                    let x = 2 * (quadrant % 2) + sub_quadrant % 2;
+                    let y = 2 * (quadrant / 2) + sub_quadrant / 2;
+                    cells[y][x] = leaf == ALIVE;
+                }
+            }
+
+            let mut next = [DEAD; 4];
+            for (quadrant, leaf) in next.iter_mut().enumerate() {
+                let (x, y) = (1 + quadrant % 2, 1 + quadrant / 2);
+                let live_neighbours = (y - 1..=y + 1)
+                    .flat_map(|y_neighbour| {
+                        (x - 1..=x + 1).map(move |x_neighbour| (x_neighbour, y_neighbour))
+                    })
+                    .filter(|&neighbour| neighbour != (x, y) && cells[neighbour.1][neighbour.0])
+                    .count();
+                let state = if cells[y][x] {
+                    State::Alive
+                } else {
+                    State::Dead
+                };
+                if self.rule.next_state(state, live_neighbours) == State::Alive {
+                    *leaf = ALIVE;
+                }
+            }
+            self.node(next)
+        }
+
+        /// The centre of `node` advanced by `2^j` generations, where `j <= level - 2`.
+        fn successor(&mut self, node: NodeId, j: u32) -> NodeId {
+            let level = self.level(node);
+            debug_assert!(level >= 2 && j <= level - 2);
+            if self.nodes[node].population == 0 {
+                return self.empty(level - 1);
+            }
+            if level == 2 {
+                return self.base_successor(node);
+            }
+            if let Some(&successor) = self.successors.get(&(node, j)) {
+                return successor;
+            }
+
+            // Nine overlapping squares of half the side, arranged in a 3 x 3 grid.
+            let [nw, ne, sw, se] = self.children(node);
+            let [_, nw_ne, nw_sw, nw_se] = self.children(nw);
+            let [ne_nw, _, ne_sw, ne_se] = self.children(ne);
+            let [sw_nw, sw_ne, _, sw_se] = self.children(sw);
+            let [se_nw, se_ne, se_sw, _] = self.children(se);
+            let squares = [
+                nw,
+                self.node([nw_ne, ne_nw, nw_se, ne_sw]),
+                ne,
+                self.node([nw_sw, nw_se, sw_nw, sw_ne]),
+                self.node([nw_se, ne_sw, sw_ne, se_nw]),
+                self.node([ne_sw, ne_se, se_nw, se_ne]),
+                sw,
+                self.node([sw_ne, se_nw, sw_se, se_sw]),
+                se,
+            ];
+
+            // Leaping the full `2^(level - 2)` generations takes two half leaps; shorter leaps
+            // only advance in the second half.
+            let mut centres = [DEAD; 9];
+            for (centre, &square) in centres.iter_mut().zip(squares.iter()) {
+                *centre = if j == level - 2 {
+                    self.successor(square, level - 3)
+                } else {
+                    self.centre(square)
+                };
+            }
+            let second_leap = if j == level - 2 { level - 3 } else { j };
+            let mut quadrants = [DEAD; 4];
+            for (quadrant, result) in quadrants.iter_mut().enumerate() {
+                let (column, row) = (quadrant % 2, quadrant / 2);
+                let corner = 3 * row + column;
+                let square = self.node([
+                    centres[corner],
+                    centres[corner + 1],
+                    centres[corner + 3],
+                    centres[corner + 4],
+                ]);
+                *result = self.successor(square, second_leap);
+            }
+            let successor = self.node(quadrants);
+
+            self.successors.insert((node, j), successor);
+            successor
+        }
+
+        /// Advances the plane by `2^j` generations.
+        fn leap(&mut self, j: u32) {
+            // The live cells must be in the central quarter of the root, which itself must be
+            // large enough, so that nothing can escape the centre that is returned.
+            loop {
+                if self.level(self.root) >= j + 3 {
+                    let centre = self.centre(self.root);
+                    let centre = self.centre(centre);
+                    if self.nodes[centre].population == self.population() {
+                        break;
+                    }
+                }
+                self.expand();
+            }
+
+            let level = self.level(self.root);
+            self.root = self.successor(self.root, j);
+            let quarter = 1 << (level - 2);
+            self.origin = (self.origin.0 + quarter, self.origin.1 + quarter);
+
+            if self.nodes.len() > MAX_NODES {
+                self.compact();
+            }
         }
+
+        pub fn advance(&mut self, generations: u64) {
+            for j in 0..64 {
+                if generations & (1 << j) != 0 {
+                    self.leap(j);
+                }
+            }
+        }
+
+        /// Drops every node that is not part of the current root, together with the memoised
+        /// successors.
+        fn compact(&mut self) {
+            let mut compacted = Self::new(self.rule);
+            let mut copies = HashMap::new();
+            compacted.root = compacted.copy_from(self, self.root, &mut copies);
+            compacted.origin = self.origin;
+            *self = compacted;
+        }
+
+        fn copy_from(
+            &mut self,
+            other: &Self,
+            node: NodeId,
+            copies: &mut HashMap<NodeId, NodeId>,
+        ) -> NodeId {
+            if node == DEAD || node == ALIVE {
+                return node;
+            }
+            if let Some(&copy) = copies.get(&node) {
+                return copy;
+            }
+            let mut children = other.children(node);
+            for child in children.iter_mut() {
+                *child = self.copy_from(other, *child, copies);
+            }
+            let copy = self.node(children);
+            copies.insert(node, copy);
+            copy
+        }
+    }
+
+    impl Debug for Quadtree {
+        fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+            f.debug_struct("Quadtree")
+                .field("rule", &self.rule)
+                .field("level", &self.level(self.root))
+                .field("origin", &self.origin)
+                .field("population", &self.population())
+                .field("nodes", &self.nodes.len())
+                .finish()
+        }
+    }
+}
+
+/// A finite configuration of live cells, as read from or written to a pattern file.
+///
+/// Supports the [RLE](https://www.conwaylife.com/wiki/Run_Length_Encoded) format used by Golly
+/// and the [plaintext](https://www.conwaylife.com/wiki/Plaintext) `.cells` format.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Pattern {
+    pub name: Option<String>,
+    pub comments: Vec<String>,
+    pub rule: Option<Rule>,
+    width: usize,
+    height: usize,
+    /// Live cells relative to the top-left corner, sorted row by row.
+    cells: Vec<(Grid2DIdx, Grid2DIdx)>,
+}
+
+impl Pattern {
+    /// Crops `cells` to their bounding box.
+    pub fn from_cells(cells: &[(Grid2DIdx, Grid2DIdx)]) -> Self {
+        if cells.is_empty() {
+            return Self::default();
+        }
+        let x_min = cells.iter().map(|&(x, _)| x).min().unwrap();
+        let x_max = cells.iter().map(|&(x, _)| x).max().unwrap();
+        let y_min = cells.iter().map(|&(_, y)| y).min().unwrap();
+        let y_max = cells.iter().map(|&(_, y)| y).max().unwrap();
+        let mut cells: Vec<_> = cells.iter().map(|&(x, y)| (x - x_min, y - y_min)).collect();
+        cells.sort_by_key(|&(x, y)| (y, x));
+        cells.dedup();
+
+        Self {
+            width: (x_max - x_min + 1) as usize,
+            height: (y_max - y_min + 1) as usize,
+            cells,
+            ..Default::default()
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn cells(&self) -> &[(Grid2DIdx, Grid2DIdx)] {
+        &self.cells
     }
 
-    mod oscillators {
+    pub fn population(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// A universe just large enough to hold the pattern with `margin` dead cells on every side.
+    /// Uses the pattern's rule if it has one, and otherwise [`Rule::conway`].
+    pub fn to_universe(&self, margin: usize, boundary: Boundary) -> Universe {
+        let mut universe = Universe::new(
+            self.width + 2 * margin,
+            self.height + 2 * margin,
+            boundary,
+            self.rule.unwrap_or_default(),
+        );
+        universe.place_pattern(self, margin as Grid2DIdx, margin as Grid2DIdx);
+        universe
+    }
+
+    pub fn from_rle(s: &str) -> Result<Self, String> {
+        let mut name = None;
+        let mut comments = Vec::new();
+        let mut rule = None;
+        let mut width = None;
+        let mut height = None;
+        let mut cells = Vec::new();
+        let (mut x, mut y) = (0, 0);
+        let mut run_count = String::new();
+
+        'lines: for line in s.lines().map(str::trim) {
+            if let Some(comment) = line.strip_prefix('#') {
+                let mut chars = comment.chars();
+                match chars.next() {
+                    Some('N') => name = Some(chars.as_str().trim().to_string()),
+                    Some('C') | Some('c') => comments.push(chars.as_str().trim().to_string()),
+                    // Other lines, e.g. `#O` (author) or `#R` (offset), are not retained.
+                    _ => {}
+                }
+                continue;
+            }
+            if width.is_none() {
+                if line.is_empty() {
+                    continue;
+                }
+                for assignment in line.split(',') {
+                    let mut key_value = assignment.splitn(2, '=').map(str::trim);
+                    match (key_value.next(), key_value.next()) {
+                        (Some("x"), Some(value)) => {
+                            width = Some(value.parse::<usize>().map_err(|e| e.to_string())?)
+                        }
+                        (Some("y"), Some(value)) => {
+                            height = Some(value.parse::<usize>().map_err(|e| e.to_string())?)
+                        }
+                        (Some("rule"), Some(value)) => rule = Some(value.parse()?),
+                        _ => return Err(format!("invalid RLE header `{}`", line)),
+                    }
+                }
+                if width.is_none() || height.is_none() {
+                    return Err(format!("RLE header `{}` lacks `x` or `y`", line));
+                }
+                continue;
+            }
+
+            for tag in line.chars() {
+                if tag.is_ascii_digit() {
+                    run_count.push(tag);
+                    continue;
+                }
+                if tag.is_whitespace() {
+                    continue;
+                }
+                let run = if run_count.is_empty() {
+                    1
+                } else {
+                    run_count.parse::<Grid2DIdx>().map_err(|e| e.to_string())?
+                };
+                run_count.clear();
+                match tag {
+                    'b' | '.' => x += run,
+                    '$' => {
+                        x = 0;
+                        y += run;
+                    }
+                    '!' => break 'lines,
+                    // Multi-state tags are read as alive.
+                    'o' | 'A'..='X' => {
+                        cells.extend((x..x + run).map(|x| (x, y)));
+                        x += run;
+                    }
+                    _ => return Err(format!("invalid RLE tag `{}`", tag)),
+                }
+            }
+        }
+
+        let (width, height) = match (width, height) {
+            (Some(width), Some(height)) => (width, height),
+            _ => return Err("RLE pattern has no header".to_string()),
+        };
+        if cells
+            .iter()
+            .any(|&(x, y)| x as usize >= width || y as usize >= height)
+        {
+            return Err(format!(
+                "RLE pattern does not fit in the declared {} x {} box",
+                width, height
+            ));
+        }
+
+        Ok(Self {
+            name,
+            comments,
+            rule,
+            width,
+            height,
+            cells,
+        })
+    }
+
+    pub fn to_rle(&self) -> String {
+        const MAX_LINE_LENGTH: usize = 70;
+
+        let mut header = String::new();
+        if let Some(name) = &self.name {
+            header.push_str(&format!("#N {}\n", name));
+        }
+        for comment in &self.comments {
+            header.push_str(&format!("#C {}\n", comment));
+        }
+        header.push_str(&format!(
+            "x = {}, y = {}, rule = {}\n",
+            self.width,
+            self.height,
+            self.rule.unwrap_or_default()
+        ));
+
+        // Runs of (count, tag); trailing dead cells in a row are never written.
+        let mut runs: Vec<(usize, char)> = Vec::new();
+        let mut push_run = |count: usize, tag: char| match runs.last_mut() {
+            Some((previous_count, previous_tag)) if *previous_tag == tag => {
+                *previous_count += count
+            }
+            _ if count > 0 => runs.push((count, tag)),
+            _ => {}
+        };
+        let (mut x, mut y) = (0, 0);
+        for &(cell_x, cell_y) in &self.cells {
+            if cell_y > y {
+                push_run((cell_y - y) as usize, '$');
+                x = 0;
+                y = cell_y;
+            }
+            push_run((cell_x - x) as usize, 'b');
+            push_run(1, 'o');
+            x = cell_x + 1;
+        }
+        push_run(1, '!');
+
+        let mut body = String::new();
+        let mut line_length = 0;
+        for (count, tag) in runs {
+            let item = if count == 1 {
+                tag.to_string()
+            } else {
+                format!("{}{}", count, tag)
+            };
+            if line_length + item.len() > MAX_LINE_LENGTH {
+                body.push('\n');
+                line_length = 0;
+            }
+            line_length += item.len();
+            body.push_str(&item);
+        }
+        body.push('\n');
+
+        header + &body
+    }
+
+    pub fn from_plaintext(s: &str) -> Result<Self, String> {
+        let mut name = None;
+        let mut comments = Vec::new();
+        let mut cells = Vec::new();
+        let mut width = 0;
+        let mut height = 0;
+
+        for line in s.lines().map(str::trim_end) {
+            if let Some(comment) = line.strip_prefix('!') {
+                match comment.strip_prefix("Name:") {
+                    Some(pattern_name) => name = Some(pattern_name.trim().to_string()),
+                    None => comments.push(comment.trim().to_string()),
+                }
+                continue;
+            }
+            for (x, cell) in line.chars().enumerate() {
+                match cell {
+                    '.' => {}
+                    'O' | '*' => cells.push((x as Grid2DIdx, height as Grid2DIdx)),
+                    _ => return Err(format!("invalid plaintext cell `{}`", cell)),
+                }
+            }
+            width = width.max(line.len());
+            height += 1;
+        }
+
+        Ok(Self {
+            name,
+            comments,
+            rule: None,
+            width,
+            height,
+            cells,
+        })
+    }
+
+    pub fn to_plaintext(&self) -> String {
+        let mut plaintext = String::new();
+        if let Some(name) = &self.name {
+            plaintext.push_str(&format!("!Name: {}\n", name));
+        }
+        for comment in &self.comments {
+            plaintext.push_str(&format!("!{}\n", comment));
+        }
+        let mut rows = vec![vec!['.'; self.width]; self.height];
+        for &(x, y) in &self.cells {
+            rows[y as usize][x as usize] = 'O';
+        }
+        for row in rows {
+            plaintext.extend(row);
+            plaintext.push('\n');
+        }
+        plaintext
+    }
+
+    /// Reads a `.rle` or `.cells` file, chosen by its extension.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("rle") => Self::from_rle(&contents),
+            Some("cells") => Self::from_plaintext(&contents),
+            _ => Err(format!("unknown pattern format of `{}`", path.display())),
+        }
+    }
+
+    /// Writes a `.rle` or `.cells` file, chosen by its extension.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let contents = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("rle") => self.to_rle(),
+            Some("cells") => self.to_plaintext(),
+            _ => return Err(format!("unknown pattern format of `{}`", path.display())),
+        };
+        fs::write(path, contents).map_err(|e| e.to_string())
+    }
+}
+
+/// Each pattern is placed with its top-left corner of its bounding box at
+/// `(x_start, y_start)`.
+pub mod patterns {
+
+    pub mod still_life {
+        use crate::game_of_life::{Grid2DIdx, Universe};
+
+        pub fn create_beehive(universe: &mut Universe, x_start: Grid2DIdx, y_start: Grid2DIdx) {
+            universe.set_alive_cells(
+                x_start,
+                y_start,
+                &[(1, 0), (2, 0), (0, 1), (3, 1), (1, 2), (2, 2)],
+            );
+        }
+
+        pub fn create_loaf(universe: &mut Universe, x_start: Grid2DIdx, y_start: Grid2DIdx) {
+            universe.set_alive_cells(
+                x_start,
+                y_start,
+                &[(1, 0), (2, 0), (0, 1), (3, 1), (1, 2), (3, 2), (2, 3)],
+            );
+        }
+
+        pub fn create_boat(universe: &mut Universe, x_start: Grid2DIdx, y_start: Grid2DIdx) {
+            universe.set_alive_cells(x_start, y_start, &[(0, 0), (1, 0), (0, 1), (2, 1), (1, 2)]);
+        }
+
+        pub fn create_tube(universe: &mut Universe, x_start: Grid2DIdx, y_start: Grid2DIdx) {
+            universe.set_alive_cells(x_start, y_start, &[(1, 0), (0, 1), (2, 1), (1, 2)]);
+        }
+    }
+
+    pub mod oscillators {
+        use crate::game_of_life::{Grid2DIdx, Universe};
+
         /// Period 2
-        fn create_blinker() {
-            todo!()
+        pub fn create_blinker(universe: &mut Universe, x_start: Grid2DIdx, y_start: Grid2DIdx) {
+            universe.set_alive_cells(x_start, y_start, &[(0, 0), (1, 0), (2, 0)]);
         }
         /// Period 2
-        fn create_toad() {
-            todo!()
+        pub fn create_toad(universe: &mut Universe, x_start: Grid2DIdx, y_start: Grid2DIdx) {
+            universe.set_alive_cells(
+                x_start,
+                y_start,
+                &[(1, 0), (2, 0), (3, 0), (0, 1), (1, 1), (2, 1)],
+            );
         }
         /// Period 2
-        fn create_beacon() {
-            todo!()
+        pub fn create_beacon(universe: &mut Universe, x_start: Grid2DIdx, y_start: Grid2DIdx) {
+            universe.create_block(x_start, y_start);
+            universe.create_block(x_start + 2, y_start + 2);
         }
         /// Period 3
-        fn create_pulsar() {
-            todo!()
+        pub fn create_pulsar(universe: &mut Universe, x_start: Grid2DIdx, y_start: Grid2DIdx) {
+            // The pulsar is symmetric in both axes; build it from one arm per quadrant.
+            for &(bar_x, bar_y) in &[(2, 0), (2, 5), (2, 7), (2, 12)] {
+                for i in 0..3 {
+                    universe.set_alive_cells(
+                        x_start,
+                        y_start,
+                        &[(bar_x + i, bar_y), (bar_x + 6 + i, bar_y)],
+                    );
+                    universe.set_alive_cells(
+                        x_start,
+                        y_start,
+                        &[(bar_y, bar_x + i), (bar_y, bar_x + 6 + i)],
+                    );
+                }
+            }
         }
         /// Period 15
-        fn create_pentadecathlon() {
-            todo!()
+        pub fn create_pentadecathlon(
+            universe: &mut Universe,
+            x_start: Grid2DIdx,
+            y_start: Grid2DIdx,
+        ) {
+            universe.set_alive_cells(
+                x_start,
+                y_start,
+                &[
+                    (2, 0),
+                    (7, 0),
+                    (0, 1),
+                    (1, 1),
+                    (3, 1),
+                    (4, 1),
+                    (5, 1),
+                    (6, 1),
+                    (8, 1),
+                    (9, 1),
+                    (2, 2),
+                    (7, 2),
+                ],
+            );
         }
     }
 
-    mod glider {
-        fn create_glider() {
-            todo!()
+    pub mod glider {
+        use crate::game_of_life::{Grid2DIdx, Universe};
+
+        /// Travels one cell down and to the right every 4 generations.
+        pub fn create_glider(universe: &mut Universe, x_start: Grid2DIdx, y_start: Grid2DIdx) {
+            universe.set_alive_cells(x_start, y_start, &[(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]);
         }
-        fn create_spaceship() {
-            todo!()
+        /// Lightweight spaceship, travels two cells to the left every 4 generations.
+        pub fn create_spaceship(universe: &mut Universe, x_start: Grid2DIdx, y_start: Grid2DIdx) {
+            universe.set_alive_cells(
+                x_start,
+                y_start,
+                &[
+                    (1, 0),
+                    (4, 0),
+                    (0, 1),
+                    (0, 2),
+                    (4, 2),
+                    (0, 3),
+                    (1, 3),
+                    (2, 3),
+                    (3, 3),
+                ],
+            );
         }
     }
 
-    mod gun {
-        fn create_gun() {
-            todo!()
+    /// Patterns from the [LifeWiki](https://www.conwaylife.com/wiki/) collection, as embedded
+    /// RLE strings to be read with [`Pattern::from_rle`](crate::game_of_life::Pattern::from_rle).
+    pub mod collection {
+        pub const GLIDER: &str = "#N Glider
+x = 3, y = 3, rule = B3/S23
+bo$2bo$3o!
+";
+
+        pub const PULSAR: &str = "#N Pulsar
+x = 13, y = 13, rule = B3/S23
+2b3o3b3o2$o4bobo4bo$o4bobo4bo$o4bobo4bo$2b3o3b3o2$2b3o3b3o$o4bobo4bo$o4b
+obo4bo$o4bobo4bo2$2b3o3b3o!
+";
+
+        pub const PENTADECATHLON: &str = "#N Pentadecathlon
+x = 10, y = 3, rule = B3/S23
+2bo4bo$2ob4ob2o$2bo4bo!
+";
+
+        pub const GOSPER_GLIDER_GUN: &str = "#N Gosper glider gun
+#C The first known gun and the first known finite pattern with unbounded growth.
+x = 36, y = 9, rule = B3/S23
+24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o$2o8bo3bob2o4b
+obo$10bo5bo7bo$11bo3bo$12b2o!
+";
+    }
+
+    pub mod gun {
+        use crate::game_of_life::{Grid2DIdx, Universe};
+
+        /// Gosper glider gun, emits a glider every 30 generations.
+        pub fn create_gun(universe: &mut Universe, x_start: Grid2DIdx, y_start: Grid2DIdx) {
+            universe.create_block(x_start, y_start + 4);
+            universe.create_block(x_start + 34, y_start + 2);
+            universe.set_alive_cells(
+                x_start,
+                y_start,
+                &[
+                    (12, 2),
+                    (13, 2),
+                    (11, 3),
+                    (15, 3),
+                    (10, 4),
+                    (16, 4),
+                    (10, 5),
+                    (14, 5),
+                    (16, 5),
+                    (17, 5),
+                    (10, 6),
+                    (16, 6),
+                    (11, 7),
+                    (15, 7),
+                    (12, 8),
+                    (13, 8),
+                    (24, 0),
+                    (22, 1),
+                    (24, 1),
+                    (20, 2),
+                    (21, 2),
+                    (20, 3),
+                    (21, 3),
+                    (20, 4),
+                    (21, 4),
+                    (22, 5),
+                    (24, 5),
+                    (24, 6),
+                ],
+            );
         }
     }
 }
+
+/// Classifies what a pattern settles into by advancing it and looking for a repeated state.
+///
+/// States are compared up to translation, so that spaceships are recognised. On a toroidal grid
+/// a spaceship eventually wraps around, and is reported as an oscillator instead.
+pub mod analysis {
+    use crate::game_of_life::{Backend, Grid2DIdx, Pattern, Rule, Universe};
+    use rand::{thread_rng, Rng};
+    use rayon::prelude::*;
+    use std::collections::{HashMap, HashSet, VecDeque};
+
+    type Cell = (Grid2DIdx, Grid2DIdx);
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum Classification {
+        /// Every cell has died.
+        Extinct,
+        StillLife,
+        Oscillator {
+            period: u64,
+        },
+        /// Returns to the same shape every `period` generations, moved by `displacement`.
+        Spaceship {
+            period: u64,
+            displacement: Cell,
+        },
+        /// The population grows by `cells` every `period` generations, as it does for a gun.
+        LinearGrowth {
+            period: u64,
+            cells: usize,
+        },
+        /// Nothing was found within the generations analysed.
+        Unclassified,
+    }
+
+    impl Classification {
+        pub fn period(&self) -> Option<u64> {
+            match *self {
+                Classification::StillLife => Some(1),
+                Classification::Oscillator { period }
+                | Classification::Spaceship { period, .. }
+                | Classification::LinearGrowth { period, .. } => Some(period),
+                Classification::Extinct | Classification::Unclassified => None,
+            }
+        }
+
+        /// Cells per generation in each direction; zero for anything that stays in place.
+        pub fn velocity(&self) -> Option<(f64, f64)> {
+            match *self {
+                Classification::Spaceship {
+                    period,
+                    displacement: (dx, dy),
+                } => Some((dx as f64 / period as f64, dy as f64 / period as f64)),
+                Classification::Extinct | Classification::Unclassified => None,
+                _ => Some((0., 0.)),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct Analysis {
+        pub classification: Classification,
+        /// Number of generations, from the start of the analysis, before the pattern settled.
+        pub settled_at: Option<u64>,
+        /// Population at every generation analysed, including the initial one.
+        pub populations: Vec<usize>,
+    }
+
+    /// Moves the top-left corner of the bounding box of `live_cells` to the origin.
+    fn normalise(live_cells: &[Cell]) -> (Cell, Vec<Cell>) {
+        let x_min = live_cells.iter().map(|&(x, _)| x).min().unwrap_or(0);
+        let y_min = live_cells.iter().map(|&(_, y)| y).min().unwrap_or(0);
+        let shape = live_cells
+            .iter()
+            .map(|&(x, y)| (x - x_min, y - y_min))
+            .collect();
+        ((x_min, y_min), shape)
+    }
+
+    /// Advances a copy of `universe` for up to `max_generations`, until a state repeats.
+    pub fn analyse(universe: &Universe, max_generations: u64) -> Analysis {
+        let mut universe = universe.clone();
+        let mut seen: HashMap<_, (u64, Cell)> = HashMap::new();
+        let mut populations = Vec::new();
+
+        for generation in 0..=max_generations {
+            let live_cells = universe.live_cells();
+            populations.push(live_cells.len());
+            if live_cells.is_empty() {
+                return Analysis {
+                    classification: Classification::Extinct,
+                    settled_at: Some(generation),
+                    populations,
+                };
+            }
+
+            let (offset, shape) = normalise(&live_cells);
+            if let Some(&(first_seen, first_offset)) = seen.get(&shape) {
+                let period = generation - first_seen;
+                let displacement = (offset.0 - first_offset.0, offset.1 - first_offset.1);
+                let classification = match (period, displacement) {
+                    (1, (0, 0)) => Classification::StillLife,
+                    (_, (0, 0)) => Classification::Oscillator { period },
+                    _ => Classification::Spaceship {
+                        period,
+                        displacement,
+                    },
+                };
+                return Analysis {
+                    classification,
+                    settled_at: Some(first_seen),
+                    populations,
+                };
+            }
+            seen.insert(shape, (generation, offset));
+
+            if generation < max_generations {
+                universe.update();
+            }
+        }
+
+        let classification = linear_growth(&populations).unwrap_or(Classification::Unclassified);
+        Analysis {
+            classification,
+            settled_at: None,
+            populations,
+        }
+    }
+
+    /// Looks for the shortest period over which the population has grown by the same positive
+    /// amount, across the last three periods.
+    fn linear_growth(populations: &[usize]) -> Option<Classification> {
+        (1..=populations.len() / 4).find_map(|period| {
+            let window = &populations[populations.len() - 3 * period - 1..];
+            let cells = window[period].checked_sub(window[0])?;
+            let constant_growth =
+                (0..window.len() - period).all(|t| window[t + period] == window[t] + cells);
+            if cells > 0 && constant_growth {
+                Some(Classification::LinearGrowth {
+                    period: period as u64,
+                    cells,
+                })
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Splits `live_cells` into groups whose cells are at most `distance` apart, horizontally
+    /// and vertically, from another cell of the group.
+    pub fn components(live_cells: &[Cell], distance: Grid2DIdx) -> Vec<Vec<Cell>> {
+        let mut unvisited: HashSet<_> = live_cells.iter().cloned().collect();
+        let mut components = Vec::new();
+        for &start in live_cells {
+            if !unvisited.remove(&start) {
+                continue;
+            }
+            let mut component = vec![start];
+            let mut queue = VecDeque::from(vec![start]);
+            while let Some((x, y)) = queue.pop_front() {
+                for dy in -distance..=distance {
+                    for dx in -distance..=distance {
+                        if unvisited.remove(&(x + dx, y + dy)) {
+                            component.push((x + dx, y + dy));
+                            queue.push_back((x + dx, y + dy));
+                        }
+                    }
+                }
+            }
+            component.sort_by_key(|&(x, y)| (y, x));
+            components.push(component);
+        }
+        components
+    }
+
+    /// A shape that is the same for every phase, rotation and reflection of an object, i.e. the
+    /// smallest of them.
+    fn canonical_shape(universe: &Universe, period: u64) -> Pattern {
+        let symmetries: [fn(Cell) -> Cell; 8] = [
+            |(x, y)| (x, y),
+            |(x, y)| (-x, y),
+            |(x, y)| (x, -y),
+            |(x, y)| (-x, -y),
+            |(x, y)| (y, x),
+            |(x, y)| (-y, x),
+            |(x, y)| (y, -x),
+            |(x, y)| (-y, -x),
+        ];
+        let mut universe = universe.clone();
+        let mut canonical: Option<Vec<_>> = None;
+        for _ in 0..period.max(1) {
+            let live_cells = universe.live_cells();
+            for symmetry in symmetries.iter() {
+                let transformed: Vec<_> = live_cells.iter().map(|&cell| symmetry(cell)).collect();
+                let mut shape = normalise(&transformed).1;
+                shape.sort_by_key(|&(x, y)| (y, x));
+                let smallest = match &canonical {
+                    Some(smallest) => shape < *smallest,
+                    None => true,
+                };
+                if smallest {
+                    canonical = Some(shape);
+                }
+            }
+            universe.update();
+        }
+        Pattern::from_cells(&canonical.unwrap_or_default())
+    }
+
+    /// A separate object found by [`SoupSearch`].
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct Object {
+        pub classification: Classification,
+        pub shape: Pattern,
+    }
+
+    pub type Census = HashMap<Object, usize>;
+
+    /// Runs random soups and counts the objects that remain.
+    #[derive(Debug, Clone)]
+    pub struct SoupSearch {
+        pub rule: Rule,
+        /// Side of the square the soup is sown in, on the unbounded plane.
+        pub soup_size: Grid2DIdx,
+        pub density: f64,
+        /// Generations to run each soup for before splitting it into objects.
+        pub settle_generations: u64,
+        /// Generations to analyse each object for.
+        pub max_period: u64,
+    }
+
+    impl Default for SoupSearch {
+        fn default() -> Self {
+            Self {
+                rule: Rule::conway(),
+                soup_size: 16,
+                density: 0.5,
+                settle_generations: 1000,
+                max_period: 100,
+            }
+        }
+    }
+
+    impl SoupSearch {
+        fn random_soup(&self) -> Universe {
+            let mut rng = thread_rng();
+            let mut universe = Universe::unbounded(self.rule, Backend::Sparse)
+                .expect("soups need a rule without B0");
+            for y in 0..self.soup_size {
+                for x in 0..self.soup_size {
+                    if rng.gen_bool(self.density) {
+                        universe.set_state(x, y, super::State::Alive);
+                    }
+                }
+            }
+            universe
+        }
+
+        /// Objects closer than two cells to each other are counted as one object.
+        pub fn census(&self, soups: usize) -> Census {
+            (0..soups)
+                .into_par_iter()
+                .map(|_| {
+                    let mut soup = self.random_soup();
+                    soup.run(self.settle_generations);
+                    components(&soup.live_cells(), 2)
+                        .into_iter()
+                        .map(|component| {
+                            let mut object = Universe::unbounded(self.rule, Backend::Sparse)
+                                .expect("soups need a rule without B0");
+                            object.set_alive_cells(0, 0, &component);
+                            let classification = analyse(&object, self.max_period).classification;
+                            let shape =
+                                canonical_shape(&object, classification.period().unwrap_or(1));
+                            Object {
+                                classification,
+                                shape,
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .flatten()
+                .fold(Census::new, |mut census, object| {
+                    *census.entry(object).or_insert(0) += 1;
+                    census
+                })
+                .reduce(Census::new, |mut census, partial_census| {
+                    for (object, count) in partial_census {
+                        *census.entry(object).or_insert(0) += count;
+                    }
+                    census
+                })
+        }
+    }
+}
+
+/// Smallest `p <= max_period` such that the universe returns to its current state.
+#[cfg(test)]
+fn period_of(universe: &Universe, max_period: u64) -> Option<u64> {
+    let mut evolved = universe.clone();
+    for period in 1..=max_period {
+        evolved.update();
+        if evolved.live_cells() == universe.live_cells() {
+            return Some(period);
+        }
+    }
+    None
+}
+
+#[test]
+fn parsing_rule_strings() {
+    assert_eq!(Rule::conway().to_string(), "B3/S23");
+    assert_eq!("b36/s23".parse::<Rule>().unwrap(), Rule::highlife());
+    assert_eq!(
+        "S34678/B3678".parse::<Rule>().unwrap(),
+        Rule::day_and_night()
+    );
+    assert_eq!("B/S".parse::<Rule>().unwrap().to_string(), "B/S");
+    assert!("B3".parse::<Rule>().is_err());
+    assert!("B39/S23".parse::<Rule>().is_err());
+    assert!("B3/S23/B1".parse::<Rule>().is_err());
+    assert_eq!("23/3".parse::<Rule>().unwrap(), Rule::conway());
+    assert_eq!("B3/S23:T100,100".parse::<Rule>().unwrap(), Rule::conway());
+}
+
+#[test]
+fn still_lifes_have_period_one() {
+    use patterns::still_life::*;
+
+    for create in &[create_beehive, create_loaf, create_boat, create_tube] {
+        let mut universe = Universe::new(8, 8, Boundary::Bounded, Rule::conway());
+        create(&mut universe, 2, 2);
+        assert_eq!(period_of(&universe, 5), Some(1));
+    }
+    let mut universe = Universe::new(8, 8, Boundary::Bounded, Rule::conway());
+    universe.create_block(3, 3);
+    assert_eq!(period_of(&universe, 5), Some(1));
+}
+
+#[test]
+fn oscillator_periods() {
+    use patterns::oscillators::*;
+
+    let cases: &[(PatternConstructor, u64)] = &[
+        (create_blinker, 2),
+        (create_toad, 2),
+        (create_beacon, 2),
+        (create_pulsar, 3),
+        (create_pentadecathlon, 15),
+    ];
+    for (create, period) in cases {
+        let mut universe = Universe::new(25, 25, Boundary::Bounded, Rule::conway());
+        create(&mut universe, 6, 6);
+        println!("{}", universe);
+        assert_eq!(period_of(&universe, 20), Some(*period));
+    }
+}
+
+#[test]
+fn spaceships_return_on_a_torus() {
+    use patterns::glider::*;
+
+    // A glider moves (1, 1) every 4 generations, so it needs 4 * 16 generations to wrap
+    // around a 16 x 16 torus.
+    let mut universe = Universe::new(16, 16, Boundary::Toroidal, Rule::conway());
+    create_glider(&mut universe, 0, 0);
+    assert_eq!(period_of(&universe, 64), Some(64));
+
+    let mut shifted = universe.clone();
+    shifted.run(4);
+    let mut expected = Universe::new(16, 16, Boundary::Toroidal, Rule::conway());
+    create_glider(&mut expected, 1, 1);
+    assert_eq!(shifted.live_cells(), expected.live_cells());
+
+    // The lightweight spaceship moves 2 cells every 4 generations.
+    let mut universe = Universe::new(20, 9, Boundary::Toroidal, Rule::conway());
+    create_spaceship(&mut universe, 5, 2);
+    assert_eq!(period_of(&universe, 40), Some(40));
+}
+
+#[test]
+fn gosper_gun_emits_a_glider_every_30_generations() {
+    use patterns::gun::create_gun;
+
+    let mut universe = Universe::new(80, 60, Boundary::Bounded, Rule::conway());
+    create_gun(&mut universe, 1, 1);
+    assert_eq!(universe.population(), 36);
+
+    let populations: Vec<_> = (0..4)
+        .map(|_| {
+            universe.run(30);
+            universe.population()
+        })
+        .collect();
+    println!("{:?}", populations);
+    assert!(populations.windows(2).all(|pair| pair[1] == pair[0] + 5));
+}
+
+#[test]
+fn highlife_replicator() {
+    let mut universe = Universe::new(40, 40, Boundary::Bounded, Rule::highlife());
+    // The HighLife replicator doubles itself every 12 generations.
+    universe.set_alive_cells(
+        18,
+        18,
+        &[
+            (2, 0),
+            (3, 0),
+            (4, 0),
+            (1, 1),
+            (4, 1),
+            (0, 2),
+            (4, 2),
+            (0, 3),
+            (3, 3),
+            (0, 4),
+            (1, 4),
+            (2, 4),
+        ],
+    );
+    let initial_population = universe.population();
+    universe.run(12);
+    println!("{}", universe);
+    assert_eq!(universe.population(), 2 * initial_population);
+}
+
+#[test]
+fn embedded_rle_patterns_match_the_constructors() {
+    use patterns::collection::*;
+    use patterns::glider::create_glider;
+    use patterns::gun::create_gun;
+    use patterns::oscillators::{create_pentadecathlon, create_pulsar};
+
+    let cases: &[(&str, PatternConstructor)] = &[
+        (GLIDER, create_glider),
+        (PULSAR, create_pulsar),
+        (PENTADECATHLON, create_pentadecathlon),
+        (GOSPER_GLIDER_GUN, create_gun),
+    ];
+    for (rle, create) in cases {
+        let pattern = Pattern::from_rle(rle).unwrap();
+        println!("{:?}: {} cells", pattern.name, pattern.population());
+        assert_eq!(pattern.rule, Some(Rule::conway()));
+
+        let mut from_file = Universe::new(40, 40, Boundary::Bounded, Rule::conway());
+        from_file.place_pattern(&pattern, 2, 3);
+        let mut from_code = Universe::new(40, 40, Boundary::Bounded, Rule::conway());
+        create(&mut from_code, 2, 3);
+        assert_eq!(from_file, from_code);
+    }
+}
+
+#[test]
+fn rle_round_trip() {
+    let gun = Pattern::from_rle(patterns::collection::GOSPER_GLIDER_GUN).unwrap();
+    assert_eq!((gun.width(), gun.height()), (36, 9));
+    assert_eq!(gun.comments.len(), 1);
+
+    let rle = gun.to_rle();
+    println!("{}", rle);
+    assert!(rle
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .all(|line| line.len() <= 70));
+    assert_eq!(Pattern::from_rle(&rle).unwrap(), gun);
+
+    // A universe cropped back to a pattern has the same cells.
+    let universe = gun.to_universe(5, Boundary::Bounded);
+    assert_eq!(universe.to_pattern().cells(), gun.cells());
+
+    assert!(Pattern::from_rle("bo$2bo$3o!").is_err());
+    assert!(Pattern::from_rle("x = 2, y = 1\n3o!").is_err());
+    assert!(Pattern::from_rle("x = 3, y = 1, rule = B3/S23\n3q!").is_err());
+}
+
+#[test]
+fn plaintext_round_trip() {
+    let glider = Pattern::from_plaintext(
+        "!Name: Glider
+!The smallest spaceship.
+.O.
+..O
+OOO
+",
+    )
+    .unwrap();
+    assert_eq!(glider.name.as_deref(), Some("Glider"));
+    assert_eq!(
+        glider.cells(),
+        Pattern::from_rle(patterns::collection::GLIDER)
+            .unwrap()
+            .cells()
+    );
+    assert_eq!(
+        Pattern::from_plaintext(&glider.to_plaintext()).unwrap(),
+        glider
+    );
+
+    let path = std::env::temp_dir().join("epibox_glider.cells");
+    glider.write(&path).unwrap();
+    assert_eq!(Pattern::read(&path).unwrap(), glider);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn backends_agree_on_a_random_soup() {
+    use rand::{thread_rng, Rng};
+
+    let mut rng = thread_rng();
+    let soup: Vec<_> = (0..20)
+        .flat_map(|y| (0..20).map(move |x| (x, y)))
+        .filter(|_| rng.gen_bool(0.35))
+        .collect();
+
+    for &boundary in &[Boundary::Toroidal, Boundary::Bounded] {
+        let mut dense =
+            Universe::with_backend(30, 30, boundary, Rule::conway(), Backend::Dense).unwrap();
+        let mut sparse =
+            Universe::with_backend(30, 30, boundary, Rule::conway(), Backend::Sparse).unwrap();
+        dense.set_alive_cells(5, 5, &soup);
+        sparse.set_alive_cells(5, 5, &soup);
+        for _ in 0..100 {
+            dense.update();
+            sparse.update();
+            assert_eq!(dense, sparse);
+        }
+    }
+
+    assert!(Universe::with_backend(
+        30,
+        30,
+        Boundary::Toroidal,
+        Rule::conway(),
+        Backend::Hashlife
+    )
+    .is_err());
+    assert!(Universe::unbounded(Rule::conway(), Backend::Dense).is_err());
+    let b0: Rule = "B03/S23".parse().unwrap();
+    assert!(Universe::with_backend(30, 30, Boundary::Toroidal, b0, Backend::Sparse).is_err());
+    assert!(Universe::unbounded(b0, Backend::Hashlife).is_err());
+
+    let mut sparse = Universe::unbounded(Rule::highlife(), Backend::Sparse).unwrap();
+    let mut hashlife = Universe::unbounded(Rule::highlife(), Backend::Hashlife).unwrap();
+    sparse.set_alive_cells(-10, -10, &soup);
+    hashlife.set_alive_cells(-10, -10, &soup);
+    for _ in 0..100 {
+        sparse.update();
+        hashlife.update();
+        assert_eq!(sparse, hashlife);
+    }
+    // Leaping many generations at once ends up in the same place.
+    sparse.run(157);
+    hashlife.run(157);
+    assert_eq!(sparse, hashlife);
+}
+
+#[test]
+fn hashlife_runs_a_gosper_gun_for_a_million_generations() {
+    let gun = Pattern::from_rle(patterns::collection::GOSPER_GLIDER_GUN).unwrap();
+    let mut universe = Universe::unbounded(Rule::conway(), Backend::Hashlife).unwrap();
+    universe.place_pattern(&gun, 0, 0);
+
+    // A new glider of 5 cells leaves the gun every 30 generations.
+    universe.run(999_990);
+    let population = universe.population();
+    universe.run(30);
+    assert_eq!(universe.generation(), 1_000_020);
+    assert_eq!(universe.population(), population + 5);
+    println!("{:?}", universe);
+}
+
+#[test]
+fn analysing_the_named_patterns() {
+    use analysis::{analyse, Classification};
+    use patterns::{glider::*, gun::*, oscillators::*, still_life::*};
+
+    let cases: &[(PatternConstructor, Classification)] = &[
+        (create_beehive, Classification::StillLife),
+        (create_loaf, Classification::StillLife),
+        (create_boat, Classification::StillLife),
+        (create_tube, Classification::StillLife),
+        (create_blinker, Classification::Oscillator { period: 2 }),
+        (create_toad, Classification::Oscillator { period: 2 }),
+        (create_beacon, Classification::Oscillator { period: 2 }),
+        (create_pulsar, Classification::Oscillator { period: 3 }),
+        (
+            create_pentadecathlon,
+            Classification::Oscillator { period: 15 },
+        ),
+        (
+            create_glider,
+            Classification::Spaceship {
+                period: 4,
+                displacement: (1, 1),
+            },
+        ),
+        (
+            create_spaceship,
+            Classification::Spaceship {
+                period: 4,
+                displacement: (-2, 0),
+            },
+        ),
+        (
+            create_gun,
+            Classification::LinearGrowth {
+                period: 30,
+                cells: 5,
+            },
+        ),
+    ];
+    for (create, expected) in cases {
+        let mut universe = Universe::unbounded(Rule::conway(), Backend::Sparse).unwrap();
+        create(&mut universe, 0, 0);
+        let analysis = analyse(&universe, 200);
+        println!(
+            "{:?} settled at {:?}",
+            analysis.classification, analysis.settled_at
+        );
+        assert_eq!(analysis.classification, *expected);
+    }
+
+    let mut universe = Universe::unbounded(Rule::conway(), Backend::Sparse).unwrap();
+    create_glider(&mut universe, 0, 0);
+    let velocity = analyse(&universe, 10).classification.velocity();
+    assert_eq!(velocity, Some((0.25, 0.25)));
+
+    // A lone cell dies straight away, and a pre-block becomes a block after one generation.
+    universe = Universe::unbounded(Rule::conway(), Backend::Sparse).unwrap();
+    universe.set_state(0, 0, State::Alive);
+    let analysis = analyse(&universe, 10);
+    assert_eq!(analysis.classification, Classification::Extinct);
+    assert_eq!(analysis.settled_at, Some(1));
+    universe.set_alive_cells(0, 0, &[(1, 0), (0, 1)]);
+    let analysis = analyse(&universe, 10);
+    assert_eq!(analysis.classification, Classification::StillLife);
+    assert_eq!(analysis.settled_at, Some(1));
+}
+
+#[test]
+fn soup_census() {
+    use analysis::{Classification, SoupSearch};
+
+    let search = SoupSearch {
+        settle_generations: 500,
+        ..Default::default()
+    };
+    let census = search.census(8);
+    let mut objects: Vec<_> = census.iter().collect();
+    objects.sort_by_key(|(_, &count)| std::cmp::Reverse(count));
+    for (object, count) in &objects {
+        println!(
+            "{:>4} x {:?}: {}",
+            count,
+            object.classification,
+            object.shape.to_rle()
+        );
+    }
+
+    // Every block found has the same canonical shape, no matter its orientation.
+    let block = Pattern::from_cells(&[(0, 0), (1, 0), (0, 1), (1, 1)]);
+    assert!(census
+        .keys()
+        .filter(|object| object.shape == block)
+        .all(|object| object.classification == Classification::StillLife));
+    assert!(census.values().sum::<usize>() > 0);
+}
@@ -1,57 +1,897 @@
 //! Source: [Lecture 4](http://prac.im.pwr.wroc.pl/~szwabin/assets/abm/lec/3.pdf)
+//!
+//! From lecture:
+//!    universe – a plane
+//!    ● agents – people being in one of two states: brave or cowardly
+//!    ● initial state:
+//!    ● all brave
+//!    ● all cowards
+//!    ● mixed population
+//!    ● random positions
+//!    ● rules:
+//!    ● if brave, move toward the midpoint of your friend and enemy
+//!    ● if a coward, put your friend between you and your enemy
+//!    ● time evolution – in every tick check the state of the agent and act
+//!    accordingly
+use crate::particle_system::{Integrator, Particle};
+use ndarray::arr1;
+use rand::seq::SliceRandom;
+use rand::{thread_rng, Rng};
+use std::fmt::{Display, Error, Formatter};
 
-struct Universe {
+type Numeric = f64;
+pub type AgentId = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Position {
+    pub x: Numeric,
+    pub y: Numeric,
+}
+
+impl Position {
+    pub fn new(x: Numeric, y: Numeric) -> Self {
+        Self { x, y }
+    }
+
+    pub fn distance(&self, other: &Position) -> Numeric {
+        (self.x - other.x).hypot(self.y - other.y)
+    }
+
+    /// The point a fraction `t` of the way from `self` to `other`; `t` may lie outside `[0, 1]`.
+    fn lerp(&self, other: &Position, t: Numeric) -> Position {
+        Position::new(
+            self.x + t * (other.x - self.x),
+            self.y + t * (other.y - self.y),
+        )
+    }
+
+    /// Moves at most `step_size` towards `target`, without overshooting it.
+    fn step_towards(&self, target: &Position, step_size: Numeric) -> Position {
+        let distance = self.distance(target);
+        if distance <= step_size {
+            *target
+        } else {
+            self.lerp(target, step_size / distance)
+        }
+    }
+}
+
+/// How the personalities of the agents are initialised.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Personality {
+    /// Every agent is [`State::Brave`].
+    Heroes,
+    /// Every agent is [`State::Cowardly`].
+    Cowards,
+    /// Each agent is [`State::Brave`] with the given probability.
+    Mixed(Numeric),
+}
+
+/// Who the agents count as friends and enemies.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SocialGraph {
+    /// Each agent picks `no_friends` friends and `no_enemies` enemies among all others, at
+    /// random and with unit weights. The original model has one of each.
+    Random {
+        no_friends: usize,
+        no_enemies: usize,
+    },
+    /// Agent `id` belongs to faction `id % no_factions`. Friends are picked from the agent's own
+    /// faction and enemies from the others, at random and with unit weights.
+    Factions {
+        no_factions: usize,
+        no_friends: usize,
+        no_enemies: usize,
+    },
+    /// `(agent, perception, relation)` gives `agent` a weighted friend or enemy.
+    Edges(Vec<(AgentId, Perceptions, Relation)>),
+}
+
+impl SocialGraph {
+    /// The friends and enemies of every agent.
+    fn relations(&self, no_agents: usize) -> Vec<(Vec<Relation>, Vec<Relation>)> {
+        let mut rng = thread_rng();
+        let mut pick = |candidates: Vec<AgentId>, amount: usize| {
+            assert!(
+                amount <= candidates.len(),
+                "not enough agents to pick {} friends or enemies from",
+                amount
+            );
+            let mut candidates = candidates;
+            candidates.partial_shuffle(&mut rng, amount).0.to_vec()
+        };
+        let unit_weights = |agents: &[AgentId]| {
+            agents
+                .iter()
+                .map(|&agent| Relation { agent, weight: 1. })
+                .collect::<Vec<_>>()
+        };
+
+        match self {
+            SocialGraph::Random {
+                no_friends,
+                no_enemies,
+            } => (0..no_agents)
+                .map(|id| {
+                    let others = (0..no_agents).filter(|&x| x != id).collect();
+                    let picked = pick(others, no_friends + no_enemies);
+                    let (friends, enemies) = picked.split_at(*no_friends);
+                    (unit_weights(friends), unit_weights(enemies))
+                })
+                .collect(),
+            SocialGraph::Factions {
+                no_factions,
+                no_friends,
+                no_enemies,
+            } => {
+                assert!(*no_factions > 0, "there must be at least one faction");
+                (0..no_agents)
+                    .map(|id| {
+                        let (allies, others): (Vec<_>, Vec<_>) = (0..no_agents)
+                            .filter(|&x| x != id)
+                            .partition(|x| x % no_factions == id % no_factions);
+                        let friends = pick(allies, *no_friends);
+                        let enemies = pick(others, *no_enemies);
+                        (unit_weights(&friends), unit_weights(&enemies))
+                    })
+                    .collect()
+            }
+            SocialGraph::Edges(edges) => {
+                let mut relations = vec![(Vec::new(), Vec::new()); no_agents];
+                for &(agent, perception, relation) in edges {
+                    assert!(
+                        agent < no_agents && relation.agent < no_agents,
+                        "the edge from {} to {} refers to an agent beyond the {} agents",
+                        agent,
+                        relation.agent,
+                        no_agents
+                    );
+                    match perception {
+                        Perceptions::Friend => relations[agent].0.push(relation),
+                        Perceptions::Enemy => relations[agent].1.push(relation),
+                    }
+                }
+                relations
+            }
+        }
+    }
+}
+
+/// How an agent decides, at the start of every tick, whether to be brave or cowardly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SwitchingRule {
+    /// Agents keep their initial personality.
+    Fixed,
+    /// Cowardly while the perceived enemy is closer than `radius`, brave otherwise.
+    FearOfEnemy { radius: Numeric },
+    /// Takes on the state with the larger total weight among the agent's friends, and keeps its
+    /// own state on a tie.
+    ImitateFriends,
+    /// Switches to the other state with the given probability.
+    Random(Numeric),
+}
+
+/// How the agents move towards their targets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Motion {
+    /// A step of at most the step size in every tick, as in the lecture.
+    Step,
+    /// Unit point masses moved by a particle [`Integrator`] over ticks of unit length. They
+    /// accelerate by `rate` times the difference between the velocity of a [`Motion::Step`]
+    /// and their own, with `rate` in `(0, 1]`, so they keep some of their momentum.
+    Inertial {
+        integrator: Integrator,
+        rate: Numeric,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Universe {
     agents: Vec<Agent>,
+    step_size: Numeric,
+    switching_rule: SwitchingRule,
+    motion: Motion,
+    /// Velocities of every agent, which only [`Motion::Inertial`] agents keep.
+    velocities: Vec<(Numeric, Numeric)>,
+    time: u64,
+    /// Positions of every agent, at every tick.
+    trajectories: Vec<Vec<Position>>,
+    /// States of every agent, at every tick.
+    states: Vec<Vec<State>>,
 }
 
 impl Universe {
-    fn new() {
-        todo!()
-        // Initialize:
-        //  Create NUMBER agents
-        //  Move each agent to a random location
-        //  If “hero” personality chosen, each agent turns blue
-        //  If “coward” personality chosen, each agent turns red
-        //  If “mixed” personality chosen, color each agent red or blue at random
-        //  Each agent picks one other agent as friend
-        //  Each agent picks one other agent as enemy
-        //  Start the clock
-    }
-
-    fn update() {
-        todo!()
-        // At each tick:
-        //  Each blue agent moves a step towards a location between his
-        //  friend and its enemy
-        //  Each red agent moves a step towards a location that puts his friend
-        //      between him and his enemy
-    }
-
-    // From lecture:
-    //    universe – a plane
-    //    ● agents – people being in one of two states: brave or cowardly
-    //    ● initial state:
-    //    ● all brave
-    //    ● all cowards
-    //    ● mixed population
-    //    ● random positions
-    //    ● rules:
-    //    ● if brave, move toward the midpoint of your friend and enemy
-    //    ● if a coward, put your friend between you and your enemy
-    //    ● time evolution – in every tick check the state of the agent and act
-    //    accordingly
-}
-
-struct Agent {
-    state: State,
-}
-
-enum State {
+    /// Initialize:
+    ///  Create NUMBER agents
+    ///  Move each agent to a random location
+    ///  If “hero” personality chosen, each agent turns blue
+    ///  If “coward” personality chosen, each agent turns red
+    ///  If “mixed” personality chosen, color each agent red or blue at random
+    ///  Each agent picks one other agent as friend
+    ///  Each agent picks one other agent as enemy
+    ///  Start the clock
+    ///
+    /// The agents are placed uniformly in the unit square, and get one friend and one enemy from
+    /// a [`SocialGraph::Random`] graph.
+    pub fn new(no_agents: usize, personality: Personality, step_size: Numeric) -> Self {
+        assert!(
+            no_agents >= 3,
+            "every agent needs a friend and an enemy other than itself"
+        );
+        let mut rng = thread_rng();
+
+        let agents = (0..no_agents)
+            .map(|_| {
+                let state = match personality {
+                    Personality::Heroes => State::Brave,
+                    Personality::Cowards => State::Cowardly,
+                    Personality::Mixed(brave_probability) => {
+                        if rng.gen_bool(brave_probability) {
+                            State::Brave
+                        } else {
+                            State::Cowardly
+                        }
+                    }
+                };
+                Agent {
+                    position: Position::new(rng.gen(), rng.gen()),
+                    state,
+                    friends: Vec::new(),
+                    enemies: Vec::new(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        Self::from_agents(agents, step_size).set_social_graph(SocialGraph::Random {
+            no_friends: 1,
+            no_enemies: 1,
+        })
+    }
+
+    /// Replaces the friends and enemies of every agent.
+    pub fn set_social_graph(mut self, social_graph: SocialGraph) -> Self {
+        let relations = social_graph.relations(self.agents.len());
+        for (agent, (friends, enemies)) in self.agents.iter_mut().zip(relations) {
+            agent.friends = friends;
+            agent.enemies = enemies;
+        }
+        self
+    }
+
+    pub fn set_switching_rule(mut self, switching_rule: SwitchingRule) -> Self {
+        self.switching_rule = switching_rule;
+        self
+    }
+
+    pub fn set_motion(mut self, motion: Motion) -> Self {
+        if let Motion::Inertial { rate, .. } = motion {
+            assert!(
+                rate > 0. && rate <= 1.,
+                "the rate of an inertial motion must lie in (0, 1]: {}",
+                rate
+            );
+        }
+        self.motion = motion;
+        self
+    }
+
+    pub fn from_agents(agents: Vec<Agent>, step_size: Numeric) -> Self {
+        let positions = agents.iter().map(|agent| agent.position).collect();
+        let states = agents.iter().map(|agent| agent.state).collect();
+        Self {
+            velocities: vec![(0., 0.); agents.len()],
+            agents,
+            step_size,
+            switching_rule: SwitchingRule::Fixed,
+            motion: Motion::Step,
+            time: 0,
+            trajectories: vec![positions],
+            states: vec![states],
+        }
+    }
+
+    pub fn agents(&self) -> &[Agent] {
+        &self.agents
+    }
+
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    /// `trajectories()[t][id]` is the position of agent `id` at tick `t`.
+    pub fn trajectories(&self) -> &[Vec<Position>] {
+        &self.trajectories
+    }
+
+    /// `states()[t][id]` is the state of agent `id` at tick `t`.
+    pub fn states(&self) -> &[Vec<State>] {
+        &self.states
+    }
+
+    /// The weighted mean position of the agent's friends or enemies, if it has any.
+    fn perceive(&self, agent: &Agent, perception: Perceptions) -> Option<Position> {
+        let relations = agent.relations(perception);
+        let total_weight: Numeric = relations.iter().map(|relation| relation.weight).sum();
+        if relations.is_empty() || total_weight == 0. {
+            return None;
+        }
+        let (x, y) = relations.iter().fold((0., 0.), |(x, y), relation| {
+            let position = self.agents[relation.agent].position;
+            (
+                x + relation.weight * position.x,
+                y + relation.weight * position.y,
+            )
+        });
+        Some(Position::new(x / total_weight, y / total_weight))
+    }
+
+    /// Where `agent` wants to go, given the current positions of everyone. An agent without
+    /// friends or enemies stays where it is.
+    fn target(&self, agent: &Agent) -> Position {
+        let friend = self.perceive(agent, Perceptions::Friend);
+        let enemy = self.perceive(agent, Perceptions::Enemy);
+        match (friend, enemy, agent.state) {
+            (Some(friend), Some(enemy), State::Brave) => friend.lerp(&enemy, 0.5),
+            // Half as far behind the friend as the friend is from the enemy.
+            (Some(friend), Some(enemy), State::Cowardly) => friend.lerp(&enemy, -0.5),
+            _ => agent.position,
+        }
+    }
+
+    fn next_state(&self, agent: &Agent, rng: &mut impl Rng) -> State {
+        match self.switching_rule {
+            SwitchingRule::Fixed => agent.state,
+            SwitchingRule::FearOfEnemy { radius } => {
+                match self.perceive(agent, Perceptions::Enemy) {
+                    Some(enemy) if agent.position.distance(&enemy) < radius => State::Cowardly,
+                    Some(_) => State::Brave,
+                    None => agent.state,
+                }
+            }
+            SwitchingRule::ImitateFriends => {
+                let brave_weight: Numeric = agent
+                    .friends
+                    .iter()
+                    .map(|relation| match self.agents[relation.agent].state {
+                        State::Brave => relation.weight,
+                        State::Cowardly => -relation.weight,
+                    })
+                    .sum();
+                if brave_weight > 0. {
+                    State::Brave
+                } else if brave_weight < 0. {
+                    State::Cowardly
+                } else {
+                    agent.state
+                }
+            }
+            SwitchingRule::Random(probability) => match (agent.state, rng.gen_bool(probability)) {
+                (state, false) => state,
+                (State::Brave, true) => State::Cowardly,
+                (State::Cowardly, true) => State::Brave,
+            },
+        }
+    }
+
+    /// Moves the agents as particles for one tick and returns their new positions.
+    fn move_inertially(
+        &mut self,
+        targets: &[Position],
+        integrator: Integrator,
+        rate: Numeric,
+    ) -> Vec<Position> {
+        let step_size = self.step_size;
+        let mut particles: Vec<_> = self
+            .agents
+            .iter()
+            .zip(&self.velocities)
+            .enumerate()
+            .map(|(id, (agent, &(vx, vy)))| Particle {
+                id,
+                position: arr1(&[agent.position.x, agent.position.y]),
+                velocity: arr1(&[vx, vy]),
+                mass: 1.,
+                age: self.time as Numeric,
+                lifetime: None,
+            })
+            .collect();
+        integrator.step(&mut particles, 1., |particles| {
+            particles
+                .iter()
+                .zip(targets)
+                .map(|(particle, target)| {
+                    let position = Position::new(particle.position[0], particle.position[1]);
+                    let step = position.step_towards(target, step_size);
+                    let desired = arr1(&[step.x - position.x, step.y - position.y]);
+                    (desired - &particle.velocity) * rate
+                })
+                .collect()
+        });
+        self.velocities = particles
+            .iter()
+            .map(|particle| (particle.velocity[0], particle.velocity[1]))
+            .collect();
+        particles
+            .iter()
+            .map(|particle| Position::new(particle.position[0], particle.position[1]))
+            .collect()
+    }
+
+    /// At each tick:
+    ///  Each blue agent moves a step towards a location between his
+    ///  friend and its enemy
+    ///  Each red agent moves a step towards a location that puts his friend
+    ///      between him and his enemy
+    ///
+    /// All agents first reconsider their state according to the [`SwitchingRule`], and then
+    /// move, at the same time.
+    pub fn update(&mut self) {
+        let mut rng = thread_rng();
+        let states: Vec<_> = self
+            .agents
+            .iter()
+            .map(|agent| self.next_state(agent, &mut rng))
+            .collect();
+        for (agent, state) in self.agents.iter_mut().zip(states.iter()) {
+            agent.state = *state;
+        }
+
+        let targets: Vec<_> = self.agents.iter().map(|agent| self.target(agent)).collect();
+        let positions: Vec<_> = match self.motion {
+            Motion::Step => self
+                .agents
+                .iter()
+                .zip(&targets)
+                .map(|(agent, target)| agent.position.step_towards(target, self.step_size))
+                .collect(),
+            Motion::Inertial { integrator, rate } => {
+                self.move_inertially(&targets, integrator, rate)
+            }
+        };
+        for (agent, position) in self.agents.iter_mut().zip(positions.iter()) {
+            agent.position = *position;
+        }
+
+        self.trajectories.push(positions);
+        self.states.push(states);
+        self.time += 1;
+    }
+
+    pub fn run(&mut self, ticks: u64) {
+        for _ in 0..ticks {
+            self.update();
+        }
+    }
+
+    /// Spread and clustering of the agents at every recorded tick. Agents closer than
+    /// `cluster_distance` to another agent of a cluster belong to that cluster.
+    pub fn statistics(&self, cluster_distance: Numeric) -> Vec<Statistics> {
+        self.trajectories
+            .iter()
+            .zip(self.states.iter())
+            .enumerate()
+            .map(|(time, (positions, states))| {
+                Statistics::new(time as u64, positions, states, cluster_distance)
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statistics {
+    pub time: u64,
+    pub centroid: Position,
+    /// Mean distance to the centroid.
+    pub dispersion: Numeric,
+    /// Largest distance to the centroid.
+    pub radius: Numeric,
+    pub no_clusters: usize,
+    pub largest_cluster: usize,
+    pub brave_fraction: Numeric,
+}
+
+impl Statistics {
+    fn new(time: u64, positions: &[Position], states: &[State], cluster_distance: Numeric) -> Self {
+        let n = positions.len() as Numeric;
+        let centroid = Position::new(
+            positions.iter().map(|p| p.x).sum::<Numeric>() / n,
+            positions.iter().map(|p| p.y).sum::<Numeric>() / n,
+        );
+        let distances = positions.iter().map(|p| p.distance(&centroid));
+        let cluster_sizes = cluster_sizes(positions, cluster_distance);
+
+        Self {
+            time,
+            centroid,
+            dispersion: distances.clone().sum::<Numeric>() / n,
+            radius: distances.fold(0., Numeric::max),
+            no_clusters: cluster_sizes.len(),
+            largest_cluster: cluster_sizes.into_iter().max().unwrap_or(0),
+            brave_fraction: states.iter().filter(|&&x| x == State::Brave).count() as Numeric / n,
+        }
+    }
+}
+
+/// Single-linkage clustering, by a depth-first search over the agents within `cluster_distance`.
+fn cluster_sizes(positions: &[Position], cluster_distance: Numeric) -> Vec<usize> {
+    let mut visited = vec![false; positions.len()];
+    let mut sizes = Vec::new();
+    for start in 0..positions.len() {
+        if visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        let mut stack = vec![start];
+        let mut size = 0;
+        while let Some(current) = stack.pop() {
+            size += 1;
+            for (other, position) in positions.iter().enumerate() {
+                if !visited[other] && positions[current].distance(position) <= cluster_distance {
+                    visited[other] = true;
+                    stack.push(other);
+                }
+            }
+        }
+        sizes.push(size);
+    }
+    sizes
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Agent {
+    pub position: Position,
+    pub state: State,
+    pub friends: Vec<Relation>,
+    pub enemies: Vec<Relation>,
+}
+
+impl Agent {
+    fn relations(&self, perception: Perceptions) -> &[Relation] {
+        match perception {
+            Perceptions::Friend => &self.friends,
+            Perceptions::Enemy => &self.enemies,
+        }
+    }
+}
+
+/// A friend or an enemy, and how much the agent cares about them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Relation {
+    pub agent: AgentId,
+    pub weight: Numeric,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum State {
     Brave,
     Cowardly,
 }
 
-enum Perceptions {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Perceptions {
     Friend,
     Enemy,
 }
+
+impl Display for Statistics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(
+            f,
+            "time = {:>4} dispersion = {:<10.6} radius = {:<10.6} clusters = {:>3} largest = {:>3} \
+             brave = {:<5.3}",
+            self.time,
+            self.dispersion,
+            self.radius,
+            self.no_clusters,
+            self.largest_cluster,
+            self.brave_fraction
+        )
+    }
+}
+
+#[test]
+fn heroes_gather_and_cowards_scatter() {
+    let mut heroes = Universe::new(50, Personality::Heroes, 0.01);
+    heroes.run(500);
+    let statistics = heroes.statistics(0.01);
+    println!("{}\n{}", statistics[0], statistics.last().unwrap());
+    assert!(statistics.last().unwrap().dispersion < statistics[0].dispersion);
+    // Brave agents never leave the convex hull of the group, so neither does its bounding box.
+    let bounding_boxes: Vec<_> = heroes
+        .trajectories()
+        .iter()
+        .map(|positions| {
+            positions.iter().fold(
+                (
+                    f64::INFINITY,
+                    f64::NEG_INFINITY,
+                    f64::INFINITY,
+                    f64::NEG_INFINITY,
+                ),
+                |(x_min, x_max, y_min, y_max), p| {
+                    (
+                        x_min.min(p.x),
+                        x_max.max(p.x),
+                        y_min.min(p.y),
+                        y_max.max(p.y),
+                    )
+                },
+            )
+        })
+        .collect();
+    assert!(bounding_boxes.windows(2).all(|pair| {
+        let ((x_min, x_max, y_min, y_max), next) = (pair[0], pair[1]);
+        x_min <= next.0 + 1e-12
+            && next.1 <= x_max + 1e-12
+            && y_min <= next.2 + 1e-12
+            && next.3 <= y_max + 1e-12
+    }));
+
+    let mut cowards = Universe::new(50, Personality::Cowards, 0.01);
+    cowards.run(500);
+    let statistics = cowards.statistics(0.01);
+    println!("{}\n{}", statistics[0], statistics.last().unwrap());
+    assert!(statistics.last().unwrap().dispersion > statistics[0].dispersion);
+}
+
+#[test]
+fn inertial_agents() {
+    for &integrator in &[Integrator::Euler, Integrator::Verlet] {
+        let motion = Motion::Inertial {
+            integrator,
+            rate: 0.5,
+        };
+        let mut heroes = Universe::new(50, Personality::Heroes, 0.01).set_motion(motion);
+        heroes.run(500);
+        let statistics = heroes.statistics(0.01);
+        println!("{:?}\n{}", integrator, statistics.last().unwrap());
+        assert!(statistics.last().unwrap().dispersion < statistics[0].dispersion);
+        // the velocity relaxes towards a step, so it is never longer than one
+        assert!(heroes.trajectories().windows(2).all(|pair| pair[0]
+            .iter()
+            .zip(&pair[1])
+            .all(|(before, after)| before.distance(after) <= 0.01 + 1e-12)));
+
+        let mut cowards = Universe::new(50, Personality::Cowards, 0.01).set_motion(motion);
+        cowards.run(500);
+        let statistics = cowards.statistics(0.01);
+        assert!(statistics.last().unwrap().dispersion > statistics[0].dispersion);
+    }
+}
+
+#[test]
+fn mixed_population() {
+    let mut mixed = Universe::new(100, Personality::Mixed(0.5), 0.005);
+    let no_brave = mixed
+        .agents()
+        .iter()
+        .filter(|agent| agent.state == State::Brave)
+        .count();
+    println!("{} of 100 agents are brave", no_brave);
+
+    mixed.run(200);
+    assert_eq!(mixed.trajectories().len(), 201);
+    assert!(mixed.agents().iter().enumerate().all(|(id, agent)| {
+        let (friend, enemy) = (agent.friends[0].agent, agent.enemies[0].agent);
+        friend != id && enemy != id && friend != enemy
+    }));
+    for statistics in mixed.statistics(0.02).iter().step_by(20) {
+        println!("{}", statistics);
+    }
+}
+
+#[test]
+fn single_steps() {
+    let agent = |x, y, state| Agent {
+        position: Position::new(x, y),
+        state,
+        friends: Vec::new(),
+        enemies: Vec::new(),
+    };
+    let relation = |agent| Relation { agent, weight: 1. };
+    let agents = vec![
+        agent(0., 0., State::Brave),
+        agent(2., 0., State::Cowardly),
+        agent(2., 2., State::Cowardly),
+    ];
+    let mut universe =
+        Universe::from_agents(agents, 0.5).set_social_graph(SocialGraph::Edges(vec![
+            (0, Perceptions::Friend, relation(1)),
+            (0, Perceptions::Enemy, relation(2)),
+            (1, Perceptions::Friend, relation(2)),
+            (1, Perceptions::Enemy, relation(0)),
+            (2, Perceptions::Friend, relation(1)),
+            (2, Perceptions::Enemy, relation(0)),
+        ]));
+    universe.update();
+
+    let close = |position: Position, x: Numeric, y: Numeric| {
+        position.distance(&Position::new(x, y)) < 1e-12
+    };
+
+    // The brave agent heads for (2, 1), the midpoint of its friend and enemy.
+    let hero = universe.agents()[0].position;
+    assert!(close(hero, 1. / 5f64.sqrt(), 0.5 / 5f64.sqrt()));
+    // The second agent hides behind its friend at (2, 2), heading for (3, 3).
+    let coward = universe.agents()[1].position;
+    assert!(close(coward, 2. + 0.5 / 10f64.sqrt(), 1.5 / 10f64.sqrt()));
+    // The third agent hides behind its friend at (2, 0), heading for (3, 0).
+    let coward = universe.agents()[2].position;
+    assert!(close(coward, 2. + 0.5 / 5f64.sqrt(), 2. - 1. / 5f64.sqrt()));
+}
+
+#[test]
+fn social_graphs() {
+    let universe =
+        Universe::new(30, Personality::Heroes, 0.01).set_social_graph(SocialGraph::Random {
+            no_friends: 3,
+            no_enemies: 2,
+        });
+    for (id, agent) in universe.agents().iter().enumerate() {
+        let mut related: Vec<_> = agent
+            .friends
+            .iter()
+            .chain(agent.enemies.iter())
+            .map(|relation| relation.agent)
+            .collect();
+        assert_eq!((agent.friends.len(), agent.enemies.len()), (3, 2));
+        assert!(!related.contains(&id));
+        related.sort();
+        related.dedup();
+        assert_eq!(related.len(), 5);
+    }
+
+    let universe =
+        Universe::new(30, Personality::Heroes, 0.01).set_social_graph(SocialGraph::Factions {
+            no_factions: 3,
+            no_friends: 4,
+            no_enemies: 4,
+        });
+    for (id, agent) in universe.agents().iter().enumerate() {
+        assert!(agent
+            .friends
+            .iter()
+            .all(|x| x.agent % 3 == id % 3 && x.agent != id));
+        assert!(agent.enemies.iter().all(|x| x.agent % 3 != id % 3));
+    }
+}
+
+#[test]
+#[should_panic(expected = "at least one faction")]
+fn no_factions() {
+    Universe::new(10, Personality::Heroes, 0.01).set_social_graph(SocialGraph::Factions {
+        no_factions: 0,
+        no_friends: 1,
+        no_enemies: 1,
+    });
+}
+
+#[test]
+#[should_panic(expected = "beyond the 10 agents")]
+fn edge_to_a_missing_agent() {
+    Universe::new(10, Personality::Heroes, 0.01).set_social_graph(SocialGraph::Edges(vec![(
+        0,
+        Perceptions::Friend,
+        Relation {
+            agent: 10,
+            weight: 1.,
+        },
+    )]));
+}
+
+#[test]
+fn weighted_relations() {
+    let agent = |x, y| Agent {
+        position: Position::new(x, y),
+        state: State::Brave,
+        friends: Vec::new(),
+        enemies: Vec::new(),
+    };
+    let agents = vec![agent(0., 0.), agent(4., 0.), agent(0., 4.), agent(-4., 0.)];
+    let universe = Universe::from_agents(agents, 1.).set_social_graph(SocialGraph::Edges(vec![
+        (
+            0,
+            Perceptions::Friend,
+            Relation {
+                agent: 1,
+                weight: 3.,
+            },
+        ),
+        (
+            0,
+            Perceptions::Friend,
+            Relation {
+                agent: 2,
+                weight: 1.,
+            },
+        ),
+        (
+            0,
+            Perceptions::Enemy,
+            Relation {
+                agent: 3,
+                weight: 1.,
+            },
+        ),
+    ]));
+    let hero = &universe.agents()[0];
+    assert_eq!(
+        universe.perceive(hero, Perceptions::Friend),
+        Some(Position::new(3., 1.))
+    );
+    // The midpoint of (3, 1) and (-4, 0).
+    assert_eq!(universe.target(hero), Position::new(-0.5, 0.5));
+    // Agents without relations stay put.
+    let loner = &universe.agents()[1];
+    assert_eq!(universe.target(loner), loner.position);
+}
+
+#[test]
+fn switching_personalities() {
+    let agent = |x, y, state| Agent {
+        position: Position::new(x, y),
+        state,
+        friends: Vec::new(),
+        enemies: Vec::new(),
+    };
+    let relation = |agent| Relation { agent, weight: 1. };
+    let agents = vec![
+        agent(0., 0., State::Brave),
+        agent(0.5, 0., State::Brave),
+        agent(5., 0., State::Cowardly),
+    ];
+    let edges = vec![
+        (0, Perceptions::Friend, relation(2)),
+        (0, Perceptions::Enemy, relation(1)),
+        (1, Perceptions::Friend, relation(0)),
+        (1, Perceptions::Enemy, relation(2)),
+        (2, Perceptions::Friend, relation(1)),
+        (2, Perceptions::Enemy, relation(0)),
+    ];
+
+    let mut fearful = Universe::from_agents(agents.clone(), 0.1)
+        .set_social_graph(SocialGraph::Edges(edges.clone()))
+        .set_switching_rule(SwitchingRule::FearOfEnemy { radius: 1. });
+    fearful.update();
+    assert_eq!(
+        fearful.states()[1],
+        vec![State::Cowardly, State::Brave, State::Brave]
+    );
+
+    let mut imitating = Universe::from_agents(agents, 0.1)
+        .set_social_graph(SocialGraph::Edges(edges))
+        .set_switching_rule(SwitchingRule::ImitateFriends);
+    imitating.update();
+    assert_eq!(
+        imitating.states()[1],
+        vec![State::Cowardly, State::Brave, State::Brave]
+    );
+
+    // Mixed strategies on a larger population.
+    let mut universe = Universe::new(100, Personality::Mixed(0.5), 0.005)
+        .set_social_graph(SocialGraph::Factions {
+            no_factions: 2,
+            no_friends: 3,
+            no_enemies: 3,
+        })
+        .set_switching_rule(SwitchingRule::FearOfEnemy { radius: 0.2 });
+    universe.run(300);
+    let statistics = universe.statistics(0.02);
+    for statistics in statistics.iter().step_by(30) {
+        println!("{}", statistics);
+    }
+    assert_eq!(statistics.len(), 301);
+    let (first, last) = (&statistics[0], &statistics[300]);
+    assert_eq!(last.time, 300);
+    // The factions gather into clusters.
+    assert!(last.dispersion < first.dispersion / 2.);
+    assert!(last.no_clusters < first.no_clusters);
+    assert!(last.largest_cluster > first.largest_cluster);
+    // Fear makes agents switch personalities.
+    assert!(statistics
+        .iter()
+        .all(|x| (0. ..=1.).contains(&x.brave_fraction)));
+    assert!(statistics
+        .iter()
+        .any(|x| x.brave_fraction != first.brave_fraction));
+}
@@ -0,0 +1,220 @@
+//! Interventions that change an epidemic while it runs: schedules that set a transition rate as
+//! a function of time, and policies that respond to the state of the epidemic, e.g. a lockdown
+//! that starts when the infectious exceed a threshold.
+//!
+//! The engines ask their [`Interventions`] for the rates and the [`Response`] in every update:
+//! [`disease::Population::update_with`], [`SteadyStateSIRModel::update_with`] and the stochastic
+//! [`NetworkEpidemic::step_with`].
+//!
+//! [`disease::Population::update_with`]: crate::disease::Population::update_with
+//! [`SteadyStateSIRModel::update_with`]: crate::steady_state_models::SteadyStateSIRModel::update_with
+//! [`NetworkEpidemic::step_with`]: crate::network_epidemic::NetworkEpidemic::step_with
+use std::collections::HashMap;
+
+type Count = f64;
+type Rate = f64;
+type Time = usize;
+
+/// The rates a schedule can set. Engines ignore the rates they don't have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Transition {
+    /// The infection rate, or the transmission probability on a network.
+    Infection,
+    Recovery,
+    ImmunityDecay,
+    /// Birth and death rate.
+    Demography,
+    DiseaseDeath,
+    Hunting,
+    Vaccination,
+}
+
+/// The value of a rate over time.
+pub enum Schedule {
+    Constant(Rate),
+    /// `(start, value)` pairs ordered by start, where each value holds until the next start.
+    /// Before the first start the rate keeps the model's own value.
+    Piecewise(Vec<(Time, Rate)>),
+    Function(Box<dyn Fn(Time) -> Rate>),
+}
+
+impl Schedule {
+    /// The value at `time`, or `None` where the schedule leaves the rate alone.
+    pub fn at(&self, time: Time) -> Option<Rate> {
+        match self {
+            Schedule::Constant(rate) => Some(*rate),
+            Schedule::Piecewise(pieces) => pieces
+                .iter()
+                .take_while(|(start, _)| *start <= time)
+                .last()
+                .map(|(_, rate)| *rate),
+            Schedule::Function(function) => Some(function(time)),
+        }
+    }
+}
+
+/// Policies triggered by the state of the epidemic.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Policy {
+    /// Multiplies the infection rate by `factor` from when the infectious exceed `start` until
+    /// they fall below `end`.
+    Lockdown {
+        start: Count,
+        end: Count,
+        factor: Rate,
+    },
+    /// Vaccinates `per_step` susceptible individuals in every update from time `from`.
+    Vaccination { per_step: Count, from: Time },
+    /// Each infectious individual is detected and isolated with `probability` in every update.
+    TestAndIsolate { probability: f64 },
+}
+
+/// The state of the epidemic that the policies respond to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Observation {
+    pub time: Time,
+    pub susceptible: Count,
+    pub infectious: Count,
+}
+
+/// What the engine should do in the next update.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Response {
+    /// Multiplies the infection rate.
+    pub infection_factor: Rate,
+    /// Susceptible individuals moved to recovered.
+    pub vaccinations: Count,
+    /// Share of the infectious moved to recovered.
+    pub isolation: f64,
+}
+
+impl Default for Response {
+    fn default() -> Self {
+        Self {
+            infection_factor: 1.,
+            vaccinations: 0.,
+            isolation: 0.,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Interventions {
+    schedules: HashMap<Transition, Schedule>,
+    policies: Vec<Policy>,
+    /// Whether each policy is a lockdown in force.
+    locked_down: Vec<bool>,
+    /// Times at which a lockdown was in force.
+    lockdown_times: Vec<Time>,
+}
+
+impl Interventions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn add_schedule(mut self, transition: Transition, schedule: Schedule) -> Self {
+        self.schedules.insert(transition, schedule);
+        self
+    }
+
+    pub fn add_policy(mut self, policy: Policy) -> Self {
+        self.policies.push(policy);
+        self.locked_down.push(false);
+        self
+    }
+
+    /// The rate of `transition` at `time`, which is `default` unless a schedule sets it.
+    pub fn rate(&self, transition: Transition, default: Rate, time: Time) -> Rate {
+        self.schedules
+            .get(&transition)
+            .and_then(|schedule| schedule.at(time))
+            .unwrap_or(default)
+    }
+
+    /// The times at which a lockdown was in force.
+    pub fn lockdown_times(&self) -> &[Time] {
+        &self.lockdown_times
+    }
+
+    /// The combined response of the policies to `observation`.
+    pub fn respond(&mut self, observation: Observation) -> Response {
+        let mut response = Response::default();
+        let mut locked_down = false;
+        for (policy, active) in self.policies.iter().zip(self.locked_down.iter_mut()) {
+            match *policy {
+                Policy::Lockdown { start, end, factor } => {
+                    if observation.infectious > start {
+                        *active = true;
+                    } else if observation.infectious < end {
+                        *active = false;
+                    }
+                    if *active {
+                        response.infection_factor *= factor;
+                        locked_down = true;
+                    }
+                }
+                Policy::Vaccination { per_step, from } => {
+                    if observation.time >= from {
+                        response.vaccinations += per_step;
+                    }
+                }
+                Policy::TestAndIsolate { probability } => {
+                    response.isolation = 1. - (1. - response.isolation) * (1. - probability);
+                }
+            }
+        }
+        response.vaccinations = response.vaccinations.min(observation.susceptible);
+        if locked_down {
+            self.lockdown_times.push(observation.time);
+        }
+        response
+    }
+}
+
+#[test]
+fn schedules() {
+    let piecewise = Schedule::Piecewise(vec![(10, 0.5), (20, 0.1)]);
+    assert_eq!(piecewise.at(0), None);
+    assert_eq!(piecewise.at(10), Some(0.5));
+    assert_eq!(piecewise.at(25), Some(0.1));
+
+    let interventions = Interventions::new()
+        .add_schedule(Transition::Infection, piecewise)
+        .add_schedule(
+            Transition::Recovery,
+            Schedule::Function(Box::new(|time| 0.1 * time as Rate)),
+        );
+    assert_eq!(interventions.rate(Transition::Infection, 0.3, 5), 0.3);
+    assert_eq!(interventions.rate(Transition::Infection, 0.3, 15), 0.5);
+    assert_eq!(interventions.rate(Transition::Recovery, 0.3, 2), 0.2);
+    assert_eq!(interventions.rate(Transition::Hunting, 0.3, 2), 0.3);
+}
+
+#[test]
+fn lockdown_hysteresis() {
+    let mut interventions = Interventions::new()
+        .add_policy(Policy::Lockdown {
+            start: 100.,
+            end: 20.,
+            factor: 0.25,
+        })
+        .add_policy(Policy::Vaccination {
+            per_step: 10.,
+            from: 3,
+        });
+    let mut respond = |time, infectious| {
+        interventions.respond(Observation {
+            time,
+            susceptible: 5.,
+            infectious,
+        })
+    };
+    assert_eq!(respond(0, 50.), Response::default());
+    assert_eq!(respond(1, 150.).infection_factor, 0.25);
+    assert_eq!(respond(2, 50.).infection_factor, 0.25);
+    let released = respond(3, 10.);
+    assert_eq!(released.infection_factor, 1.);
+    assert_eq!(released.vaccinations, 5.);
+    assert_eq!(interventions.lockdown_times(), &[1, 2]);
+}
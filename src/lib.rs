@@ -1,31 +1,55 @@
-#![feature(option_expect_none)]
-//mod population;
-//mod disease;
+pub mod population;
+pub mod disease;
 
-// mod steady_state_models;
-//mod lotka_volterra_models;
+pub mod steady_state_models;
+pub mod lotka_volterra_models;
+pub mod wolf_sheep;
+pub mod stratified_models;
+pub mod metapopulation;
 
-//mod game_of_life;
-//mod heroes_and_cowards;
+pub mod game_of_life;
+pub mod heroes_and_cowards;
 
-//pub mod simple_forest_fire;
+pub mod simple_forest_fire;
 //pub mod simple_forest_fire_with_wind;
 //
-//mod schelling_segregation;
+pub mod schelling_segregation;
 
-//mod nagel_schreckenberg;
+#[path = "nagel_Schreckenberg.rs"]
+pub mod nagel_schreckenberg;
 //
-//mod boids;
+pub mod boids;
+pub mod vicsek;
 
 //mod boids_thesis;
 
 //mod situation;
 //
-//mod bass_diffusion;
+pub mod bass_diffusion;
+pub mod network;
+pub mod network_epidemic;
 
-//mod spatial_data_structures;
+pub mod spatial_data_structures;
 
-//mod particle_system;
+pub mod particle_system;
 
-//mod bayesian_optimisation;
-//mod bayesian_optimisation_example;
+pub mod bayesian_optimisation;
+#[cfg(test)]
+mod bayesian_optimisation_example;
+pub mod calibration;
+
+pub mod approximate_bayesian_computation;
+
+pub mod maximum_likelihood;
+
+pub mod epidemic_summary;
+
+pub mod equilibrium;
+
+pub mod interventions;
+
+pub mod ode;
+
+pub mod csv;
+pub mod linalg;
+pub mod statistics;
@@ -0,0 +1,52 @@
+//! Dense linear algebra on small `ndarray` matrices, such as Jacobians and Hessians.
+use ndarray::Array2;
+
+type Numeric = f64;
+
+/// Inverse by Gauss–Jordan elimination with partial pivoting, or `None` if `a` is singular.
+pub fn inverse(a: &Array2<Numeric>) -> Option<Array2<Numeric>> {
+    let n = a.nrows();
+    let mut a = a.clone();
+    let mut inverse = Array2::eye(n);
+    for column in 0..n {
+        let pivot = (column..n)
+            .max_by(|&i, &j| {
+                a[[i, column]]
+                    .abs()
+                    .partial_cmp(&a[[j, column]].abs())
+                    .unwrap()
+            })
+            .unwrap();
+        if a[[pivot, column]].abs() < 1e-300 {
+            return None;
+        }
+        for k in 0..n {
+            a.swap([column, k], [pivot, k]);
+            inverse.swap([column, k], [pivot, k]);
+        }
+        let scale = a[[column, column]];
+        a.row_mut(column).mapv_inplace(|x| x / scale);
+        inverse.row_mut(column).mapv_inplace(|x| x / scale);
+        for row in 0..n {
+            if row != column {
+                let factor = a[[row, column]];
+                let pivot_row = a.row(column).to_owned();
+                let pivot_inverse_row = inverse.row(column).to_owned();
+                a.row_mut(row).scaled_add(-factor, &pivot_row);
+                inverse.row_mut(row).scaled_add(-factor, &pivot_inverse_row);
+            }
+        }
+    }
+    Some(inverse)
+}
+
+#[test]
+fn inverting_matrices() {
+    let a = ndarray::arr2(&[[4., 1.], [2., 3.]]);
+    let identity = a.dot(&inverse(&a).unwrap());
+    assert!((identity - Array2::<Numeric>::eye(2))
+        .iter()
+        .all(|x| x.abs() < 1e-12));
+    let singular = ndarray::arr2(&[[1., 2.], [2., 4.]]);
+    assert_eq!(inverse(&singular), None);
+}
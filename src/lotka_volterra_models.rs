@@ -4,32 +4,83 @@
 //!
 //!
 //! Source [lectures](http://prac.im.pwr.wroc.pl/~szwabin/assets/abm/lec/2.pdf)
+//!
+//! [`Model`] is the classical prey–predator pair and [`GeneralisedModel`] has any number of
+//! species, with logistic growth and Holling functional responses. Both are integrated with the
+//! adaptive Runge–Kutta method of [`ode::integrate`](crate::ode::integrate).
+use crate::equilibrium::Equilibrium;
+use crate::linalg::inverse;
+use crate::ode::integrate;
+use ndarray::{arr1, Array1, Array2, ArrayView1};
 
 type Numeric = f64;
 
-struct Population {
-    x: Numeric,
-    y: Numeric,
+/// Relative error allowed in each step of the integration.
+const TOLERANCE: Numeric = 1e-9;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Population {
+    pub x: Numeric,
+    pub y: Numeric,
 }
 
 /// `x` is prey
 /// `y` is predators
 /// `t` is time
 /// `alpha`, `beta`, `gamma`, and `delta` are species specific parameters.
-struct Parameters {
-    alpha: Numeric,
-    beta: Numeric,
-    gamma: Numeric,
-    delta: Numeric,
+#[derive(Debug, Clone, Copy)]
+pub struct Parameters {
+    pub alpha: Numeric,
+    pub beta: Numeric,
+    pub gamma: Numeric,
+    pub delta: Numeric,
 }
 
-struct Model {
+pub struct Model {
     population: Population,
     parameters: Parameters,
 }
 
 impl Model {
-    fn update(&mut self) {
+    pub fn new(population: Population, parameters: Parameters) -> Self {
+        Self {
+            population,
+            parameters,
+        }
+    }
+
+    pub fn population(&self) -> Population {
+        self.population
+    }
+
+    /// Rate of change of `[x, y]`.
+    pub fn rate_of_change(&self, state: ArrayView1<Numeric>) -> Array1<Numeric> {
+        let Parameters {
+            alpha,
+            beta,
+            gamma,
+            delta,
+        } = self.parameters;
+        let (x, y) = (state[0], state[1]);
+        arr1(&[alpha * x - beta * x * y, delta * x * y - gamma * y])
+    }
+
+    /// The equilibrium where both species are extinct.
+    pub fn extinction_equilibrium(&self) -> Equilibrium {
+        Equilibrium::at(|x| self.rate_of_change(x), arr1(&[0., 0.]))
+    }
+
+    /// The equilibrium where prey and predators coexist, found by Newton iteration from the
+    /// current population.
+    pub fn coexistence_equilibrium(&self) -> Option<Equilibrium> {
+        let Population { x, y } = self.population;
+        Equilibrium::find(|x| self.rate_of_change(x), arr1(&[x, y]).view())
+            .filter(|equilibrium| equilibrium.state().iter().all(|&n| n > 0.))
+    }
+
+    /// The quantity `delta x - gamma ln x + beta y - alpha ln y`, which is constant along
+    /// trajectories, so its drift measures the error of the integration.
+    pub fn conserved_quantity(&self) -> Numeric {
         let Parameters {
             alpha,
             beta,
@@ -37,8 +88,359 @@ impl Model {
             delta,
         } = self.parameters;
         let Population { x, y } = self.population;
+        delta * x - gamma * x.ln() + beta * y - alpha * y.ln()
+    }
+
+    /// Advances the population by one unit of time.
+    pub fn update(&mut self) {
+        self.run(1);
+    }
+
+    /// The population now and after each of `steps` units of time.
+    pub fn run(&mut self, steps: usize) -> Vec<Population> {
+        let Population { x, y } = self.population;
+        let times: Vec<_> = (0..=steps).map(|t| t as Numeric).collect();
+        let trajectory = integrate(
+            |state| self.rate_of_change(state),
+            arr1(&[x, y]).view(),
+            &times,
+            TOLERANCE,
+        );
+        let populations: Vec<_> = trajectory
+            .genrows()
+            .into_iter()
+            .map(|row| Population {
+                x: row[0],
+                y: row[1],
+            })
+            .collect();
+        self.population = *populations.last().unwrap();
+        populations
+    }
+}
+
+/// How the consumption of a predator depends on the abundance `x` of its prey.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FunctionalResponse {
+    /// Consumption proportional to `x`, as in the classical model.
+    HollingI,
+    /// Consumption `x / (1 + handling x)`, which saturates as predators spend their time
+    /// handling prey.
+    HollingII { handling: Numeric },
+    /// Sigmoidal consumption `x^2 / (1 + handling x^2)`, as when predators switch to prey once
+    /// it is abundant.
+    HollingIII { handling: Numeric },
+}
+
+impl FunctionalResponse {
+    /// Consumption per unit of prey.
+    fn per_prey(&self, prey: Numeric) -> Numeric {
+        match *self {
+            FunctionalResponse::HollingI => 1.,
+            FunctionalResponse::HollingII { handling } => 1. / (1. + handling * prey),
+            FunctionalResponse::HollingIII { handling } => prey / (1. + handling * prey * prey),
+        }
+    }
+}
+
+/// The generalised Lotka–Volterra model of `n` species,
+///
+/// `dx_i/dt = x_i (r_i (1 - x_i / K_i) + A_ii x_i + Σ_{j≠i} A_ij x_j g(x_r))`,
+///
+/// where `A_ij` is the per capita effect of species `j` on species `i` and `g` is the
+/// functional response per unit of prey, evaluated at the prey `r`: species `j` if it is eaten
+/// by `i`, i.e. `A_ij > 0`, and species `i` otherwise. Species without a carrying capacity grow
+/// exponentially.
+#[derive(Debug, Clone)]
+pub struct GeneralisedModel {
+    populations: Array1<Numeric>,
+    growth_rates: Array1<Numeric>,
+    carrying_capacities: Vec<Option<Numeric>>,
+    interactions: Array2<Numeric>,
+    functional_response: FunctionalResponse,
+    time: Numeric,
+}
+
+impl GeneralisedModel {
+    pub fn new(
+        populations: Array1<Numeric>,
+        growth_rates: Array1<Numeric>,
+        interactions: Array2<Numeric>,
+    ) -> Self {
+        let n = populations.len();
+        assert_eq!(
+            growth_rates.len(),
+            n,
+            "a growth rate is needed for every species"
+        );
+        assert_eq!(
+            interactions.dim(),
+            (n, n),
+            "the interaction matrix must have a row and a column per species"
+        );
+        Self {
+            populations,
+            growth_rates,
+            carrying_capacities: vec![None; n],
+            interactions,
+            functional_response: FunctionalResponse::HollingI,
+            time: 0.,
+        }
+    }
+
+    /// Makes the growth of `species` logistic.
+    pub fn set_carrying_capacity(mut self, species: usize, carrying_capacity: Numeric) -> Self {
+        self.carrying_capacities[species] = Some(carrying_capacity);
+        self
+    }
+
+    pub fn set_functional_response(mut self, functional_response: FunctionalResponse) -> Self {
+        self.functional_response = functional_response;
+        self
+    }
+
+    pub fn populations(&self) -> &Array1<Numeric> {
+        &self.populations
+    }
+
+    pub fn time(&self) -> Numeric {
+        self.time
+    }
 
-        self.population.x += alpha * x - beta * x * y;
-        self.population.y += delta * x * y - gamma * y;
+    pub fn rate_of_change(&self, state: ArrayView1<Numeric>) -> Array1<Numeric> {
+        let n = state.len();
+        (0..n)
+            .map(|i| {
+                let growth = match self.carrying_capacities[i] {
+                    Some(capacity) => self.growth_rates[i] * (1. - state[i] / capacity),
+                    None => self.growth_rates[i],
+                };
+                let interactions: Numeric = (0..n)
+                    .map(|j| {
+                        let a = self.interactions[[i, j]];
+                        if i == j {
+                            a * state[j]
+                        } else {
+                            let prey = if a > 0. { state[j] } else { state[i] };
+                            a * state[j] * self.functional_response.per_prey(prey)
+                        }
+                    })
+                    .sum();
+                state[i] * (growth + interactions)
+            })
+            .collect()
+    }
+
+    /// The populations now and at `steps` equally spaced times over the next `duration`, one row
+    /// per time.
+    pub fn run(&mut self, duration: Numeric, steps: usize) -> Array2<Numeric> {
+        assert!(steps > 0, "at least one step is needed");
+        let times: Vec<_> = (0..=steps)
+            .map(|k| self.time + duration * k as Numeric / steps as Numeric)
+            .collect();
+        let trajectory = integrate(
+            |state| self.rate_of_change(state),
+            self.populations.view(),
+            &times,
+            TOLERANCE,
+        );
+        self.populations = trajectory.row(steps).to_owned();
+        self.time += duration;
+        trajectory
+    }
+
+    /// The equilibrium that Newton iteration reaches from `initial`.
+    pub fn equilibrium(&self, initial: ArrayView1<Numeric>) -> Option<Equilibrium> {
+        Equilibrium::find(|state| self.rate_of_change(state), initial)
+    }
+
+    /// Weights `w` such that `W A` is skew-symmetric, if they exist and are positive.
+    fn skew_weights(&self) -> Option<Array1<Numeric>> {
+        let n = self.populations.len();
+        let a = &self.interactions;
+        let mut weights: Vec<Option<Numeric>> = vec![None; n];
+        for root in 0..n {
+            if weights[root].is_some() {
+                continue;
+            }
+            weights[root] = Some(1.);
+            let mut queue = vec![root];
+            while let Some(i) = queue.pop() {
+                let w_i = weights[i].unwrap();
+                for j in 0..n {
+                    if i == j || (a[[i, j]] == 0. && a[[j, i]] == 0.) {
+                        continue;
+                    }
+                    if a[[j, i]] == 0. || a[[i, j]] == 0. {
+                        return None;
+                    }
+                    let w_j = -w_i * a[[i, j]] / a[[j, i]];
+                    match weights[j] {
+                        Some(w) if (w - w_j).abs() > 1e-12 * w.abs() => return None,
+                        Some(_) => {}
+                        None => {
+                            weights[j] = Some(w_j);
+                            queue.push(j);
+                        }
+                    }
+                }
+            }
+        }
+        let weights: Array1<_> = weights.into_iter().map(Option::unwrap).collect();
+        if weights.iter().all(|&w| w > 0.) {
+            Some(weights)
+        } else {
+            None
+        }
+    }
+
+    /// The Volterra function `Σ w_i (x_i - x*_i ln x_i)` at the current populations, where `x*`
+    /// is the coexistence equilibrium. It is conserved in the classical case: a linear functional
+    /// response, no carrying capacities or self-interaction, and interactions that some positive
+    /// weights make skew-symmetric, e.g. a prey–predator pair. Otherwise `None`.
+    pub fn conserved_quantity(&self) -> Option<Numeric> {
+        let classical = self.functional_response == FunctionalResponse::HollingI
+            && self.carrying_capacities.iter().all(Option::is_none)
+            && self.interactions.diag().iter().all(|&a| a == 0.);
+        if !classical {
+            return None;
+        }
+        let weights = self.skew_weights()?;
+        let equilibrium = -inverse(&self.interactions)?.dot(&self.growth_rates);
+        Some(
+            weights
+                .iter()
+                .zip(&equilibrium)
+                .zip(&self.populations)
+                .map(|((w, e), x)| w * (x - e * x.ln()))
+                .sum(),
+        )
+    }
+}
+
+/// The classical model as a two-species generalised model, with the prey first.
+impl From<&Model> for GeneralisedModel {
+    fn from(model: &Model) -> Self {
+        let Parameters {
+            alpha,
+            beta,
+            gamma,
+            delta,
+        } = model.parameters;
+        let Population { x, y } = model.population;
+        GeneralisedModel::new(
+            arr1(&[x, y]),
+            arr1(&[alpha, -gamma]),
+            ndarray::arr2(&[[0., -beta], [delta, 0.]]),
+        )
     }
 }
+
+#[test]
+fn coexistence_is_a_centre() {
+    use crate::equilibrium::Stability;
+
+    let parameters = Parameters {
+        alpha: 0.1,
+        beta: 0.02,
+        gamma: 0.4,
+        delta: 0.02,
+    };
+    let model = Model::new(Population { x: 15., y: 4. }, parameters);
+
+    let coexistence = model.coexistence_equilibrium().unwrap();
+    println!("coexistence {}", coexistence);
+    assert!((coexistence.state()[0] - 20.).abs() < 1e-6);
+    assert!((coexistence.state()[1] - 5.).abs() < 1e-6);
+    // the eigenvalues are ±i sqrt(alpha gamma)
+    assert_eq!(coexistence.stability(), Stability::NonHyperbolic);
+    assert!((coexistence.eigenvalues()[0].im.abs() - 0.2).abs() < 1e-6);
+
+    assert_eq!(
+        model.extinction_equilibrium().stability(),
+        Stability::Saddle
+    );
+}
+
+#[test]
+fn classical_cycles_conserve_the_first_integral() {
+    let parameters = Parameters {
+        alpha: 1.,
+        beta: 0.1,
+        gamma: 1.5,
+        delta: 0.075,
+    };
+    let mut model = Model::new(Population { x: 10., y: 5. }, parameters);
+    let initial = model.conserved_quantity();
+    let mut generalised = GeneralisedModel::from(&model);
+    let generalised_initial = generalised.conserved_quantity().unwrap();
+
+    let trajectory = model.run(200);
+    assert!(trajectory.iter().all(|p| p.x > 0. && p.y > 0.));
+    assert!((model.conserved_quantity() - initial).abs() < 1e-6 * initial.abs());
+    // the prey returns to its initial abundance over the cycles
+    assert!(trajectory.iter().skip(1).any(|p| (p.x - 10.).abs() < 1.));
+
+    generalised.run(200., 200);
+    let drift = generalised.conserved_quantity().unwrap() - generalised_initial;
+    assert!(drift.abs() < 1e-6 * generalised_initial.abs());
+    assert!((generalised.populations()[0] - model.population().x).abs() < 1e-4);
+    assert!(GeneralisedModel::from(&model)
+        .set_carrying_capacity(0, 100.)
+        .conserved_quantity()
+        .is_none());
+}
+
+#[test]
+fn paradox_of_enrichment() {
+    use crate::equilibrium::Stability;
+
+    // with a saturating response the prey-predator equilibrium at x = 2/3 loses its stability
+    // once the carrying capacity exceeds 1 / handling + 2 x = 7/3
+    let model = |carrying_capacity| {
+        GeneralisedModel::new(
+            arr1(&[1., 0.5]),
+            arr1(&[1., -0.2]),
+            ndarray::arr2(&[[0., -1.], [0.5, 0.]]),
+        )
+        .set_carrying_capacity(0, carrying_capacity)
+        .set_functional_response(FunctionalResponse::HollingII { handling: 1. })
+    };
+    let late_range = |mut model: GeneralisedModel| {
+        let trajectory = model.run(400., 400);
+        let late = trajectory.slice(ndarray::s![300.., 0]);
+        let max = late.fold(Numeric::MIN, |m, &x| m.max(x));
+        let min = late.fold(Numeric::MAX, |m, &x| m.min(x));
+        max - min
+    };
+
+    let poor = model(2.);
+    let equilibrium = poor.equilibrium(arr1(&[0.7, 0.5]).view()).unwrap();
+    assert!((equilibrium.state()[0] - 2. / 3.).abs() < 1e-8);
+    assert_eq!(equilibrium.stability(), Stability::StableFocus);
+    assert!(late_range(poor) < 1e-3);
+
+    let rich = model(6.);
+    let equilibrium = rich.equilibrium(arr1(&[0.7, 1.]).view()).unwrap();
+    assert_eq!(equilibrium.stability(), Stability::UnstableFocus);
+    assert!(late_range(rich) > 1.);
+
+    // a sigmoidal response keeps the populations positive as well
+    let mut sigmoidal =
+        model(6.).set_functional_response(FunctionalResponse::HollingIII { handling: 1. });
+    let trajectory = sigmoidal.run(200., 200);
+    assert!(trajectory.iter().all(|&x| x > 0.));
+}
+
+#[test]
+#[should_panic(expected = "at least one step")]
+fn running_without_steps() {
+    let population = Population { x: 10., y: 5. };
+    let parameters = Parameters {
+        alpha: 0.1,
+        beta: 0.02,
+        gamma: 0.4,
+        delta: 0.02,
+    };
+    GeneralisedModel::from(&Model::new(population, parameters)).run(1., 0);
+}
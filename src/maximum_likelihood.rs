@@ -0,0 +1,545 @@
+//! Maximum-likelihood estimation of the parameters of the SIR model of
+//! [`create_sir_population`] from incidence data, i.e. the number of new cases at each time
+//! step. The likelihood is minimised with the Nelder–Mead simplex method, on the logarithm of the
+//! parameters so they stay positive. Confidence intervals come from either the Hessian of the
+//! negative log-likelihood or the profile likelihood.
+use crate::disease::{create_sir_population, Population};
+use crate::linalg::inverse;
+use crate::statistics::normal_cdf;
+use ndarray::{Array1, Array2, ArrayView1};
+use std::fmt::{Display, Error, Formatter};
+
+type Numeric = f64;
+
+/// Lanczos approximation, with `g = 7` and 9 coefficients.
+fn ln_gamma(x: Numeric) -> Numeric {
+    const COEFFICIENTS: [Numeric; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        // Reflection formula
+        (std::f64::consts::PI / (std::f64::consts::PI * x).sin()).ln() - ln_gamma(1. - x)
+    } else {
+        let x = x - 1.;
+        let t = x + 7.5;
+        let series = COEFFICIENTS[1..]
+            .iter()
+            .enumerate()
+            .fold(COEFFICIENTS[0], |acc, (i, c)| {
+                acc + c / (x + i as Numeric + 1.)
+            });
+        0.5 * (2. * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + series.ln()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Likelihood {
+    /// Gaussian errors with the variance profiled out, which amounts to least squares.
+    LeastSquares,
+    Poisson,
+    /// Variance `μ + μ² / dispersion` for mean `μ`.
+    NegativeBinomial {
+        dispersion: Numeric,
+    },
+}
+
+impl Likelihood {
+    pub fn negative_log_likelihood(&self, observed: &[Numeric], expected: &[Numeric]) -> Numeric {
+        assert_eq!(observed.len(), expected.len());
+        let pairs = observed.iter().zip(expected);
+        match *self {
+            Likelihood::LeastSquares => {
+                let n = observed.len() as Numeric;
+                let residual_sum_of_squares =
+                    pairs.map(|(c, mu)| (c - mu).powi(2)).sum::<Numeric>();
+                0.5 * n * (residual_sum_of_squares / n).ln()
+            }
+            Likelihood::Poisson => pairs
+                .map(|(c, mu)| mu - c * mu.ln() + ln_gamma(c + 1.))
+                .sum(),
+            Likelihood::NegativeBinomial { dispersion: k } => pairs
+                .map(|(c, mu)| {
+                    -(ln_gamma(c + k) - ln_gamma(k) - ln_gamma(c + 1.)
+                        + k * (k / (k + mu)).ln()
+                        + c * (mu / (k + mu)).ln())
+                })
+                .sum(),
+        }
+    }
+}
+
+/// Minimises `f` with the Nelder–Mead simplex method, from a simplex with edges of length `step`
+/// at `initial`. Stops when the values at the vertices differ by at most `tolerance`, or after
+/// `max_iterations`. Returns the best vertex and its value.
+pub fn nelder_mead<F>(
+    f: F,
+    initial: Array1<Numeric>,
+    step: Numeric,
+    tolerance: Numeric,
+    max_iterations: usize,
+) -> (Array1<Numeric>, Numeric)
+where
+    F: Fn(ArrayView1<Numeric>) -> Numeric,
+{
+    let evaluate = |x: Array1<Numeric>| {
+        let y = f(x.view());
+        (x, if y.is_nan() { Numeric::INFINITY } else { y })
+    };
+    let n = initial.len();
+    let mut simplex: Vec<_> = (0..=n)
+        .map(|k| {
+            let mut vertex = initial.clone();
+            if k > 0 {
+                vertex[k - 1] += step;
+            }
+            evaluate(vertex)
+        })
+        .collect();
+
+    for _iteration in 0..max_iterations {
+        simplex.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+        if simplex[n].1 - simplex[0].1 <= tolerance {
+            break;
+        }
+        let centroid = simplex[..n]
+            .iter()
+            .fold(Array1::zeros(n), |acc, (x, _)| acc + x)
+            / n as Numeric;
+        let worst = simplex[n].clone();
+        let reflected = evaluate(&centroid * 2. - &worst.0);
+        if reflected.1 < simplex[0].1 {
+            let expanded = evaluate(&centroid * 3. - &worst.0 * 2.);
+            simplex[n] = if expanded.1 < reflected.1 {
+                expanded
+            } else {
+                reflected
+            };
+        } else if reflected.1 < simplex[n - 1].1 {
+            simplex[n] = reflected;
+        } else {
+            let contracted = if reflected.1 < worst.1 {
+                evaluate((&centroid + &reflected.0) * 0.5)
+            } else {
+                evaluate((&centroid + &worst.0) * 0.5)
+            };
+            if contracted.1 < reflected.1.min(worst.1) {
+                simplex[n] = contracted;
+            } else {
+                // Shrink towards the best vertex
+                let best = simplex[0].0.clone();
+                for vertex in simplex.iter_mut().skip(1) {
+                    *vertex = evaluate((&best + &vertex.0) * 0.5);
+                }
+            }
+        }
+    }
+    simplex.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+    simplex.swap_remove(0)
+}
+
+/// The `z` for which a standard normal lies within `[-z, z]` with probability `confidence`.
+fn critical_value(confidence: Numeric) -> Numeric {
+    assert!(confidence > 0. && confidence < 1.);
+    let target = 0.5 + 0.5 * confidence;
+    let (mut low, mut high) = (0., 10.);
+    for _ in 0..60 {
+        let middle = 0.5 * (low + high);
+        if normal_cdf(middle) < target {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    0.5 * (low + high)
+}
+
+/// Incidence of the SIR model over `steps` updates, starting from `initial_infected` in a
+/// population of `population_size`.
+pub fn sir_incidence(
+    population_size: Numeric,
+    initial_infected: Numeric,
+    infection_rate: Numeric,
+    recovery_rate: Numeric,
+    steps: usize,
+) -> Vec<Numeric> {
+    let mut population = create_sir_population(
+        population_size - initial_infected,
+        initial_infected,
+        infection_rate,
+        recovery_rate,
+    );
+    (0..steps)
+        .map(|_| {
+            let incidence = population.incidence();
+            population.update_disease_state();
+            incidence
+        })
+        .collect()
+}
+
+const PARAMETER_NAMES: [&str; 3] = ["infection rate", "recovery rate", "initial infected"];
+
+/// Estimation of `[infection_rate, recovery_rate, initial_infected]` from the `incidence` in a
+/// population of known size.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SirEstimation {
+    incidence: Vec<Numeric>,
+    population_size: Numeric,
+    likelihood: Likelihood,
+    initial_guess: Array1<Numeric>,
+    max_iterations: usize,
+}
+
+impl SirEstimation {
+    /// The initial guess is a recovery rate of 0.2 and a reproduction number of 2.
+    pub fn new(incidence: Vec<Numeric>, population_size: Numeric) -> Self {
+        assert!(!incidence.is_empty(), "there is no incidence data");
+        let recovery_rate = 0.2;
+        let initial_infected = incidence[0].max(1.).min(0.5 * population_size);
+        Self {
+            incidence,
+            population_size,
+            likelihood: Likelihood::Poisson,
+            initial_guess: ndarray::arr1(&[
+                2. * recovery_rate / population_size,
+                recovery_rate,
+                initial_infected,
+            ]),
+            max_iterations: 2000,
+        }
+    }
+
+    pub fn set_likelihood(mut self, likelihood: Likelihood) -> Self {
+        self.likelihood = likelihood;
+        self
+    }
+
+    pub fn set_initial_guess(
+        mut self,
+        infection_rate: Numeric,
+        recovery_rate: Numeric,
+        initial_infected: Numeric,
+    ) -> Self {
+        self.initial_guess = ndarray::arr1(&[infection_rate, recovery_rate, initial_infected]);
+        self
+    }
+
+    pub fn set_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    /// Negative log-likelihood at the logarithm of the parameters.
+    fn negative_log_likelihood(&self, log_parameters: ArrayView1<Numeric>) -> Numeric {
+        let parameters = log_parameters.mapv(Numeric::exp);
+        if parameters[2] >= self.population_size {
+            return Numeric::INFINITY;
+        }
+        let expected: Vec<_> = sir_incidence(
+            self.population_size,
+            parameters[2],
+            parameters[0],
+            parameters[1],
+            self.incidence.len(),
+        )
+        .into_iter()
+        .map(|mu| mu.max(1e-10))
+        .collect();
+        self.likelihood
+            .negative_log_likelihood(&self.incidence, &expected)
+    }
+
+    /// Minimises the negative log-likelihood, restarting the simplex at the optimum until it
+    /// stops improving.
+    fn minimise<F>(&self, f: F, initial: Array1<Numeric>) -> (Array1<Numeric>, Numeric)
+    where
+        F: Fn(ArrayView1<Numeric>) -> Numeric,
+    {
+        let mut best = nelder_mead(&f, initial, 0.5, 1e-10, self.max_iterations);
+        for _restart in 0..5 {
+            let next = nelder_mead(&f, best.0.clone(), 0.1, 1e-10, self.max_iterations);
+            let improved = best.1 - next.1 > 1e-9;
+            best = next;
+            if !improved {
+                break;
+            }
+        }
+        best
+    }
+
+    pub fn fit(&self) -> SirFit {
+        let (log_parameters, negative_log_likelihood) = self.minimise(
+            |x| self.negative_log_likelihood(x),
+            self.initial_guess.mapv(Numeric::ln),
+        );
+        SirFit {
+            estimation: self.clone(),
+            log_parameters,
+            negative_log_likelihood,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SirFit {
+    estimation: SirEstimation,
+    log_parameters: Array1<Numeric>,
+    negative_log_likelihood: Numeric,
+}
+
+impl SirFit {
+    /// `[infection_rate, recovery_rate, initial_infected]`
+    pub fn parameters(&self) -> Array1<Numeric> {
+        self.log_parameters.mapv(Numeric::exp)
+    }
+
+    pub fn infection_rate(&self) -> Numeric {
+        self.log_parameters[0].exp()
+    }
+
+    pub fn recovery_rate(&self) -> Numeric {
+        self.log_parameters[1].exp()
+    }
+
+    pub fn initial_infected(&self) -> Numeric {
+        self.log_parameters[2].exp()
+    }
+
+    pub fn negative_log_likelihood(&self) -> Numeric {
+        self.negative_log_likelihood
+    }
+
+    /// Akaike information criterion
+    pub fn aic(&self) -> Numeric {
+        2. * self.negative_log_likelihood + 2. * self.log_parameters.len() as Numeric
+    }
+
+    /// The fitted model at the first time step.
+    pub fn population(&self) -> Population {
+        let initial_infected = self.initial_infected();
+        create_sir_population(
+            self.estimation.population_size - initial_infected,
+            initial_infected,
+            self.infection_rate(),
+            self.recovery_rate(),
+        )
+    }
+
+    pub fn expected_incidence(&self) -> Vec<Numeric> {
+        sir_incidence(
+            self.estimation.population_size,
+            self.initial_infected(),
+            self.infection_rate(),
+            self.recovery_rate(),
+            self.estimation.incidence.len(),
+        )
+    }
+
+    /// Hessian of the negative log-likelihood in the logarithm of the parameters, by central
+    /// differences.
+    pub fn hessian(&self) -> Array2<Numeric> {
+        let h = 1e-4;
+        let n = self.log_parameters.len();
+        let f = |di: (usize, Numeric), dj: (usize, Numeric)| {
+            let mut x = self.log_parameters.clone();
+            x[di.0] += di.1;
+            x[dj.0] += dj.1;
+            self.estimation.negative_log_likelihood(x.view())
+        };
+        Array2::from_shape_fn((n, n), |(i, j)| {
+            (f((i, h), (j, h)) - f((i, h), (j, -h)) - f((i, -h), (j, h)) + f((i, -h), (j, -h)))
+                / (4. * h * h)
+        })
+    }
+
+    /// Wald intervals from the inverse of the [`SirFit::hessian`], on the logarithmic scale and
+    /// transformed back, for each of the parameters. `None` if the Hessian is singular or not
+    /// positive definite, e.g. where the likelihood is flat in a parameter.
+    pub fn hessian_intervals(&self, confidence: Numeric) -> Option<Vec<(Numeric, Numeric)>> {
+        let z = critical_value(confidence);
+        let covariance = inverse(&self.hessian())?;
+        self.log_parameters
+            .iter()
+            .zip(covariance.diag())
+            .map(|(x, &variance)| {
+                if variance > 0. && variance.is_finite() {
+                    let half_width = z * variance.sqrt();
+                    Some(((x - half_width).exp(), (x + half_width).exp()))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Smallest negative log-likelihood with `parameter` fixed at the logarithm `value`.
+    fn profile(&self, parameter: usize, value: Numeric) -> Numeric {
+        let full = |others: ArrayView1<Numeric>| {
+            let mut others = others.iter();
+            (0..self.log_parameters.len())
+                .map(|k| {
+                    if k == parameter {
+                        value
+                    } else {
+                        *others.next().unwrap()
+                    }
+                })
+                .collect::<Array1<Numeric>>()
+        };
+        let initial = self
+            .log_parameters
+            .iter()
+            .enumerate()
+            .filter(|(k, _)| *k != parameter)
+            .map(|(_, x)| *x)
+            .collect();
+        self.estimation
+            .minimise(
+                |others| self.estimation.negative_log_likelihood(full(others).view()),
+                initial,
+            )
+            .1
+    }
+
+    /// Interval of `parameter`, an index into [`SirFit::parameters`], where the profile
+    /// likelihood ratio test does not reject at the `confidence` level.
+    pub fn profile_interval(&self, parameter: usize, confidence: Numeric) -> (Numeric, Numeric) {
+        let threshold = self.negative_log_likelihood + 0.5 * critical_value(confidence).powi(2);
+        let estimate = self.log_parameters[parameter];
+        let bound = |direction: Numeric| {
+            let mut inner = estimate;
+            let mut step = 0.05;
+            let mut outer = estimate + direction * step;
+            while self.profile(parameter, outer) < threshold {
+                inner = outer;
+                step *= 2.;
+                outer = estimate + direction * step;
+                if step > 20. {
+                    return outer.exp();
+                }
+            }
+            for _ in 0..30 {
+                let middle = 0.5 * (inner + outer);
+                if self.profile(parameter, middle) < threshold {
+                    inner = middle;
+                } else {
+                    outer = middle;
+                }
+            }
+            (0.5 * (inner + outer)).exp()
+        };
+        (bound(-1.), bound(1.))
+    }
+}
+
+impl Display for SirFit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        let intervals = self.hessian_intervals(0.95);
+        for (k, (name, estimate)) in PARAMETER_NAMES.iter().zip(&self.parameters()).enumerate() {
+            write!(f, "{:<17} {:>12.6e}", name, estimate)?;
+            match &intervals {
+                Some(intervals) => {
+                    let (low, high) = intervals[k];
+                    writeln!(f, " [{:.6e}, {:.6e}]", low, high)?
+                }
+                None => writeln!(f)?,
+            }
+        }
+        write!(
+            f,
+            "-log L = {:.4}, AIC = {:.4}",
+            self.negative_log_likelihood,
+            self.aic()
+        )
+    }
+}
+
+#[test]
+fn minimising_the_rosenbrock_function() {
+    let rosenbrock =
+        |x: ArrayView1<Numeric>| (1. - x[0]).powi(2) + 100. * (x[1] - x[0] * x[0]).powi(2);
+    let (minimum, value) = nelder_mead(rosenbrock, ndarray::arr1(&[-1.2, 1.]), 0.5, 1e-14, 5000);
+    assert!((minimum[0] - 1.).abs() < 1e-4 && (minimum[1] - 1.).abs() < 1e-4);
+    assert!(value < 1e-8);
+}
+
+#[test]
+fn likelihoods() {
+    assert!((ln_gamma(5.) - 24f64.ln()).abs() < 1e-10);
+    assert!((ln_gamma(0.5) - std::f64::consts::PI.sqrt().ln()).abs() < 1e-10);
+    let observed = [0., 3., 10.];
+    let expected = [0.5, 2., 12.];
+    let poisson = Likelihood::Poisson.negative_log_likelihood(&observed, &expected);
+    // Without overdispersion the negative binomial is the Poisson distribution.
+    let negative_binomial = Likelihood::NegativeBinomial { dispersion: 1e8 }
+        .negative_log_likelihood(&observed, &expected);
+    assert!((poisson - negative_binomial).abs() < 1e-5);
+    assert!((critical_value(0.95) - 1.959_964).abs() < 1e-5);
+}
+
+#[test]
+fn recovering_exact_parameters() {
+    let incidence = sir_incidence(1000., 5., 0.0004, 0.2, 60);
+    let fit = SirEstimation::new(incidence, 1000.).fit();
+    println!("{}", fit);
+    for (estimate, truth) in fit.parameters().iter().zip(&[0.0004, 0.2, 5.]) {
+        assert!((estimate / truth - 1.).abs() < 1e-3);
+    }
+}
+
+#[test]
+fn confidence_intervals_from_noisy_incidence() {
+    use rand_distr::{Distribution, Poisson};
+
+    let truth = [0.0004, 0.2, 5.];
+    let mut rng = rand::thread_rng();
+    let incidence: Vec<Numeric> = sir_incidence(1000., truth[2], truth[0], truth[1], 60)
+        .into_iter()
+        .map(|mu| {
+            if mu > 0. {
+                let cases: u64 = Poisson::new(mu).unwrap().sample(&mut rng);
+                cases as Numeric
+            } else {
+                0.
+            }
+        })
+        .collect();
+
+    let fit = SirEstimation::new(incidence.clone(), 1000.).fit();
+    println!("{}", fit);
+    let intervals = fit.hessian_intervals(0.999).unwrap();
+    for ((low, high), truth) in intervals.iter().zip(&truth) {
+        assert!(low < truth && truth < high);
+    }
+    let (low, high) = fit.profile_interval(0, 0.999);
+    println!(
+        "profile interval of the infection rate [{:.6e}, {:.6e}]",
+        low, high
+    );
+    assert!(low < truth[0] && truth[0] < high);
+    assert!((low / intervals[0].0 - 1.).abs() < 0.2 && (high / intervals[0].1 - 1.).abs() < 0.2);
+
+    let overdispersed = SirEstimation::new(incidence, 1000.)
+        .set_likelihood(Likelihood::NegativeBinomial { dispersion: 2. })
+        .fit();
+    let wider = overdispersed.hessian_intervals(0.999).unwrap();
+    assert!(wider[0].1 - wider[0].0 > intervals[0].1 - intervals[0].0);
+}
+
+#[test]
+fn printing_a_fit_without_intervals() {
+    // A single observation can't identify three parameters, so the Hessian is singular.
+    let fit = SirEstimation::new(vec![3.], 1000.).fit();
+    assert_eq!(fit.hessian_intervals(0.95), None);
+    let printed = fit.to_string();
+    println!("{}", printed);
+    assert!(printed.contains("-log L") && !printed.contains('['));
+}
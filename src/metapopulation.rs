@@ -0,0 +1,333 @@
+//! Spatial spread of an epidemic between patches, e.g. towns, each of which runs a compartment
+//! model of [`disease::Population`]. After every local update a share of each compartment moves
+//! between patches according to the mobility matrix, where `M_ij` is the share of the population
+//! of patch `i` that moves to patch `j` in one time step.
+//!
+//! The mobility matrix is read from CSV in the format of
+//! [`parse_contact_matrix`](crate::stratified_models::parse_contact_matrix).
+//!
+//! [`disease::Population`]: crate::disease::Population
+use crate::disease::{DiseaseStates, Population};
+use crate::stratified_models::parse_contact_matrix;
+use ndarray::{Array1, Array2};
+use rand::Rng;
+use rand_distr::Binomial;
+use std::fmt::{Display, Error, Formatter};
+use std::path::Path;
+
+type Count = f64;
+type Rate = f64;
+
+/// How individuals move between patches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Coupling {
+    /// The expected share of every compartment moves.
+    Deterministic,
+    /// The whole individuals of every compartment move independently, so the number of movers
+    /// out of a patch is multinomial.
+    Stochastic,
+}
+
+pub struct Metapopulation {
+    patches: Vec<String>,
+    populations: Vec<Population>,
+    mobility: Array2<Rate>,
+    coupling: Coupling,
+    arrival_threshold: Count,
+    arrival_times: Vec<Option<usize>>,
+    time: usize,
+}
+
+/// The number of compartments that move. `removed` mirrors `recovered` in [`Population`], so
+/// they move together.
+const COMPARTMENTS: usize = 4;
+
+fn count(states: &DiseaseStates, k: usize) -> Count {
+    match k {
+        0 => states.susceptible,
+        1 => states.exposed,
+        2 => states.infectious,
+        3 => states.recovered,
+        _ => unreachable!("there are {} compartments", COMPARTMENTS),
+    }
+}
+
+fn add(states: &mut DiseaseStates, k: usize, amount: Count) {
+    match k {
+        0 => states.susceptible += amount,
+        1 => states.exposed += amount,
+        2 => states.infectious += amount,
+        3 => {
+            states.removed += amount;
+            states.recovered += amount;
+        }
+        _ => unreachable!("there are {} compartments", COMPARTMENTS),
+    }
+}
+
+impl Metapopulation {
+    pub fn new(patches: Vec<String>, populations: Vec<Population>, mobility: Array2<Rate>) -> Self {
+        let n = patches.len();
+        assert_eq!(
+            populations.len(),
+            n,
+            "a population is needed for every patch"
+        );
+        assert_eq!(
+            mobility.dim(),
+            (n, n),
+            "the mobility matrix must have a row and a column per patch"
+        );
+        for (i, row) in mobility.genrows().into_iter().enumerate() {
+            let leaving: Rate = row
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(_, m)| m)
+                .sum();
+            assert!(
+                row.iter().all(|m| *m >= 0.) && leaving <= 1.,
+                "the shares leaving patch {} must be non-negative and sum to at most one",
+                patches[i]
+            );
+        }
+
+        let mut metapopulation = Self {
+            patches,
+            populations,
+            mobility,
+            coupling: Coupling::Deterministic,
+            arrival_threshold: 1.,
+            arrival_times: vec![None; n],
+            time: 0,
+        };
+        metapopulation.record_arrivals();
+        metapopulation
+    }
+
+    /// Reads the patch names and the mobility matrix from `mobility`, with the patches'
+    /// `populations` in the same order.
+    pub fn read_csv<P: AsRef<Path>>(
+        mobility: P,
+        populations: Vec<Population>,
+    ) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(mobility).map_err(|error| error.to_string())?;
+        let (patches, mobility) = parse_contact_matrix(&contents)?;
+        if patches.len() != populations.len() {
+            return Err(format!(
+                "{} patches but {} populations",
+                patches.len(),
+                populations.len()
+            ));
+        }
+        Ok(Self::new(patches, populations, mobility))
+    }
+
+    pub fn set_coupling(mut self, coupling: Coupling) -> Self {
+        self.coupling = coupling;
+        self
+    }
+
+    /// The number of infectious at which the epidemic is considered to have arrived in a patch.
+    pub fn set_arrival_threshold(mut self, arrival_threshold: Count) -> Self {
+        self.arrival_threshold = arrival_threshold;
+        self.arrival_times = vec![None; self.patches.len()];
+        self.record_arrivals();
+        self
+    }
+
+    pub fn patches(&self) -> &[String] {
+        &self.patches
+    }
+
+    pub fn populations(&self) -> &[Population] {
+        &self.populations
+    }
+
+    pub fn time(&self) -> usize {
+        self.time
+    }
+
+    /// The first time at which each patch had at least the arrival threshold of infectious, or
+    /// `None` if the epidemic has not arrived yet.
+    pub fn arrival_times(&self) -> &[Option<usize>] {
+        &self.arrival_times
+    }
+
+    pub fn infectious(&self) -> Array1<Count> {
+        self.populations
+            .iter()
+            .map(Population::infectious)
+            .collect()
+    }
+
+    pub fn total_population(&self) -> Count {
+        self.populations
+            .iter()
+            .map(Population::total_population)
+            .sum()
+    }
+
+    fn record_arrivals(&mut self) {
+        for (arrival, population) in self.arrival_times.iter_mut().zip(&self.populations) {
+            if arrival.is_none() && population.infectious() >= self.arrival_threshold {
+                *arrival = Some(self.time);
+            }
+        }
+    }
+
+    /// Number of individuals of each compartment moving from patch `i` to patch `j`.
+    fn movers(&self) -> Vec<Array2<Count>> {
+        let n = self.patches.len();
+        let mut rng = rand::thread_rng();
+        (0..COMPARTMENTS)
+            .map(|k| {
+                let mut movers = Array2::zeros((n, n));
+                for (i, population) in self.populations.iter().enumerate() {
+                    let count = count(population.disease_states(), k);
+                    match self.coupling {
+                        Coupling::Deterministic => {
+                            for j in (0..n).filter(|&j| j != i) {
+                                movers[[i, j]] = self.mobility[[i, j]] * count;
+                            }
+                        }
+                        Coupling::Stochastic => {
+                            // multinomial draw by successive binomials on the remainder
+                            let mut remaining = count.floor().max(0.) as u64;
+                            let mut share = 1.;
+                            for j in (0..n).filter(|&j| j != i) {
+                                let p = self.mobility[[i, j]];
+                                if remaining == 0 || p <= 0. {
+                                    continue;
+                                }
+                                let moving = rng.sample(
+                                    Binomial::new(remaining, (p / share).min(1.))
+                                        .expect("mobility is a probability"),
+                                );
+                                movers[[i, j]] = moving as Count;
+                                remaining -= moving;
+                                share -= p;
+                            }
+                        }
+                    }
+                }
+                movers
+            })
+            .collect()
+    }
+
+    /// Updates the disease in every patch, then moves individuals between the patches.
+    pub fn update(&mut self) {
+        for population in self.populations.iter_mut() {
+            population.update_disease_state();
+        }
+
+        for (k, movers) in self.movers().into_iter().enumerate() {
+            let arriving = movers.sum_axis(ndarray::Axis(0));
+            let leaving = movers.sum_axis(ndarray::Axis(1));
+            for (i, population) in self.populations.iter_mut().enumerate() {
+                add(population.disease_states_mut(), k, arriving[i] - leaving[i]);
+            }
+        }
+
+        self.time += 1;
+        self.record_arrivals();
+    }
+
+    pub fn run(&mut self, steps: usize) {
+        for _ in 0..steps {
+            self.update();
+        }
+    }
+}
+
+impl Display for Metapopulation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        for ((patch, population), arrival) in self
+            .patches
+            .iter()
+            .zip(&self.populations)
+            .zip(&self.arrival_times)
+        {
+            write!(f, "{:<10} {} arrival ", patch, population)?;
+            match arrival {
+                Some(time) => writeln!(f, "{}", time)?,
+                None => writeln!(f, "-")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A chain of `n` towns of `size` where a share `mobility` moves to each neighbour, with one
+/// infectious individual in the first town.
+#[cfg(test)]
+fn chain(n: usize, size: Count, mobility: Rate) -> Metapopulation {
+    use crate::disease::create_sir_population;
+
+    let patches = (0..n).map(|i| format!("town {}", i)).collect();
+    let populations = (0..n)
+        .map(|i| {
+            let infected = if i == 0 { 1. } else { 0. };
+            create_sir_population(size - infected, infected, 0.4 / size, 0.2)
+        })
+        .collect();
+    let mobility = Array2::from_shape_fn((n, n), |(i, j)| {
+        if (i as i64 - j as i64).abs() == 1 {
+            mobility
+        } else {
+            0.
+        }
+    });
+    Metapopulation::new(patches, populations, mobility)
+}
+
+#[test]
+fn deterministic_spread_along_a_chain() {
+    let mut metapopulation = chain(4, 1000., 0.001);
+    let total = metapopulation.total_population();
+    metapopulation.run(300);
+    println!("{}", metapopulation);
+
+    assert!((metapopulation.total_population() - total).abs() < 1e-6);
+    let arrivals: Vec<_> = metapopulation
+        .arrival_times()
+        .iter()
+        .map(|arrival| arrival.expect("the epidemic reaches every town"))
+        .collect();
+    assert_eq!(arrivals[0], 0);
+    assert!(arrivals.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[test]
+fn stochastic_spread_along_a_chain() {
+    let mut isolated = chain(3, 1000., 0.).set_coupling(Coupling::Stochastic);
+    isolated.run(100);
+    assert_eq!(isolated.arrival_times(), &[Some(0), None, None]);
+
+    let mut metapopulation = chain(3, 1000., 0.01).set_coupling(Coupling::Stochastic);
+    let total = metapopulation.total_population();
+    metapopulation.run(300);
+    println!("{}", metapopulation);
+    assert!((metapopulation.total_population() - total).abs() < 1e-6);
+    assert!(metapopulation.arrival_times().iter().all(Option::is_some));
+}
+
+#[test]
+fn reading_the_mobility_matrix() {
+    use crate::disease::create_sir_population;
+
+    let path = std::env::temp_dir().join("epibox_mobility.csv");
+    std::fs::write(&path, ",a,b\na,0,0.1\nb,0.2,0\n").unwrap();
+    let populations = || {
+        vec![
+            create_sir_population(100., 1., 0.004, 0.2),
+            create_sir_population(100., 0., 0.004, 0.2),
+        ]
+    };
+    let metapopulation = Metapopulation::read_csv(&path, populations()).unwrap();
+    assert_eq!(metapopulation.patches(), &["a", "b"]);
+    assert_eq!(metapopulation.arrival_times(), &[Some(0), None]);
+    assert!(Metapopulation::read_csv(&path, populations()[..1].to_vec()).is_err());
+}
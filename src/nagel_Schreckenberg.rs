@@ -3,31 +3,32 @@
 use rand::distributions::Distribution;
 use rand::seq::IteratorRandom;
 use rand::thread_rng;
+use rayon::prelude::*;
 use std::fmt::{Display, Error, Formatter};
 
-type CarId = usize;
+pub type CarId = usize;
 #[derive(Debug)]
-struct Road {
+pub struct Road {
     cars: Vec<Car>,
     road_length: usize,
 }
 
-type Position = usize;
-type Velocity = usize;
+pub type Position = usize;
+pub type Velocity = usize;
 #[derive(Debug, Clone)]
-struct Car {
+pub struct Car {
     position: Position,
     velocity: Velocity,
 }
 
 impl Car {
-    fn new(position: usize, velocity: usize) -> Self {
+    pub fn new(position: usize, velocity: usize) -> Self {
         Self { position, velocity }
     }
 }
 
 impl Road {
-    fn new(road_length: usize, cars: usize) -> Self {
+    pub fn new(road_length: usize, cars: usize) -> Self {
         let mut random_positions = (0..road_length).choose_multiple(&mut thread_rng(), cars);
         random_positions.sort();
         let cars = random_positions
@@ -40,7 +41,7 @@ impl Road {
         Self { cars, road_length }
     }
 
-    fn road(&self) -> Vec<Option<CarId>> {
+    pub fn road(&self) -> Vec<Option<CarId>> {
         let mut road = vec![None; self.road_length];
         for (id, x) in self.cars.iter().enumerate() {
             road[x.position] = Some(id)
@@ -48,14 +49,14 @@ impl Road {
         road
     }
 
-    fn next_car(&self, current_car: CarId) -> Option<&Car> {
+    pub fn next_car(&self, current_car: CarId) -> Option<&Car> {
         if self.cars.len() <= 1 {
             panic!("there are no next car")
         }
         self.cars.iter().cycle().nth(current_car + 1)
     }
 
-    fn average_velocity(&self) -> f64 {
+    pub fn average_velocity(&self) -> f64 {
         self.cars.iter().map(|x| x.velocity as f64).sum::<f64>() / self.cars.len() as f64
     }
 }
@@ -100,7 +101,7 @@ impl Display for Road {
 impl From<&str> for Road {
     fn from(s: &str) -> Self {
         let mut cars = Vec::new();
-        let mut road_length = s.trim().len();
+        let road_length = s.trim().len();
         s.trim()
             .char_indices()
             .for_each(|(position, cell)| match cell {
@@ -117,6 +118,7 @@ impl From<&str> for Road {
 }
 
 #[test]
+#[allow(unused_variables)]
 fn testing_road_conversion() {
     println!("Creating roads from string-slices:");
     let roads = "_1________1____1_______1_1____
@@ -144,15 +146,15 @@ fn random_placed_cars_on_road() {
     })
 }
 
-fn maximum_velocity(density: f64) -> usize {
+pub fn maximum_velocity(density: f64) -> usize {
     (density.powi(-1) - 1.).round() as usize
 }
 
-fn density(maximum_velocity: usize) -> f64 {
+pub fn density(maximum_velocity: usize) -> f64 {
     1. / (maximum_velocity as f64 + 1.)
 }
 
-struct Model {
+pub struct Model {
     road: Road,
     timesteps: Vec<Road>,
     max_velocity: Velocity,
@@ -163,6 +165,40 @@ struct Model {
 }
 
 impl Model {
+    pub fn road(&self) -> &Road {
+        &self.road
+    }
+
+    pub fn timesteps(&self) -> &[Road] {
+        &self.timesteps
+    }
+
+    pub fn max_velocity(&self) -> Velocity {
+        self.max_velocity
+    }
+
+    pub fn density(&self) -> f64 {
+        self.density
+    }
+
+    pub fn randomisation_probability(&self) -> f64 {
+        self.randomisation_probability
+    }
+
+    /// Number of cars per cell of the road.
+    pub fn occupancy(&self) -> f64 {
+        self.road.cars.len() as f64 / self.road.road_length as f64
+    }
+
+    /// Number of cars passing a fixed point per update, averaged over the road. An empty road
+    /// has no flow.
+    pub fn flow(&self) -> f64 {
+        if self.road.cars.is_empty() {
+            return 0.;
+        }
+        self.occupancy() * self.road.average_velocity()
+    }
+
     fn update_acceleration(&mut self) {
         for car in &mut self.road.cars {
             if car.velocity < self.max_velocity {
@@ -200,7 +236,7 @@ impl Model {
         }
     }
 
-    fn update(&mut self) {
+    pub fn update(&mut self) {
         self.update_acceleration();
         self.update_slowing_down();
         self.update_randomisation();
@@ -216,7 +252,7 @@ impl Model {
         self
     }
 
-    fn new(
+    pub fn new(
         road_length: usize,
         cars: usize,
         randomisation_probability: f64,
@@ -250,7 +286,7 @@ impl Model {
         .amend_randomisation_sampler(randomisation_probability)
     }
 
-    fn run(mut self, _no_saved_iterations: usize) -> Self {
+    pub fn run(mut self, _no_saved_iterations: usize) -> Self {
         for _iteration in 0..self.max_iterations {
             self.timesteps.push(Road {
                 cars: self.road.cars.clone(),
@@ -262,11 +298,48 @@ impl Model {
     }
 }
 
-enum RoadDimension {
+pub enum RoadDimension {
     Density(f64),
     MaximalVelocity(usize),
 }
 
+/// The flow at each of the `densities` of cars on a ring road of `road_length` cells, averaged
+/// over `measurements` updates after discarding the first `transient` updates. The densities run
+/// in parallel.
+pub fn fundamental_diagram(
+    road_length: usize,
+    max_velocity: Velocity,
+    randomisation_probability: f64,
+    densities: &[f64],
+    transient: usize,
+    measurements: usize,
+) -> Vec<(f64, f64)> {
+    densities
+        .par_iter()
+        .map(|&density| {
+            let cars = (density * road_length as f64).round() as usize;
+            let mut model = Model::new(
+                road_length,
+                cars,
+                randomisation_probability,
+                RoadDimension::MaximalVelocity(max_velocity),
+                transient + measurements,
+            );
+            for _ in 0..transient {
+                model.update();
+            }
+            let flow = (0..measurements)
+                .map(|_| {
+                    model.update();
+                    model.flow()
+                })
+                .sum::<f64>()
+                / measurements as f64;
+            (density, flow)
+        })
+        .collect()
+}
+
 #[test]
 fn test_trajectory() {
     let max_iterations = 60;
@@ -292,3 +365,26 @@ fn test_update() {
     simple_model.update_motion();
     println!("1: {}", simple_model.road);
 }
+
+#[test]
+fn flow_peaks_at_low_density() {
+    let densities = [0.05, 0.1, 0.15, 0.3, 0.6, 0.9];
+    let deterministic = fundamental_diagram(200, 5, 0., &densities, 200, 100);
+    let randomised = fundamental_diagram(200, 5, 0.3, &densities, 200, 100);
+    for ((density, flow), (_, randomised_flow)) in deterministic.iter().zip(&randomised) {
+        println!(
+            "ρ = {:<4} J = {:.3} J(p = 0.3) = {:.3}",
+            density, flow, randomised_flow
+        );
+        // Without randomisation, free flow up to 1 / (v_max + 1) and jammed flow beyond it.
+        let expected = (density * 5.).min(1. - density);
+        assert!((flow - expected).abs() < 1e-9);
+        assert!(randomised_flow <= flow);
+    }
+}
+
+#[test]
+fn no_flow_without_cars() {
+    let diagram = fundamental_diagram(200, 5, 0.3, &[0., 0.001], 10, 10);
+    assert_eq!(diagram, vec![(0., 0.), (0.001, 0.)]);
+}
@@ -0,0 +1,217 @@
+//! Networks of agents, from regular lattices to the random graphs of Erdős–Rényi, Barabási–Albert
+//! and Watts–Strogatz, or edges read from a file.
+//!
+//! Source: [Barabási, Network Science](http://networksciencebook.com/)
+use rand::prelude::*;
+use std::path::Path;
+
+/// Who interacts with whom, e.g. who imitates whom in
+/// [`AgentBasedBass`](crate::bass_diffusion::AgentBasedBass).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Network {
+    /// Everyone is a neighbour of everyone else, as in the aggregate model.
+    FullMixing,
+    /// Periodic `width` by `height` lattice with the eight surrounding cells as neighbours.
+    Lattice { width: usize, height: usize },
+    /// Agents on a circle, each with `neighbours / 2` neighbours on either side.
+    Ring { neighbours: usize },
+    /// Random graph where each pair of agents is linked with `probability`, i.e. Erdős–Rényi.
+    Random { probability: f64 },
+    /// Preferential attachment: each agent after the first `edges + 1`, which are fully
+    /// connected, links to `edges` earlier agents chosen with probability proportional to their
+    /// degree.
+    BarabasiAlbert { edges: usize },
+    /// Small world: a [`Network::Ring`] where each link is rewired to a random agent with
+    /// probability `rewiring`.
+    WattsStrogatz { neighbours: usize, rewiring: f64 },
+    /// Undirected edges between agents.
+    Edges(Vec<(usize, usize)>),
+}
+
+impl Network {
+    /// Reads an edge list with a pair of agent indices per line, separated by whitespace or a
+    /// comma. Empty lines and lines starting with `#` are skipped.
+    pub fn read_edge_list<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+        Self::parse_edge_list(&contents)
+    }
+
+    pub fn parse_edge_list(contents: &str) -> Result<Self, String> {
+        contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim().starts_with('#'))
+            .map(|(row, line)| {
+                let agents = line
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|agent| !agent.is_empty())
+                    .map(|agent| {
+                        agent
+                            .parse::<usize>()
+                            .map_err(|error| format!("line {}: {}", row + 1, error))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                match agents.as_slice() {
+                    &[a, b] => Ok((a, b)),
+                    _ => Err(format!("line {}: expected two agents", row + 1)),
+                }
+            })
+            .collect::<Result<_, _>>()
+            .map(Network::Edges)
+    }
+
+    /// Neighbours of each of `n` agents; empty for full mixing.
+    pub fn adjacency(&self, n: usize) -> Vec<Vec<usize>> {
+        let mut neighbours = vec![Vec::new(); n];
+        let mut link = |a: usize, b: usize| {
+            if a != b && !neighbours[a].contains(&b) {
+                neighbours[a].push(b);
+                neighbours[b].push(a);
+            }
+        };
+        match self {
+            Network::FullMixing => {}
+            &Network::Lattice { width, height } => {
+                assert_eq!(width * height, n, "the lattice must hold every agent");
+                for y in 0..height {
+                    for x in 0..width {
+                        for &(dx, dy) in &[(1, 0), (0, 1), (1, 1), (1, height - 1)] {
+                            link(y * width + x, (y + dy) % height * width + (x + dx) % width);
+                        }
+                    }
+                }
+            }
+            &Network::Ring { neighbours } => {
+                for a in 0..n {
+                    for offset in 1..=neighbours / 2 {
+                        link(a, (a + offset) % n);
+                    }
+                }
+            }
+            &Network::Random { probability } => {
+                let mut rng = thread_rng();
+                for a in 0..n {
+                    for b in a + 1..n {
+                        if rng.gen_bool(probability) {
+                            link(a, b);
+                        }
+                    }
+                }
+            }
+            &Network::BarabasiAlbert { edges } => {
+                assert!(edges > 0 && edges < n, "need 0 < edges < agents");
+                let mut rng = thread_rng();
+                // every agent appears once per link, so uniform draws are proportional to degree
+                let mut ends = Vec::new();
+                for a in 0..=edges {
+                    for b in a + 1..=edges {
+                        link(a, b);
+                        ends.extend_from_slice(&[a, b]);
+                    }
+                }
+                for a in edges + 1..n {
+                    let mut targets: Vec<usize> = Vec::with_capacity(edges);
+                    while targets.len() < edges {
+                        let b = *ends.choose(&mut rng).unwrap();
+                        if !targets.contains(&b) {
+                            targets.push(b);
+                        }
+                    }
+                    for b in targets {
+                        link(a, b);
+                        ends.extend_from_slice(&[a, b]);
+                    }
+                }
+            }
+            &Network::WattsStrogatz {
+                neighbours: k,
+                rewiring,
+            } => {
+                assert!(k < n, "need fewer neighbours than agents");
+                Network::Ring { neighbours: k }
+                    .adjacency(n)
+                    .into_iter()
+                    .enumerate()
+                    .for_each(|(a, others)| {
+                        for b in others {
+                            link(a, b)
+                        }
+                    });
+                let mut rng = thread_rng();
+                for a in 0..n {
+                    for offset in 1..=k / 2 {
+                        let b = (a + offset) % n;
+                        if !neighbours[a].contains(&b) || !rng.gen_bool(rewiring) {
+                            continue;
+                        }
+                        let c = rng.gen_range(0, n);
+                        if c == a || neighbours[a].contains(&c) {
+                            continue;
+                        }
+                        neighbours[a].retain(|&x| x != b);
+                        neighbours[b].retain(|&x| x != a);
+                        neighbours[a].push(c);
+                        neighbours[c].push(a);
+                    }
+                }
+            }
+            Network::Edges(edges) => {
+                for &(a, b) in edges {
+                    assert!(a < n && b < n, "edge ({}, {}) has no agent", a, b);
+                    link(a, b);
+                }
+            }
+        }
+        neighbours
+    }
+}
+
+#[cfg(test)]
+fn degrees(neighbours: &[Vec<usize>]) -> Vec<usize> {
+    neighbours.iter().map(Vec::len).collect()
+}
+
+#[test]
+fn random_graph_degrees() {
+    let n = 400;
+    let erdos_renyi = Network::Random { probability: 0.02 }.adjacency(n);
+    let mean = degrees(&erdos_renyi).iter().sum::<usize>() as f64 / n as f64;
+    assert!((mean - 0.02 * (n - 1) as f64).abs() < 1.);
+
+    let barabasi_albert = degrees(&Network::BarabasiAlbert { edges: 3 }.adjacency(n));
+    assert!(barabasi_albert.iter().all(|&d| d >= 3));
+    assert_eq!(barabasi_albert.iter().sum::<usize>(), 2 * (6 + 3 * (n - 4)));
+    // hubs are much larger than the mean degree of about six
+    assert!(*barabasi_albert.iter().max().unwrap() > 20);
+
+    let sorted = |mut neighbours: Vec<Vec<usize>>| {
+        neighbours.iter_mut().for_each(|others| others.sort());
+        neighbours
+    };
+    let ring = Network::WattsStrogatz {
+        neighbours: 4,
+        rewiring: 0.,
+    }
+    .adjacency(n);
+    assert_eq!(
+        sorted(ring),
+        sorted(Network::Ring { neighbours: 4 }.adjacency(n))
+    );
+    let small_world = degrees(
+        &Network::WattsStrogatz {
+            neighbours: 4,
+            rewiring: 0.2,
+        }
+        .adjacency(n),
+    );
+    assert_eq!(small_world.iter().sum::<usize>(), 4 * n);
+}
+
+#[test]
+fn reading_an_edge_list() {
+    let network = Network::parse_edge_list("# a triangle\n0 1\n1,2\n\n2\t0\n").unwrap();
+    assert_eq!(network, Network::Edges(vec![(0, 1), (1, 2), (2, 0)]));
+    assert_eq!(degrees(&network.adjacency(3)), vec![2, 2, 2]);
+    assert!(Network::parse_edge_list("0 1 2").is_err());
+    assert!(Network::parse_edge_list("0 a").is_err());
+}
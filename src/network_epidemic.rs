@@ -0,0 +1,464 @@
+//! Individual-based SIR, SEIR and SIS epidemics on a contact [`Network`]. In every time step each
+//! infectious agent infects each susceptible neighbour with the transmission probability. The
+//! latent and infectious periods are drawn per agent, so the model records who infected whom and
+//! when, which gives the infection tree.
+//!
+//! Source: [Kiss, Miller & Simon, Mathematics of Epidemics on Networks](https://doi.org/10.1007/978-3-319-50806-1)
+use crate::interventions::{Interventions, Observation, Transition};
+use crate::network::Network;
+use rand::prelude::*;
+use rand_distr::{Exp, Gamma, Poisson};
+use std::fmt::{Display, Error, Formatter};
+
+type Time = usize;
+
+/// Which compartments an agent passes through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Model {
+    Sir,
+    Seir,
+    /// Recovered agents are susceptible again.
+    Sis,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Susceptible,
+    Exposed,
+    Infectious,
+    Recovered,
+}
+
+/// Distribution of the number of time steps an agent stays latent or infectious, which is
+/// rounded up to at least one step.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Period {
+    Fixed(f64),
+    /// The memoryless period of the mean-field models.
+    Exponential {
+        mean: f64,
+    },
+    Gamma {
+        shape: f64,
+        scale: f64,
+    },
+    Poisson {
+        mean: f64,
+    },
+}
+
+impl Period {
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Time {
+        let steps = match *self {
+            Period::Fixed(steps) => steps,
+            Period::Exponential { mean } => rng.sample(Exp::new(1. / mean).expect("invalid mean")),
+            Period::Gamma { shape, scale } => {
+                rng.sample(Gamma::new(shape, scale).expect("invalid shape or scale"))
+            }
+            Period::Poisson { mean } => rng.sample(Poisson::new(mean).expect("invalid mean")),
+        };
+        (steps.ceil() as Time).max(1)
+    }
+}
+
+/// `infector` infected `infectee` at `time`; seeds have no infector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Infection {
+    pub infectee: usize,
+    pub infector: Option<usize>,
+    pub time: Time,
+}
+
+/// Schedules and policies may leave [0, 1], so `p` is clamped into it, with NaN taken as 0.
+fn as_probability(p: f64) -> f64 {
+    if p.is_nan() {
+        0.
+    } else {
+        p.clamp(0., 1.)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NetworkEpidemic {
+    model: Model,
+    neighbours: Vec<Vec<usize>>,
+    transmission_probability: f64,
+    latent_period: Period,
+    infectious_period: Period,
+    states: Vec<State>,
+    /// Steps left in the current state, for exposed and infectious agents.
+    remaining: Vec<Time>,
+    infections: Vec<Infection>,
+    incidence: Vec<usize>,
+    time: Time,
+}
+
+impl NetworkEpidemic {
+    /// `n` susceptible agents on `network`, where full mixing links every pair of agents.
+    pub fn new(n: usize, network: &Network, model: Model, transmission_probability: f64) -> Self {
+        assert!(
+            (0. ..=1.).contains(&transmission_probability),
+            "the transmission probability must lie in [0, 1]: {}",
+            transmission_probability
+        );
+        let neighbours = match network {
+            Network::FullMixing => (0..n)
+                .map(|a| (0..n).filter(|&b| b != a).collect())
+                .collect(),
+            _ => network.adjacency(n),
+        };
+        Self {
+            model,
+            neighbours,
+            transmission_probability,
+            latent_period: Period::Fixed(1.),
+            infectious_period: Period::Fixed(1.),
+            states: vec![State::Susceptible; n],
+            remaining: vec![0; n],
+            infections: Vec::new(),
+            incidence: Vec::new(),
+            time: 0,
+        }
+    }
+
+    /// Only used by [`Model::Seir`].
+    pub fn set_latent_period(mut self, latent_period: Period) -> Self {
+        self.latent_period = latent_period;
+        self
+    }
+
+    pub fn set_infectious_period(mut self, infectious_period: Period) -> Self {
+        self.infectious_period = infectious_period;
+        self
+    }
+
+    /// Infects the `agents` from outside the network at the current time.
+    pub fn seed(mut self, agents: &[usize]) -> Self {
+        let mut rng = thread_rng();
+        for &agent in agents {
+            self.infect(agent, None, &mut rng);
+        }
+        self
+    }
+
+    fn infect<R: Rng>(&mut self, infectee: usize, infector: Option<usize>, rng: &mut R) {
+        let (state, period) = match self.model {
+            Model::Seir => (State::Exposed, self.latent_period),
+            Model::Sir | Model::Sis => (State::Infectious, self.infectious_period),
+        };
+        self.states[infectee] = state;
+        self.remaining[infectee] = period.sample(rng);
+        self.infections.push(Infection {
+            infectee,
+            infector,
+            time: self.time,
+        });
+    }
+
+    pub fn neighbours(&self) -> &[Vec<usize>] {
+        &self.neighbours
+    }
+
+    pub fn states(&self) -> &[State] {
+        &self.states
+    }
+
+    pub fn time(&self) -> Time {
+        self.time
+    }
+
+    /// Every infection so far, in the order they happened.
+    pub fn infections(&self) -> &[Infection] {
+        &self.infections
+    }
+
+    /// The number of new infections in each time step.
+    pub fn incidence(&self) -> &[usize] {
+        &self.incidence
+    }
+
+    pub fn count(&self, state: State) -> usize {
+        self.states.iter().filter(|&&s| s == state).count()
+    }
+
+    /// Whether there are no exposed or infectious agents left.
+    pub fn is_over(&self) -> bool {
+        !self
+            .states
+            .iter()
+            .any(|&s| s == State::Exposed || s == State::Infectious)
+    }
+
+    /// The agents infected by each agent, i.e. the edges of the infection tree, or forest if
+    /// agents were infected more than once.
+    pub fn infection_tree(&self) -> Vec<Vec<usize>> {
+        let mut children = vec![Vec::new(); self.states.len()];
+        for infection in &self.infections {
+            if let Some(infector) = infection.infector {
+                children[infector].push(infection.infectee);
+            }
+        }
+        children
+    }
+
+    /// The generation of each infection, counting the seeds as generation zero.
+    pub fn generations(&self) -> Vec<usize> {
+        let mut latest = vec![0; self.states.len()];
+        self.infections
+            .iter()
+            .map(|infection| {
+                let generation = match infection.infector {
+                    Some(infector) => latest[infector] + 1,
+                    None => 0,
+                };
+                latest[infection.infectee] = generation;
+                generation
+            })
+            .collect()
+    }
+
+    /// Transmission from every infectious agent, then the progression of the agents that were
+    /// exposed or infectious at the start of the step.
+    pub fn step(&mut self) {
+        self.step_with(&mut Interventions::new());
+    }
+
+    /// Like [`NetworkEpidemic::step`], with the transmission probability set by the schedule of
+    /// [`Transition::Infection`] in `interventions`. At the start of the step the detected
+    /// infectious agents are isolated and randomly chosen susceptible agents are vaccinated,
+    /// both becoming recovered.
+    pub fn step_with(&mut self, interventions: &mut Interventions) {
+        let mut rng = thread_rng();
+        let response = interventions.respond(Observation {
+            time: self.time,
+            susceptible: self.count(State::Susceptible) as f64,
+            infectious: self.count(State::Infectious) as f64,
+        });
+        let isolation = as_probability(response.isolation);
+        for agent in 0..self.states.len() {
+            if self.states[agent] == State::Infectious && rng.gen_bool(isolation) {
+                self.states[agent] = State::Recovered;
+            }
+        }
+        let susceptible: Vec<usize> = (0..self.states.len())
+            .filter(|&agent| self.states[agent] == State::Susceptible)
+            .collect();
+        let vaccinations = response.vaccinations.round() as usize;
+        for &agent in susceptible.choose_multiple(&mut rng, vaccinations) {
+            self.states[agent] = State::Recovered;
+        }
+        let transmission_probability = as_probability(
+            interventions.rate(
+                Transition::Infection,
+                self.transmission_probability,
+                self.time,
+            ) * response.infection_factor,
+        );
+
+        let mut infectious: Vec<usize> = (0..self.states.len())
+            .filter(|&agent| self.states[agent] == State::Infectious)
+            .collect();
+        // an agent exposed to several infectious neighbours is infected by a random one of them
+        infectious.shuffle(&mut rng);
+        let mut infected = vec![false; self.states.len()];
+        let mut transmissions = Vec::new();
+        for &infector in &infectious {
+            for &infectee in &self.neighbours[infector] {
+                if self.states[infectee] == State::Susceptible
+                    && !infected[infectee]
+                    && rng.gen_bool(transmission_probability)
+                {
+                    infected[infectee] = true;
+                    transmissions.push((infectee, infector));
+                }
+            }
+        }
+
+        for agent in 0..self.states.len() {
+            if self.states[agent] == State::Susceptible || self.states[agent] == State::Recovered {
+                continue;
+            }
+            self.remaining[agent] -= 1;
+            if self.remaining[agent] > 0 {
+                continue;
+            }
+            match (self.states[agent], self.model) {
+                (State::Exposed, _) => {
+                    self.states[agent] = State::Infectious;
+                    self.remaining[agent] = self.infectious_period.sample(&mut rng);
+                }
+                (State::Infectious, Model::Sis) => self.states[agent] = State::Susceptible,
+                (State::Infectious, _) => self.states[agent] = State::Recovered,
+                _ => unreachable!(),
+            }
+        }
+
+        self.time += 1;
+        self.incidence.push(transmissions.len());
+        for (infectee, infector) in transmissions {
+            self.infect(infectee, Some(infector), &mut rng);
+        }
+    }
+
+    /// Steps until the epidemic is over or `max_steps` have been made, and returns the incidence.
+    pub fn run(&mut self, max_steps: usize) -> &[usize] {
+        self.run_with(max_steps, &mut Interventions::new())
+    }
+
+    pub fn run_with(&mut self, max_steps: usize, interventions: &mut Interventions) -> &[usize] {
+        for _ in 0..max_steps {
+            if self.is_over() {
+                break;
+            }
+            self.step_with(interventions);
+        }
+        &self.incidence
+    }
+}
+
+impl Display for NetworkEpidemic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(
+            f,
+            "time = {:>4} S = {:<6} E = {:<6} I = {:<6} R = {:<6}",
+            self.time,
+            self.count(State::Susceptible),
+            self.count(State::Exposed),
+            self.count(State::Infectious),
+            self.count(State::Recovered)
+        )?;
+
+        Ok(())
+    }
+}
+
+#[test]
+fn sir_infection_tree() {
+    let mut epidemic =
+        NetworkEpidemic::new(300, &Network::BarabasiAlbert { edges: 2 }, Model::Sir, 0.3)
+            .set_infectious_period(Period::Exponential { mean: 3. })
+            .seed(&[0]);
+    let incidence = epidemic.run(1_000).to_vec();
+    println!("{}", epidemic);
+
+    assert!(epidemic.is_over());
+    let infections = epidemic.infections();
+    assert_eq!(infections.len(), epidemic.count(State::Recovered));
+    assert_eq!(incidence.iter().sum::<usize>() + 1, infections.len());
+    // everyone infected on the network was infected earlier by a neighbour
+    for infection in &infections[1..] {
+        let infector = infection.infector.unwrap();
+        assert!(epidemic.neighbours()[infector].contains(&infection.infectee));
+        let infected_at = infections.iter().find(|i| i.infectee == infector).unwrap();
+        assert!(infected_at.time < infection.time);
+    }
+    let tree = epidemic.infection_tree();
+    assert_eq!(
+        tree.iter().map(Vec::len).sum::<usize>() + 1,
+        infections.len()
+    );
+    let generations = epidemic.generations();
+    assert_eq!(generations[0], 0);
+    assert!(generations[1..].iter().all(|&g| g > 0));
+}
+
+#[test]
+fn seir_latency_delays_transmission() {
+    let mut epidemic = NetworkEpidemic::new(100, &Network::FullMixing, Model::Seir, 0.05)
+        .set_latent_period(Period::Fixed(4.))
+        .set_infectious_period(Period::Gamma {
+            shape: 2.,
+            scale: 1.5,
+        })
+        .seed(&[7]);
+    epidemic.run(1_000);
+    println!("{}", epidemic);
+    // the seed becomes infectious after four steps and its infections happen after that
+    for infection in epidemic.infections() {
+        if infection.infector == Some(7) {
+            assert!(infection.time >= 5);
+        }
+    }
+    assert_eq!(epidemic.incidence()[..4], [0, 0, 0, 0]);
+}
+
+#[test]
+fn sis_is_endemic_on_a_dense_graph() {
+    let network = Network::parse_edge_list("0 1\n1 2\n2 0\n").unwrap();
+    let mut isolated = NetworkEpidemic::new(3, &network, Model::Sis, 0.)
+        .set_infectious_period(Period::Poisson { mean: 2. })
+        .seed(&[0]);
+    isolated.run(100);
+    assert!(isolated.is_over());
+    assert_eq!(isolated.count(State::Susceptible), 3);
+
+    let mut endemic = NetworkEpidemic::new(
+        200,
+        &Network::WattsStrogatz {
+            neighbours: 10,
+            rewiring: 0.1,
+        },
+        Model::Sis,
+        0.5,
+    )
+    .set_infectious_period(Period::Fixed(2.))
+    .seed(&[0, 100]);
+    endemic.run(200);
+    println!("{}", endemic);
+    assert!(!endemic.is_over());
+    assert_eq!(endemic.count(State::Recovered), 0);
+    // agents are reinfected
+    assert!(endemic.infections().len() > 200);
+}
+
+#[test]
+#[should_panic(expected = "must lie in [0, 1]")]
+fn transmission_probability_beyond_one() {
+    NetworkEpidemic::new(10, &Network::FullMixing, Model::Sir, 1.5);
+}
+
+#[test]
+fn vaccination_and_isolation_limit_the_outbreak() {
+    use crate::interventions::{Policy, Schedule};
+
+    let network = Network::Random { probability: 0.02 };
+    let outbreak = |interventions: &mut Interventions| {
+        let mut epidemic = NetworkEpidemic::new(500, &network, Model::Sir, 0.1)
+            .set_infectious_period(Period::Fixed(4.))
+            .seed(&[0, 1, 2, 3, 4]);
+        epidemic.run_with(1_000, interventions);
+        assert!(epidemic.is_over());
+        epidemic.infections().len()
+    };
+    let unmitigated = outbreak(&mut Interventions::new());
+    let mitigated = outbreak(
+        &mut Interventions::new()
+            .add_policy(Policy::Vaccination {
+                per_step: 20.,
+                from: 0,
+            })
+            .add_policy(Policy::TestAndIsolate { probability: 0.3 }),
+    );
+    println!(
+        "infections {} with interventions {}",
+        unmitigated, mitigated
+    );
+    assert!(mitigated < unmitigated);
+
+    let closed = outbreak(
+        &mut Interventions::new().add_schedule(Transition::Infection, Schedule::Constant(0.)),
+    );
+    assert_eq!(closed, 5);
+
+    // probabilities outside [0, 1] are clamped
+    let negative = outbreak(
+        &mut Interventions::new()
+            .add_schedule(Transition::Infection, Schedule::Constant(-0.5))
+            .add_policy(Policy::TestAndIsolate { probability: 2. }),
+    );
+    assert_eq!(negative, 5);
+    let certain = outbreak(
+        &mut Interventions::new().add_schedule(Transition::Infection, Schedule::Constant(3.)),
+    );
+    assert!(certain > 450);
+}
@@ -0,0 +1,165 @@
+//! Integration of autonomous ordinary differential equations `dy/dt = f(y)` with the adaptive
+//! Dormand–Prince 5(4) Runge–Kutta method, which controls the local error of every step.
+//!
+//! Source: [Hairer, Nørsett & Wanner, Solving Ordinary Differential Equations I](https://doi.org/10.1007/978-3-540-78862-1)
+use ndarray::{Array1, Array2, ArrayView1};
+
+type Numeric = f64;
+
+const A: [[Numeric; 6]; 7] = [
+    [0., 0., 0., 0., 0., 0.],
+    [1. / 5., 0., 0., 0., 0., 0.],
+    [3. / 40., 9. / 40., 0., 0., 0., 0.],
+    [44. / 45., -56. / 15., 32. / 9., 0., 0., 0.],
+    [
+        19372. / 6561.,
+        -25360. / 2187.,
+        64448. / 6561.,
+        -212. / 729.,
+        0.,
+        0.,
+    ],
+    [
+        9017. / 3168.,
+        -355. / 33.,
+        46732. / 5247.,
+        49. / 176.,
+        -5103. / 18656.,
+        0.,
+    ],
+    [
+        35. / 384.,
+        0.,
+        500. / 1113.,
+        125. / 192.,
+        -2187. / 6784.,
+        11. / 84.,
+    ],
+];
+/// Weights of the fifth order solution, which equal the last row of `A`.
+const B: [Numeric; 7] = [
+    35. / 384.,
+    0.,
+    500. / 1113.,
+    125. / 192.,
+    -2187. / 6784.,
+    11. / 84.,
+    0.,
+];
+/// Weights of the embedded fourth order solution.
+const B_STAR: [Numeric; 7] = [
+    5179. / 57600.,
+    0.,
+    7571. / 16695.,
+    393. / 640.,
+    -92097. / 339200.,
+    187. / 2100.,
+    1. / 40.,
+];
+
+const MAX_STEPS: usize = 1_000_000;
+
+/// One step of size `h` from `y`, with the fifth order solution and the estimate of its error.
+fn step<F>(f: &F, y: &Array1<Numeric>, h: Numeric) -> (Array1<Numeric>, Array1<Numeric>)
+where
+    F: Fn(ArrayView1<Numeric>) -> Array1<Numeric>,
+{
+    let mut k: Vec<Array1<Numeric>> = Vec::with_capacity(7);
+    for a in A.iter() {
+        let mut stage = y.clone();
+        for (a, k) in a.iter().zip(&k) {
+            stage.scaled_add(h * a, k);
+        }
+        k.push(f(stage.view()));
+    }
+    let mut next = y.clone();
+    let mut error = Array1::zeros(y.len());
+    for ((b, b_star), k) in B.iter().zip(&B_STAR).zip(&k) {
+        next.scaled_add(h * b, k);
+        error.scaled_add(h * (b - b_star), k);
+    }
+    (next, error)
+}
+
+/// Integrates `f` from `initial` at `times[0]` and returns the state at each of the increasing
+/// `times`, one row per time. The error of each step, relative to the size of the state, is
+/// kept below `tolerance`.
+pub fn integrate<F>(
+    f: F,
+    initial: ArrayView1<Numeric>,
+    times: &[Numeric],
+    tolerance: Numeric,
+) -> Array2<Numeric>
+where
+    F: Fn(ArrayView1<Numeric>) -> Array1<Numeric>,
+{
+    assert!(!times.is_empty(), "at least the initial time is needed");
+    assert!(
+        times.windows(2).all(|pair| pair[0] < pair[1]),
+        "times must be increasing"
+    );
+    let mut trajectory = Array2::zeros((times.len(), initial.len()));
+    trajectory.row_mut(0).assign(&initial);
+
+    let mut y = initial.to_owned();
+    let mut t = times[0];
+    let mut h = match times.get(1) {
+        Some(next) => (next - t) / 10.,
+        None => return trajectory,
+    };
+    let mut steps = 0;
+    for (row, &target) in times.iter().enumerate().skip(1) {
+        while t < target {
+            steps += 1;
+            assert!(
+                steps < MAX_STEPS,
+                "the step size became too small at t = {}",
+                t
+            );
+            let size = h.min(target - t);
+            let (next, error) = step(&f, &y, size);
+            let error = next
+                .iter()
+                .zip(&y)
+                .zip(&error)
+                .map(|((next, y), e)| e.abs() / (tolerance * (1. + next.abs().max(y.abs()))))
+                .fold(0., Numeric::max);
+            let accepted = error <= 1.;
+            if accepted {
+                y = next;
+                t = if size == target - t { target } else { t + size };
+            }
+            let factor = if error == 0. {
+                5.
+            } else {
+                (0.9 * error.powf(-0.2)).clamp(0.2, 5.)
+            };
+            // a step shortened to land on the target says nothing about the size of the next one
+            if !(accepted && size < h) {
+                h = size * factor;
+            }
+        }
+        trajectory.row_mut(row).assign(&y);
+    }
+    trajectory
+}
+
+#[test]
+fn exponential_and_harmonic_oscillator() {
+    use ndarray::arr1;
+
+    let times: Vec<_> = (0..=10).map(|t| t as Numeric).collect();
+    let decay = integrate(|y| -0.5 * &y.to_owned(), arr1(&[2.]).view(), &times, 1e-10);
+    for (t, y) in times.iter().zip(decay.column(0)) {
+        assert!((y - 2. * (-0.5 * t).exp()).abs() < 1e-8);
+    }
+
+    let oscillator = integrate(
+        |y| arr1(&[y[1], -y[0]]),
+        arr1(&[1., 0.]).view(),
+        &[0., 2. * std::f64::consts::PI * 10.],
+        1e-10,
+    );
+    assert!((oscillator[[1, 0]] - 1.).abs() < 1e-7);
+    assert!(oscillator[[1, 1]].abs() < 1e-7);
+}
@@ -1,2 +1,610 @@
 //! [](http://www.cs.cmu.edu/afs/cs/academic/class/15462-s10/www/lec-slides/lec25.pdf)
 //! [](http://www.cs.cmu.edu/afs/cs/academic/class/15462-s10/www/lec-slides/lec27.pdf)
+//!
+//! Point masses in any number of dimensions, born from emitters, pushed around by forces and
+//! bouncing off planes and spheres. The [`Integrator`]s also step the boids of
+//! [`boids::Simulation`] and the agents of [`heroes_and_cowards::Universe`], which compute their
+//! own accelerations.
+//!
+//! [`boids::Simulation`]: crate::boids::Simulation
+//! [`heroes_and_cowards::Universe`]: crate::heroes_and_cowards::Universe
+use crate::spatial_data_structures::UniformGrid;
+use ndarray::{Array1, ArrayView1};
+use rand::prelude::*;
+use rand_distr::StandardNormal;
+use std::collections::HashMap;
+
+type Numeric = f64;
+pub type NumericVector = Array1<Numeric>;
+/// Force on a particle at a given time.
+pub type ForceField = Box<dyn Fn(&Particle, Numeric) -> NumericVector + Send + Sync>;
+
+fn norm(vector: ArrayView1<Numeric>) -> Numeric {
+    vector.dot(&vector).sqrt()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Particle {
+    /// Unique for the lifetime of the system, unlike the index of the particle.
+    pub id: usize,
+    pub position: NumericVector,
+    pub velocity: NumericVector,
+    pub mass: Numeric,
+    pub age: Numeric,
+    /// Particles are removed once their age reaches their lifetime; `None` lives forever.
+    pub lifetime: Option<Numeric>,
+}
+
+impl Particle {
+    pub fn kinetic_energy(&self) -> Numeric {
+        0.5 * self.mass * self.velocity.dot(&self.velocity)
+    }
+}
+
+/// Emits `rate` particles per unit of time at `position`, with `velocity` plus a uniformly
+/// random velocity of at most `spread`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Emitter {
+    pub position: NumericVector,
+    pub velocity: NumericVector,
+    pub spread: Numeric,
+    pub rate: Numeric,
+    pub mass: Numeric,
+    pub lifetime: Option<Numeric>,
+    /// Fraction of a particle carried over to the next step.
+    pending: Numeric,
+}
+
+impl Emitter {
+    pub fn new(position: NumericVector, velocity: NumericVector, rate: Numeric) -> Self {
+        Self {
+            position,
+            velocity,
+            spread: 0.,
+            rate,
+            mass: 1.,
+            lifetime: None,
+            pending: 0.,
+        }
+    }
+
+    pub fn set_spread(mut self, spread: Numeric) -> Self {
+        self.spread = spread;
+        self
+    }
+
+    pub fn set_mass(mut self, mass: Numeric) -> Self {
+        self.mass = mass;
+        self
+    }
+
+    pub fn set_lifetime(mut self, lifetime: Numeric) -> Self {
+        self.lifetime = Some(lifetime);
+        self
+    }
+
+    /// A uniformly random point in the ball of radius `spread`.
+    fn jitter<R: Rng>(&self, rng: &mut R) -> NumericVector {
+        let dimension = self.velocity.len();
+        if self.spread == 0. {
+            return Array1::zeros(dimension);
+        }
+        let direction: NumericVector = (0..dimension)
+            .map(|_| rng.sample::<Numeric, _>(StandardNormal))
+            .collect();
+        let radius = self.spread * rng.gen::<Numeric>().powf(1. / dimension as Numeric);
+        &direction * (radius / norm(direction.view()))
+    }
+}
+
+pub enum Force {
+    /// Constant acceleration.
+    Gravity(NumericVector),
+    /// Linear drag, `-coefficient * velocity`.
+    Drag(Numeric),
+    /// Damped spring between the particles with ids `a` and `b`. It goes away with either of
+    /// them.
+    Spring {
+        a: usize,
+        b: usize,
+        stiffness: Numeric,
+        rest_length: Numeric,
+        damping: Numeric,
+    },
+    Field(ForceField),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Integrator {
+    /// Explicit Euler, first order.
+    Euler,
+    /// Velocity Verlet, second order and symplectic for forces that depend only on position.
+    /// Velocity dependent forces use the velocity at the start of the step.
+    Verlet,
+}
+
+impl Integrator {
+    /// Advances `particles` by `delta_time`, where `accelerations` gives the acceleration of
+    /// every particle in a state of all of them. Verlet asks for them twice.
+    pub fn step<F>(self, particles: &mut [Particle], delta_time: Numeric, mut accelerations: F)
+    where
+        F: FnMut(&[Particle]) -> Vec<NumericVector>,
+    {
+        let accelerations_before = accelerations(particles);
+        match self {
+            Integrator::Euler => {
+                for (particle, acceleration) in particles.iter_mut().zip(&accelerations_before) {
+                    particle.position.scaled_add(delta_time, &particle.velocity);
+                    particle.velocity.scaled_add(delta_time, acceleration);
+                }
+            }
+            Integrator::Verlet => {
+                for (particle, acceleration) in particles.iter_mut().zip(&accelerations_before) {
+                    particle.position.scaled_add(delta_time, &particle.velocity);
+                    particle
+                        .position
+                        .scaled_add(0.5 * delta_time * delta_time, acceleration);
+                }
+                let accelerations_after = accelerations(particles);
+                for ((particle, before), after) in particles
+                    .iter_mut()
+                    .zip(&accelerations_before)
+                    .zip(&accelerations_after)
+                {
+                    particle.velocity.scaled_add(0.5 * delta_time, before);
+                    particle.velocity.scaled_add(0.5 * delta_time, after);
+                }
+            }
+        }
+    }
+}
+
+/// Solid plane, or line in two dimensions; the side `normal` points to is free.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plane {
+    pub point: NumericVector,
+    pub normal: NumericVector,
+}
+
+/// Solid sphere.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sphere {
+    pub center: NumericVector,
+    pub radius: Numeric,
+}
+
+pub struct ParticleSystem {
+    dimension: usize,
+    particles: Vec<Particle>,
+    emitters: Vec<Emitter>,
+    forces: Vec<Force>,
+    integrator: Integrator,
+    planes: Vec<Plane>,
+    spheres: Vec<Sphere>,
+    /// Centres of the spheres, with cells as large as the largest sphere.
+    sphere_index: UniformGrid,
+    /// Fraction of the normal velocity kept after a collision.
+    restitution: Numeric,
+    /// Fraction of the tangential velocity lost in a collision.
+    friction: Numeric,
+    time: Numeric,
+    next_id: usize,
+}
+
+impl ParticleSystem {
+    pub fn new(dimension: usize, integrator: Integrator) -> Self {
+        Self {
+            dimension,
+            particles: Vec::new(),
+            emitters: Vec::new(),
+            forces: Vec::new(),
+            integrator,
+            planes: Vec::new(),
+            spheres: Vec::new(),
+            sphere_index: UniformGrid::new(1.),
+            restitution: 1.,
+            friction: 0.,
+            time: 0.,
+            next_id: 0,
+        }
+    }
+
+    pub fn add_emitter(mut self, emitter: Emitter) -> Self {
+        assert_eq!(emitter.position.len(), self.dimension);
+        self.emitters.push(emitter);
+        self
+    }
+
+    pub fn add_force(mut self, force: Force) -> Self {
+        self.forces.push(force);
+        self
+    }
+
+    pub fn add_plane(mut self, plane: Plane) -> Self {
+        assert_eq!(plane.point.len(), self.dimension);
+        self.planes.push(plane);
+        self
+    }
+
+    pub fn add_sphere(mut self, sphere: Sphere) -> Self {
+        assert_eq!(sphere.center.len(), self.dimension);
+        self.spheres.push(sphere);
+        let max_radius = self
+            .spheres
+            .iter()
+            .map(|sphere| sphere.radius)
+            .fold(0., Numeric::max);
+        self.sphere_index = UniformGrid::new(max_radius);
+        for (i, sphere) in self.spheres.iter().enumerate() {
+            self.sphere_index.insert(i, sphere.center.clone());
+        }
+        self
+    }
+
+    pub fn set_restitution(mut self, restitution: Numeric) -> Self {
+        self.restitution = restitution;
+        self
+    }
+
+    pub fn set_friction(mut self, friction: Numeric) -> Self {
+        self.friction = friction;
+        self
+    }
+
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    pub fn particle(&self, id: usize) -> Option<&Particle> {
+        self.particles.iter().find(|particle| particle.id == id)
+    }
+
+    pub fn time(&self) -> Numeric {
+        self.time
+    }
+
+    /// Adds a particle and returns its id.
+    pub fn spawn(
+        &mut self,
+        position: NumericVector,
+        velocity: NumericVector,
+        mass: Numeric,
+        lifetime: Option<Numeric>,
+    ) -> usize {
+        assert_eq!(position.len(), self.dimension);
+        assert_eq!(velocity.len(), self.dimension);
+        let id = self.next_id;
+        self.next_id += 1;
+        self.particles.push(Particle {
+            id,
+            position,
+            velocity,
+            mass,
+            age: 0.,
+            lifetime,
+        });
+        id
+    }
+
+    fn emit(&mut self, delta_time: Numeric) {
+        let mut rng = thread_rng();
+        let mut emitted = Vec::new();
+        for emitter in &mut self.emitters {
+            emitter.pending += emitter.rate * delta_time;
+            while emitter.pending >= 1. {
+                emitter.pending -= 1.;
+                let velocity = &emitter.velocity + &emitter.jitter(&mut rng);
+                emitted.push((
+                    emitter.position.clone(),
+                    velocity,
+                    emitter.mass,
+                    emitter.lifetime,
+                ));
+            }
+        }
+        for (position, velocity, mass, lifetime) in emitted {
+            self.spawn(position, velocity, mass, lifetime);
+        }
+    }
+
+    /// Total force on each particle.
+    pub fn forces(&self) -> Vec<NumericVector> {
+        self.forces_on(&self.particles)
+    }
+
+    fn forces_on(&self, particles: &[Particle]) -> Vec<NumericVector> {
+        let mut forces = vec![Array1::zeros(self.dimension); particles.len()];
+        let index: HashMap<usize, usize> = particles
+            .iter()
+            .enumerate()
+            .map(|(i, particle)| (particle.id, i))
+            .collect();
+        for force in &self.forces {
+            match force {
+                Force::Gravity(acceleration) => {
+                    for (total, particle) in forces.iter_mut().zip(particles) {
+                        total.scaled_add(particle.mass, acceleration);
+                    }
+                }
+                Force::Drag(coefficient) => {
+                    for (total, particle) in forces.iter_mut().zip(particles) {
+                        total.scaled_add(-coefficient, &particle.velocity);
+                    }
+                }
+                Force::Spring {
+                    a,
+                    b,
+                    stiffness,
+                    rest_length,
+                    damping,
+                } => {
+                    let (a, b) = match (index.get(a), index.get(b)) {
+                        (Some(&a), Some(&b)) => (a, b),
+                        _ => continue,
+                    };
+                    let offset = &particles[b].position - &particles[a].position;
+                    let length = norm(offset.view());
+                    if length == 0. {
+                        continue;
+                    }
+                    let direction = offset / length;
+                    let relative_velocity = &particles[b].velocity - &particles[a].velocity;
+                    let magnitude = stiffness * (length - rest_length)
+                        + damping * relative_velocity.dot(&direction);
+                    forces[a].scaled_add(magnitude, &direction);
+                    forces[b].scaled_add(-magnitude, &direction);
+                }
+                Force::Field(field) => {
+                    for (total, particle) in forces.iter_mut().zip(particles) {
+                        *total += &field(particle, self.time);
+                    }
+                }
+            }
+        }
+        forces
+    }
+
+    fn accelerations(&self, particles: &[Particle]) -> Vec<NumericVector> {
+        self.forces_on(particles)
+            .into_iter()
+            .zip(particles)
+            .map(|(force, particle)| force / particle.mass)
+            .collect()
+    }
+
+    /// Pushes particles out of planes and spheres, reflecting their normal velocity.
+    fn collide(&mut self) {
+        let ParticleSystem {
+            particles,
+            planes,
+            spheres,
+            sphere_index,
+            restitution,
+            friction,
+            ..
+        } = self;
+        let max_radius = spheres
+            .iter()
+            .map(|sphere| sphere.radius)
+            .fold(0., Numeric::max);
+        for particle in particles {
+            // Resolving one contact can cause another, e.g. in the corner between a sphere and
+            // a plane, so the deepest is resolved first, a few times over.
+            for _ in 0..8 {
+                let position = &particle.position;
+                let plane_contacts = planes.iter().map(|plane| {
+                    let normal = &plane.normal / norm(plane.normal.view());
+                    ((position - &plane.point).dot(&normal), normal)
+                });
+                let sphere_contacts = sphere_index
+                    .within_radius(position.view(), max_radius)
+                    .into_iter()
+                    .filter_map(|i| {
+                        let Sphere { center, radius } = &spheres[i];
+                        let offset = position - center;
+                        let distance = norm(offset.view());
+                        if distance == 0. {
+                            None
+                        } else {
+                            Some((distance - radius, offset / distance))
+                        }
+                    });
+                let deepest = plane_contacts
+                    .chain(sphere_contacts)
+                    .filter(|(depth, _)| *depth < 0.)
+                    .min_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap());
+                let (depth, normal) = match deepest {
+                    Some(contact) => contact,
+                    None => break,
+                };
+                particle.position.scaled_add(-depth, &normal);
+                let normal_speed = particle.velocity.dot(&normal);
+                if normal_speed < 0. {
+                    let tangential = &particle.velocity - &(&normal * normal_speed);
+                    particle.velocity =
+                        tangential * (1. - *friction) - &normal * (*restitution * normal_speed);
+                }
+            }
+        }
+    }
+
+    pub fn step(&mut self, delta_time: Numeric) {
+        self.emit(delta_time);
+        self.particles.retain(|particle| match particle.lifetime {
+            Some(lifetime) => particle.age < lifetime,
+            None => true,
+        });
+
+        let mut particles = std::mem::take(&mut self.particles);
+        self.integrator
+            .step(&mut particles, delta_time, |particles| {
+                self.accelerations(particles)
+            });
+        self.particles = particles;
+        self.collide();
+
+        for particle in &mut self.particles {
+            particle.age += delta_time;
+        }
+        self.time += delta_time;
+    }
+
+    /// Steps until `end_time`.
+    pub fn run(&mut self, end_time: Numeric, delta_time: Numeric) {
+        while self.time + delta_time <= end_time + 1e-12 {
+            self.step(delta_time);
+        }
+    }
+}
+
+#[test]
+fn projectile_under_gravity() {
+    let mut errors = Vec::new();
+    for &integrator in &[Integrator::Euler, Integrator::Verlet] {
+        let mut system = ParticleSystem::new(2, integrator)
+            .add_force(Force::Gravity(ndarray::arr1(&[0., -9.81])));
+        let id = system.spawn(
+            ndarray::arr1(&[0., 0.]),
+            ndarray::arr1(&[3., 10.]),
+            2.,
+            None,
+        );
+        system.run(1., 0.01);
+        let expected = ndarray::arr1(&[3., 10. - 0.5 * 9.81]);
+        let error = norm((&system.particle(id).unwrap().position - &expected).view());
+        println!("{:?}: error {:e}", integrator, error);
+        errors.push(error);
+    }
+    // Verlet is exact for constant forces.
+    assert!(errors[0] > 1e-2);
+    assert!(errors[1] < 1e-9);
+}
+
+#[test]
+fn spring_energy() {
+    let energy = |system: &ParticleSystem| {
+        let stretch =
+            norm((&system.particles()[1].position - &system.particles()[0].position).view()) - 1.;
+        system
+            .particles()
+            .iter()
+            .map(Particle::kinetic_energy)
+            .sum::<Numeric>()
+            + 0.5 * 4. * stretch * stretch
+    };
+    let mut drifts = Vec::new();
+    for &integrator in &[Integrator::Euler, Integrator::Verlet] {
+        let mut system = ParticleSystem::new(3, integrator);
+        let a = system.spawn(
+            ndarray::arr1(&[0., 0., 0.]),
+            ndarray::arr1(&[0., 0., 0.]),
+            1.,
+            None,
+        );
+        let b = system.spawn(
+            ndarray::arr1(&[1.5, 0., 0.]),
+            ndarray::arr1(&[0., 0.3, 0.]),
+            1.,
+            None,
+        );
+        system = system.add_force(Force::Spring {
+            a,
+            b,
+            stiffness: 4.,
+            rest_length: 1.,
+            damping: 0.,
+        });
+        let initial = energy(&system);
+        system.run(20., 0.01);
+        drifts.push((energy(&system) - initial).abs() / initial);
+    }
+    println!("relative energy drift: {:?}", drifts);
+    assert!(drifts[0] > 0.1);
+    assert!(drifts[1] < 1e-3);
+
+    let mut damped = ParticleSystem::new(1, Integrator::Verlet);
+    let a = damped.spawn(ndarray::arr1(&[0.]), ndarray::arr1(&[0.]), 1., None);
+    let b = damped.spawn(ndarray::arr1(&[2.]), ndarray::arr1(&[0.]), 1., None);
+    damped = damped
+        .add_force(Force::Spring {
+            a,
+            b,
+            stiffness: 4.,
+            rest_length: 1.,
+            damping: 1.,
+        })
+        .add_force(Force::Drag(0.1));
+    damped.run(30., 0.01);
+    let length = damped.particles()[1].position[0] - damped.particles()[0].position[0];
+    assert!((length - 1.).abs() < 1e-3);
+}
+
+#[test]
+fn emitters_and_lifetimes() {
+    let emitter = Emitter::new(ndarray::arr1(&[0., 0.]), ndarray::arr1(&[1., 0.]), 50.)
+        .set_spread(0.5)
+        .set_lifetime(2.);
+    let swirl: ForceField =
+        Box::new(|particle, _| ndarray::arr1(&[-particle.position[1], particle.position[0]]) * 0.1);
+    let mut system = ParticleSystem::new(2, Integrator::Euler)
+        .add_emitter(emitter)
+        .add_force(Force::Field(swirl));
+    system.run(10., 0.1);
+    // At 50 per unit of time and living for 2, there are 100 particles at any time.
+    assert!((99..=101).contains(&system.particles().len()));
+    assert!(system
+        .particles()
+        .iter()
+        .all(|particle| particle.age <= 2. + 1e-9));
+    let ids: std::collections::HashSet<_> = system.particles().iter().map(|p| p.id).collect();
+    assert_eq!(ids.len(), system.particles().len());
+    assert!(system.particles().iter().all(|particle| {
+        let speed = norm(particle.velocity.view());
+        speed > 0.
+    }));
+}
+
+#[test]
+fn bouncing_off_planes_and_spheres() {
+    let mut system = ParticleSystem::new(3, Integrator::Verlet)
+        .add_force(Force::Gravity(ndarray::arr1(&[0., 0., -9.81])))
+        .add_plane(Plane {
+            point: ndarray::arr1(&[0., 0., 0.]),
+            normal: ndarray::arr1(&[0., 0., 1.]),
+        })
+        .set_restitution(0.8);
+    for i in 0..10 {
+        for j in 0..10 {
+            system = system.add_sphere(Sphere {
+                center: ndarray::arr1(&[i as Numeric, j as Numeric, 0.]),
+                radius: 0.2 + 0.02 * ((i + j) % 5) as Numeric,
+            });
+        }
+    }
+    let mut rng = thread_rng();
+    for _ in 0..200 {
+        let position = ndarray::arr1(&[
+            rng.gen_range(0., 9.),
+            rng.gen_range(0., 9.),
+            rng.gen_range(1., 3.),
+        ]);
+        system.spawn(position, ndarray::arr1(&[0.5, 0., 0.]), 1., None);
+    }
+    for step in 0..300 {
+        system.step(0.01);
+        if step % 20 != 0 {
+            continue;
+        }
+        for particle in system.particles() {
+            assert!(particle.position[2] >= -1e-9);
+            for sphere in &system.spheres {
+                assert!(norm((&particle.position - &sphere.center).view()) >= sphere.radius - 1e-9);
+            }
+        }
+    }
+    // Inelastic bounces never gain height.
+    assert!(system
+        .particles()
+        .iter()
+        .all(|particle| particle.position[2] < 3.));
+}
@@ -1,17 +1,21 @@
+use crate::disease;
+use ndarray::Array2;
 use std::collections::HashMap;
 use std::fmt::{Display, Error, Formatter};
 
-type Count = f64;
-type Rate = f64;
+pub type Count = f64;
+pub type Rate = f64;
 
 #[derive(Default)]
-struct Population {
+pub struct Population {
     count: HashMap<DiseaseCompartment, Count>,
     transitions: HashMap<DiseaseTransition, Box<dyn Fn(Count, Count) -> Rate>>,
     terms: HashMap<DiseaseCompartment, Box<dyn Fn(Count) -> Rate>>,
+    #[allow(dead_code)]
     transition_parameters: HashMap<(DiseaseCompartment, DiseaseCompartment), DiseaseParameter>,
 }
 
+#[allow(dead_code)]
 #[derive(Debug)]
 enum DiseaseParameter {
     Rate(Rate),
@@ -23,12 +27,13 @@ enum DiseaseParameter {
 struct DiseaseTransition {
     from: DiseaseCompartment,
     to: DiseaseCompartment,
+    #[allow(dead_code)]
     using: Option<Vec<DiseaseCompartment>>,
     //    dynamic: fn(Count, Count) -> Rate,
 }
 
 #[derive(Hash, PartialEq, Eq, Debug, Copy, Clone)]
-enum DiseaseCompartment {
+pub enum DiseaseCompartment {
     Susceptible,
     Exposed, //also Latent
     Infected,
@@ -36,15 +41,74 @@ enum DiseaseCompartment {
     Removed,
 }
 
+impl DiseaseCompartment {
+    /// Whether individuals in the compartment carry the disease, i.e. are latent or infectious.
+    pub fn is_infected(&self) -> bool {
+        matches!(
+            self,
+            DiseaseCompartment::Exposed | DiseaseCompartment::Infected
+        )
+    }
+}
+
+/// Change in the counts during one update.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flow {
+    Transition {
+        from: DiseaseCompartment,
+        to: DiseaseCompartment,
+        amount: Rate,
+    },
+    Term {
+        compartment: DiseaseCompartment,
+        amount: Rate,
+    },
+}
+
+impl Flow {
+    /// Whether the flow brings susceptible individuals into an infected compartment.
+    pub fn is_infection(&self) -> bool {
+        match self {
+            Flow::Transition { from, to, .. } => !from.is_infected() && to.is_infected(),
+            Flow::Term { .. } => false,
+        }
+    }
+}
+
 impl Population {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Default::default()
     }
-    fn add_compartment(mut self, compartment: DiseaseCompartment, count: Count) -> Self {
+
+    /// The SIR model, with mass-action infection and a constant recovery rate.
+    pub fn sir(
+        susceptible: Count,
+        infected: Count,
+        infection_rate: Rate,
+        recovery_rate: Rate,
+    ) -> Self {
+        use DiseaseCompartment::*;
+
+        Self::new()
+            .add_compartment(Susceptible, susceptible)
+            .add_compartment(Infected, infected)
+            .add_compartment(Recovered, 0.)
+            .add_transition(
+                Susceptible,
+                Infected,
+                Box::new(move |sus, inf| infection_rate * sus * inf),
+            )
+            .add_transition(
+                Infected,
+                Recovered,
+                Box::new(move |inf, _recover| recovery_rate * inf),
+            )
+    }
+    pub fn add_compartment(mut self, compartment: DiseaseCompartment, count: Count) -> Self {
         self.count.insert(compartment, count);
         self
     }
-    fn add_transition(
+    pub fn add_transition(
         mut self,
         from: DiseaseCompartment,
         to: DiseaseCompartment,
@@ -63,6 +127,7 @@ impl Population {
 
     /// Useful in the case where the closure is not a function of of LEFT -> RIGHT, but other disease
     /// compartments.
+    #[allow(dead_code)]
     fn update_state_transition(
         mut self,
         from: DiseaseCompartment,
@@ -79,8 +144,8 @@ impl Population {
             .collect();
         let mut next_counts = self.count.clone();
 
-        let from_count = *self.count.get(&from).unwrap_or(&Default::default());
-        let to_count = *self.count.get(&to).unwrap_or(&Default::default());
+        let _from_count = *self.count.get(&from).unwrap_or(&Default::default());
+        let _to_count = *self.count.get(&to).unwrap_or(&Default::default());
         let from_diff = dynamic(state);
         //        let from_diff = boxed_transition(from_count, to_count);
         //let to_diff = -from_diff;
@@ -93,7 +158,7 @@ impl Population {
         todo!()
     }
     /// A better name would be to call this `scalar_term`, as one cannot inject a different
-    fn add_term(
+    pub fn add_term(
         mut self,
         compartment: DiseaseCompartment,
         dynamic: Box<dyn Fn(Count) -> Rate>,
@@ -102,35 +167,43 @@ impl Population {
         self
     }
 
-    fn update_disease_states(&mut self) {
-        let mut next_counts = self.count.clone();
-        // Update transitions
-        for (transition, boxed_transition) in self.transitions.iter() {
-            let from_count = *self
-                .count
-                .get(&transition.from)
-                .unwrap_or(&Default::default());
-            let to_count = *self
-                .count
-                .get(&transition.to)
-                .unwrap_or(&Default::default());
-            let from_diff = boxed_transition(from_count, to_count);
-            //let to_diff = -from_diff;
-
-            *next_counts.entry(transition.from).or_default() -= from_diff;
-            *next_counts.entry(transition.to).or_default() += from_diff;
-        }
+    /// The transitions and terms evaluated at `count`.
+    pub fn flows(&self, count: &HashMap<DiseaseCompartment, Count>) -> Vec<Flow> {
+        let get = |compartment| *count.get(compartment).unwrap_or(&Default::default());
+        let transitions = self
+            .transitions
+            .iter()
+            .map(|(transition, boxed_transition)| Flow::Transition {
+                from: transition.from,
+                to: transition.to,
+                amount: boxed_transition(get(&transition.from), get(&transition.to)),
+            });
+        let terms = self.terms.iter().map(|(compartment, dynamic)| Flow::Term {
+            compartment: *compartment,
+            amount: dynamic(get(compartment)),
+        });
+        transitions.chain(terms).collect()
+    }
 
-        // Update individual terms
-        for (compartment, dynamic) in self.terms.iter() {
-            *next_counts.entry(*compartment).or_default() +=
-                dynamic(*self.count.get(&compartment).unwrap_or(&Default::default()))
+    pub fn update_disease_states(&mut self) {
+        let mut next_counts = self.count.clone();
+        for flow in self.flows(&self.count) {
+            match flow {
+                Flow::Transition { from, to, amount } => {
+                    *next_counts.entry(from).or_default() -= amount;
+                    *next_counts.entry(to).or_default() += amount;
+                }
+                Flow::Term {
+                    compartment,
+                    amount,
+                } => *next_counts.entry(compartment).or_default() += amount,
+            }
         }
 
         self.count = next_counts;
     }
 
-    fn total_population(&self) -> Count {
+    pub fn total_population(&self) -> Count {
         self.count
             .iter()
             .fold(Default::default(), |acc, x| match x {
@@ -138,6 +211,37 @@ impl Population {
                 (_, &count) => acc + count,
             })
     }
+
+    pub fn counts(&self) -> &HashMap<DiseaseCompartment, Count> {
+        &self.count
+    }
+
+    pub fn set_count(&mut self, compartment: DiseaseCompartment, count: Count) {
+        self.count.insert(compartment, count);
+    }
+
+    pub fn count(&self, compartment: DiseaseCompartment) -> Count {
+        *self.count.get(&compartment).unwrap_or(&Default::default())
+    }
+
+    /// The counts of `compartments` at the current time and after each of `steps` updates, one
+    /// row per time.
+    pub fn trajectory(
+        &mut self,
+        compartments: &[DiseaseCompartment],
+        steps: usize,
+    ) -> Array2<Count> {
+        let mut trajectory = Array2::zeros((steps + 1, compartments.len()));
+        for (time, mut row) in trajectory.genrows_mut().into_iter().enumerate() {
+            if time > 0 {
+                self.update_disease_states();
+            }
+            for (count, &compartment) in row.iter_mut().zip(compartments) {
+                *count = self.count(compartment);
+            }
+        }
+        trajectory
+    }
 }
 
 impl Display for Population {
@@ -148,6 +252,41 @@ impl Display for Population {
     }
 }
 
+/// The compartment graph of the SIR or SIRS model of a [`disease::Population`].
+impl From<&disease::Population> for Population {
+    fn from(population: &disease::Population) -> Self {
+        use DiseaseCompartment::*;
+
+        let infection_rate = population.infection_rate();
+        let recovery_rate = population.recovery_rate();
+        let immunity_decay_rate = population.immunity_decay_rate();
+        let sir = Self::new()
+            .add_compartment(Susceptible, population.susceptible())
+            .add_compartment(Infected, population.infectious())
+            .add_compartment(Recovered, population.recovered())
+            .add_transition(
+                Susceptible,
+                Infected,
+                Box::new(move |sus, inf| infection_rate * sus * inf),
+            )
+            .add_transition(
+                Infected,
+                Recovered,
+                Box::new(move |inf, _rec| recovery_rate * inf),
+            );
+        // without waning immunity the graph is the plain SIR model
+        if immunity_decay_rate == 0. {
+            sir
+        } else {
+            sir.add_transition(
+                Recovered,
+                Susceptible,
+                Box::new(move |rec, _sus| immunity_decay_rate * rec),
+            )
+        }
+    }
+}
+
 #[test]
 fn building_a_sir_population() {
     use DiseaseCompartment::*;
@@ -346,6 +485,7 @@ fn building_steady_sir_with_hunting_model() {
 }
 
 #[test]
+#[allow(unused_variables, non_snake_case)]
 fn building_sei_model() {
     //    let r;
     //    let k;
@@ -1,20 +1,17 @@
 //! Source: [Assignment 4](http://prac.im.pwr.wroc.pl/~szwabin/assets/abm/labs/l4.pdf)
 use itertools::Itertools;
 use ndarray::Array2;
-use rand::seq::IteratorRandom;
+use rand::seq::{IteratorRandom, SliceRandom};
 use rand::thread_rng;
-use std::cell::RefCell;
+#[cfg(test)]
 use std::collections::HashSet;
-use std::convert::identity;
 use std::fmt::{Display, Error, Formatter};
 use std::iter::once;
-use std::ops::Deref;
-use std::rc::Rc;
 
-const MAX_ITERATIONS: i32 = 10000;
+const MAX_ITERATIONS: usize = 10000;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-enum Mark {
+pub enum Mark {
     None,
     Blue,
     Red,
@@ -37,7 +34,7 @@ impl Display for Mark {
 /// `m_red` and `m_blue` are no. of closest neighbours to consider.
 /// Presumably `j_red` and `j_blue` are percentages.
 #[derive(Clone, Debug)]
-struct Model {
+pub struct Model {
     //    no_agents: usize,
     no_red: usize,
     no_blue: usize,
@@ -50,6 +47,7 @@ struct Model {
     //    agents: Vec<&'a Agent>,
 }
 
+#[allow(dead_code)]
 enum Neighbourhood {
     Radius(u32),
     Size(u32),
@@ -72,6 +70,7 @@ enum Neighbourhood {
 //    }
 //}
 
+#[allow(dead_code)]
 impl Neighbourhood {
     fn from_neighbourhood_size(size: u32) -> Self {
         match size {
@@ -100,15 +99,18 @@ impl Neighbourhood {
 struct Agent {
     position: (isize, isize),
     mark: Mark,
+    /// Whether the agent is unhappy where it is, and so looking for another cell.
     moving: bool,
 }
 
 impl Model {
-    fn no_agents(&self) -> usize {
+    pub fn no_agents(&self) -> usize {
         self.no_blue + self.no_red
     }
 
-    fn new(
+    /// Places the agents at random on a periodic lattice, 10 by 10 unless more cells are needed
+    /// to hold all the agents.
+    pub fn new(
         no_blue: usize,
         no_red: usize,
         m_red: usize,
@@ -116,16 +118,28 @@ impl Model {
         j_red: f64,
         j_blue: f64,
     ) -> Self {
-        let lattice_size = 10;
+        let lattice_size = (10..).find(|size| size * size >= no_blue + no_red).unwrap();
+        Self::with_lattice_size(lattice_size, no_blue, no_red, m_red, m_blue, j_red, j_blue)
+    }
 
+    /// Places the agents at random on a periodic `lattice_size` by `lattice_size` lattice.
+    pub fn with_lattice_size(
+        lattice_size: usize,
+        no_blue: usize,
+        no_red: usize,
+        m_red: usize,
+        m_blue: usize,
+        j_red: f64,
+        j_blue: f64,
+    ) -> Self {
         assert!(lattice_size * lattice_size >= no_blue + no_red);
 
         // FIXME: incorporate m_t closest neighbours
         assert_eq!(m_red, 8);
         assert_eq!(m_blue, 8);
 
-        assert!(0.1 <= j_red && j_red <= 0.9);
-        assert!(0.1 <= j_blue && j_blue <= 0.9);
+        assert!((0.1..=0.9).contains(&j_red));
+        assert!((0.1..=0.9).contains(&j_blue));
 
         let j_red: usize = (j_red * m_red as f64) as usize;
         let j_blue: usize = (j_blue * m_blue as f64) as usize;
@@ -158,13 +172,13 @@ impl Model {
             // be a list of references to those agents?
             *cell = Some(Agent {
                 position: (x as isize, y as isize),
-                mark: mark,
+                mark,
                 moving: true,
             });
         }
 
         // The update-scheme first starts off with the red individuals and onto the blue individuals
-        Self {
+        let mut model = Self {
             no_blue,
             no_red,
             m_red,
@@ -173,10 +187,13 @@ impl Model {
             j_blue,
             lattice,
             //            agents,
-        }
+        };
+        model.refresh_moving();
+        model
     }
 
     /// TODO: Add range of cells where it is considered neighbours
+    #[allow(clippy::single_range_in_vec_init)]
     fn closest_neighbours(&self, position: (isize, isize)) -> Vec<Option<Agent>> {
         use itertools::iproduct;
         use ndarray::s;
@@ -201,10 +218,10 @@ impl Model {
 
         let intervalsx;
         let intervalsy;
-        let leftx = idx as isize - radius as isize;
-        let rightx = idx as isize + radius as isize + 1;
-        let lefty = idy as isize - radius as isize;
-        let righty = idy as isize + radius as isize + 1;
+        let leftx = idx - radius;
+        let rightx = idx + radius + 1;
+        let lefty = idy - radius;
+        let righty = idy + radius + 1;
 
         if leftx < 0 {
             intervalsx = vec![0..rightx as usize, (n + leftx) as usize..n as usize];
@@ -234,179 +251,165 @@ impl Model {
             .collect_vec()
     }
 
-    fn run(&mut self) {
-        let mut no_iterations = 0;
-
-        let mark_count = |model: Self| {
-            model
-                .mark_lattice()
-                .iter()
-                .map(|x| match x {
-                    Mark::None => 0,
-                    _ => 1,
-                })
-                .sum::<usize>()
-        };
+    /// Number of the agents next to `agent` with the same mark.
+    fn same_type_neighbours(&self, agent: &Agent) -> usize {
+        self.closest_neighbours(agent.position)
+            .into_iter()
+            .filter(|neighbour| match neighbour {
+                Some(neighbour) => neighbour.mark == agent.mark,
+                None => false,
+            })
+            .count()
+            - 1 // subtract origin
+    }
 
-        dbg!(mark_count(self.clone()));
-        //        while self
-        //            .lattice
-        //            .iter()
-        //            .filter_map(|x| match x {
-        //                None => None,
-        //                Some(a) if a.moving => Some(true),
-        //                _ => Some(false),
-        //            })
-        //            .clone()
-        //            .any(identity)
-        //        {
-        for _iter in 0..MAX_ITERATIONS {
-            self.update_moving_agent();
-            no_iterations += 1;
-
-            if no_iterations % 25 == 0 {
-                println!("Iteration: {:<5}", no_iterations);
-                dbg!(mark_count(self.clone()));
-            }
+    /// Share of the `m` closest neighbours with the same mark that an agent of `mark` needs to
+    /// be happy.
+    pub fn tolerance(&self, mark: Mark) -> f64 {
+        match mark {
+            Mark::None => panic!("there are no agents without a mark"),
+            Mark::Blue => self.j_blue as f64 / self.m_blue as f64,
+            Mark::Red => self.j_red as f64 / self.m_red as f64,
+        }
+    }
+
+    fn is_happy(&self, agent: &Agent) -> bool {
+        let threshold = match agent.mark {
+            Mark::None => unreachable!("agent is not assigned type"),
+            Mark::Blue => self.j_blue,
+            Mark::Red => self.j_red,
+        };
+        self.same_type_neighbours(agent) >= threshold
+    }
 
-            if no_iterations == MAX_ITERATIONS {
-                break;
+    /// Marks the unhappy agents as moving, and the others as settled.
+    fn refresh_moving(&mut self) {
+        let moving = self
+            .lattice
+            .indexed_iter()
+            .filter_map(|(position, cell)| cell.map(|agent| (position, !self.is_happy(&agent))))
+            .collect_vec();
+        for (position, moving) in moving {
+            if let Some(agent) = &mut self.lattice[position] {
+                agent.moving = moving;
             }
         }
-        dbg!(mark_count(self.clone()));
     }
 
-    fn update_moving_agent(&mut self) {
-        //        let present_lattice = self.lattice.clone();
-        use ndarray::s;
-        let mut rc_lattice = Rc::new(RefCell::new(self.lattice.clone()));
-        let moving_agent: &mut Agent = *rc_lattice
-            .borrow_mut()
-            .iter_mut()
-            .filter_map(|cell| match cell {
-                Some(ref mut agent) if agent.moving => Some(agent),
-                _ => None,
-            })
-            .next()
-            .expect("no moving agents available");
+    fn move_agent(&mut self, agent: Agent, new_location: (usize, usize)) {
+        let previous_location = (agent.position.0 as usize, agent.position.1 as usize);
+        assert!(self.lattice[previous_location].is_some());
+        assert!(self.lattice[new_location].is_none());
+        self.lattice[previous_location] = None;
+        self.lattice[new_location] = Some(Agent {
+            position: (new_location.0 as isize, new_location.1 as isize),
+            ..agent
+        });
+    }
 
-        let empty_position = Rc::get_mut(&mut rc_lattice)
-            .unwrap()
+    /// Moves a random moving agent to a random empty cell. Returns false if every agent has
+    /// settled.
+    pub fn update_moving_agent(&mut self) -> bool {
+        let mut rng = thread_rng();
+        let moving_agent = match self
+            .lattice
+            .iter()
+            .flatten()
+            .filter(|agent| agent.moving)
+            .choose(&mut rng)
+        {
+            Some(agent) => *agent,
+            None => return false,
+        };
+        let empty_position = self
+            .lattice
             .indexed_iter()
-            .filter_map(|(pos, x)| match x {
-                None => Some(pos),
-                Some(_) => None,
-            })
-            .choose(&mut thread_rng())
+            .filter(|(_, cell)| cell.is_none())
+            .map(|(position, _)| position)
+            .choose(&mut rng)
             .expect("no empty cells available");
 
-        //dbg!(&moving_agent);
-        //dbg!(empty_position);
-
-        let same_type_neighbours: isize = self
-            .closest_neighbours(moving_agent.clone().position)
-            .into_iter()
-            .map(|x| match x {
-                None => 0,
-                Some(neighbour_agent) if neighbour_agent.mark == moving_agent.mark => 1,
-                Some(_) => 0,
-            })
-            .sum();
-        let same_type_neighbours = same_type_neighbours - 1; // subtract origin
-                                                             //dbg!(same_type_neighbours);
-
-        let previous_location = (
-            moving_agent.position.0 as usize,
-            moving_agent.position.1 as usize,
-        );
-        moving_agent.position = (empty_position.0 as isize, empty_position.1 as isize);
-
-        dbg!(previous_location, empty_position);
-        dbg!(&self.lattice.get(previous_location).unwrap());
-        dbg!(&self.lattice.get(empty_position).unwrap());
-
-        assert!(self.lattice.get(previous_location).unwrap().is_some());
-        assert!(self.lattice.get(empty_position).unwrap().is_none());
+        self.move_agent(moving_agent, empty_position);
+        self.refresh_moving();
+        true
+    }
 
-        match moving_agent.mark {
-            Mark::None => unreachable!("agent is not assigned type"),
-            Mark::Blue => {
-                if same_type_neighbours > self.j_blue as isize {
-                    //                    print!("Moved. ");
-                    *self
-                        .lattice
-                        .get_mut(empty_position)
-                        .expect("new position was not empty") = Some(*moving_agent);
-                    *self
-                        .lattice
-                        .get_mut(previous_location)
-                        .expect("previous location was empty already") = None;
-                } else if same_type_neighbours == self.j_blue as isize {
-                    // settle individual
-                    // print!("Settled. ");
-                    moving_agent.moving = false;
-                }
+    /// Runs until every agent is happy, or for at most [`MAX_ITERATIONS`] sweeps. Returns the
+    /// number of sweeps.
+    pub fn run(&mut self) -> usize {
+        for iteration in 0..MAX_ITERATIONS {
+            if self.update() == 0 {
+                return iteration;
             }
-            Mark::Red => {
-                if same_type_neighbours > self.j_red as isize {
-                    *self.lattice.get_mut(empty_position).unwrap() = Some(*moving_agent);
-                    *self
-                        .lattice
-                        .get_mut(previous_location)
-                        .expect("previous location was empty already") = None;
-                } else if same_type_neighbours == self.j_red as isize {
-                    // settle individual
-                    // print!("Settled. ");
-                    moving_agent.moving = false;
-                }
+        }
+        MAX_ITERATIONS
+    }
+
+    /// Moves every unhappy agent, in random order, to a random empty cell. Returns the number of
+    /// agents that moved.
+    pub fn update(&mut self) -> usize {
+        let mut rng = thread_rng();
+        let mut agents = self.lattice.iter().flatten().copied().collect_vec();
+        agents.shuffle(&mut rng);
+        let mut empty_positions = self
+            .lattice
+            .indexed_iter()
+            .filter(|(_, cell)| cell.is_none())
+            .map(|(position, _)| position)
+            .collect_vec();
+        assert!(!empty_positions.is_empty(), "no empty cells available");
+
+        let mut moved = 0;
+        for agent in agents {
+            if self.is_happy(&agent) {
+                continue;
             }
+            let previous_location = (agent.position.0 as usize, agent.position.1 as usize);
+            let empty_position = empty_positions
+                .iter_mut()
+                .choose(&mut rng)
+                .expect("no empty cells available");
+            let new_location = *empty_position;
+            *empty_position = previous_location;
+
+            self.move_agent(agent, new_location);
+            moved += 1;
         }
-        //print!("\n")
+        self.refresh_moving();
+        moved
     }
 
     /// Similar neighbor index
-    fn segregation_index(&self) -> f64 {
+    pub fn segregation_index(&self) -> f64 {
         // for all individuals of a certain type, find the number of their neighbors that are of
         // the same type, and average over this.
 
         self.lattice
             .iter()
-            .filter(|x: &&Option<Agent>| x.is_some())
-            .clone()
-            .map(
-                |some_agent| {
-                    let agent = some_agent.clone().unwrap();
-                    self.closest_neighbours(agent.position)
-                        .iter()
-                        .map(|x| match x {
-                            None => 0.,
-                            Some(ref a) => {
-                                if a.mark == agent.mark {
-                                    1.
-                                } else {
-                                    0.
-                                }
-                            }
-                        })
-                        //.map(|x| if *x == agent.mark { 1. } else { 0. })
-                        //.map(|x| if agent.mark == x.mark { 1. } else { 0. })
-                        .sum::<f64>()
-                        / self.m_red as f64
-                }, //FIXME: no way to select m based on Mark
-            )
+            .flatten()
+            .map(|agent| {
+                let m = match agent.mark {
+                    Mark::None => unreachable!("agent is not assigned type"),
+                    Mark::Blue => self.m_blue,
+                    Mark::Red => self.m_red,
+                };
+                self.same_type_neighbours(agent) as f64 / m as f64
+            })
             .sum::<f64>()
             / self.no_agents() as f64
     }
 
-    //    fn agents(&mut self) -> Vec<&mut Agent> {
-    //        self.lattice.iter_mut().flat_map(|x| x).collect()
-    //    }
-
-    //    fn moving_agents(&mut self) -> impl Iterator<Item = &'static Agent> {
-    //        self.lattice.iter_mut().flat_map(|x| x).filter(|x| x.moving)
-    //    }
+    /// Share of the agents that are happy where they are.
+    pub fn happy_fraction(&self) -> f64 {
+        self.lattice
+            .iter()
+            .flatten()
+            .filter(|agent| self.is_happy(agent))
+            .count() as f64
+            / self.no_agents() as f64
+    }
 
-    fn mark_lattice(&self) -> Array2<Mark> {
+    pub fn mark_lattice(&self) -> Array2<Mark> {
         self.lattice.mapv(|x| match x {
             None => Mark::None,
             Some(a) => a.mark,
@@ -430,6 +433,31 @@ fn baseline_model() {
 }
 
 #[test]
+fn agents_settle_into_segregated_neighbourhoods() {
+    let mut model = Model::with_lattice_size(20, 150, 150, 8, 8, 0.5, 0.5);
+    let initial_index = model.segregation_index();
+    let sweeps = model.run();
+    assert!(sweeps < MAX_ITERATIONS);
+    assert_eq!(model.happy_fraction(), 1.);
+    assert_eq!(model.lattice.iter().flatten().count(), 300);
+    assert!(model.segregation_index() > initial_index + 0.1);
+}
+
+#[test]
+fn moving_agents_one_at_a_time() {
+    let mut model = Model::with_lattice_size(20, 100, 100, 8, 8, 0.4, 0.4);
+    let mut moves = 0;
+    while model.update_moving_agent() {
+        moves += 1;
+        assert!(moves < 100_000, "the agents never settled");
+    }
+    assert_eq!(model.happy_fraction(), 1.);
+    assert!(model.lattice.iter().flatten().all(|agent| !agent.moving));
+    assert_eq!(model.lattice.iter().flatten().count(), 200);
+}
+
+#[test]
+#[allow(unused_imports)]
 fn example_runs() {
     let mut sketch_model = Model::new(50, 25, 8, 8, 0.5, 0.5);
     use ndarray::prelude::array;
@@ -576,6 +604,8 @@ fn example() {
 
 /// Returns all the neighbours with periodic boundary condition, including the
 /// origin.
+#[cfg(test)]
+#[allow(clippy::single_range_in_vec_init)]
 fn find_all_periodic_boundary_neighbours_2d<T: Clone>(
     lattice: Array2<T>,
     neighbourhood: Neighbourhood,
@@ -627,6 +657,7 @@ fn find_all_periodic_boundary_neighbours_2d<T: Clone>(
 }
 
 #[test]
+#[allow(unused_variables)]
 fn figuring_out_boundary_slicing() {
     let n = 5;
     let arr = Array2::from_shape_vec((5, 5), (0..5_i32.pow(2)).collect_vec()).unwrap_or_default();
@@ -648,6 +679,7 @@ fn figuring_out_boundary_slicing() {
 /// I.e. one is a neighbour to thyself.
 /// One could remove the element from its neighbour-slice.
 /// Currently, we remove the origin from each neighbourhood.
+#[cfg(test)]
 fn find_all_periodic_neighbours<T: Clone>(
     lattice: &[T],
     neighbourhood: Neighbourhood,
@@ -692,14 +724,14 @@ fn find_all_periodic_neighbours<T: Clone>(
             }
             intervals
                 .into_iter()
-                .map(|x| lattice.get(x).clone().unwrap_or_default().to_vec())
-                .flatten()
+                .flat_map(|x| lattice.get(x).unwrap_or_default().to_vec())
                 .collect_vec()
         })
         .collect_vec()
 }
 
 #[test]
+#[allow(unused_imports)]
 fn one_dim_periodic_boundary_neighbours() {
     let lattice = vec![43, 24, 10, 20, 4];
     let neighbourhood = find_all_periodic_neighbours(&lattice, Neighbourhood::Radius(1));
@@ -1,11 +1,10 @@
+//! Source: [Assignment 1](http://prac.im.pwr.wroc.pl/~szwabin/assets/abm/labs/l1.pdf)
 use ndarray::Array2;
 use ndarray_rand::RandomExt;
 use rayon::prelude::*;
 use std::collections::HashMap;
 use std::fmt::{Display, Error, Formatter};
 
-///! Source: [Assignment 1](http://prac.im.pwr.wroc.pl/~szwabin/assets/abm/labs/l1.pdf)
-
 #[derive(Debug, Clone)]
 pub struct Forrest {
     cells: ndarray::Array2<TreeState>,
@@ -13,8 +12,9 @@ pub struct Forrest {
     size: usize,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub enum TreeState {
+    #[default]
     None,
     Tree,
     Burning,
@@ -39,14 +39,50 @@ impl Forrest {
         &self.cells
     }
 
+    pub fn vegetation_probability(&self) -> f64 {
+        self.vegetation_probability
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Share of the cells with [`TreeState::Tree`].
+    pub fn tree_fraction(&self) -> f64 {
+        self.cells.iter().filter(|x| **x == TreeState::Tree).count() as f64
+            / self.cells.len() as f64
+    }
+
+    /// Sets the trees in the left most column on fire and lets the fire spread until it dies out.
+    /// Returns the number of updates the fire lasted.
+    pub fn burn(&mut self) -> usize {
+        self.ignite_left_column();
+        let mut duration = 0;
+        while !self.no_fire() {
+            self.update();
+            duration += 1;
+        }
+        duration
+    }
+
+    fn ignite_left_column(&mut self) {
+        self.cells.column_mut(0).mapv_inplace(|x| {
+            if let TreeState::Tree = x {
+                TreeState::Burning
+            } else {
+                x
+            }
+        });
+    }
+
     /// TODO: Add an argument so we can count [`TreeState::Burning`] as well as [`TreeState::Tree`].
-    pub(crate) fn no_clusters(&self) -> usize {
+    pub fn no_clusters(&self) -> usize {
         hoshen_kopelman::Raster::from(self.clone())
             .raster_scan()
             .no_clusters()
     }
 
-    pub(crate) fn cluster_sizes(&self) -> HashMap<usize, usize> {
+    pub fn cluster_sizes(&self) -> HashMap<usize, usize> {
         hoshen_kopelman::Raster::from(self.clone())
             .raster_scan()
             .labels_array()
@@ -63,14 +99,7 @@ impl Forrest {
 
     /// Returns true if there are no more cells with [`TreeState::Burning`].
     fn no_fire(&self) -> bool {
-        let mut no_fire = true;
-        self.cells.visit(|x| {
-            if let TreeState::Burning = x {
-                no_fire = false;
-                return;
-            }
-        });
-        no_fire
+        !self.cells.iter().any(|x| *x == TreeState::Burning)
     }
     fn update(&mut self) {
         // it is only necessary to count the neighbours of cells with trees in them.
@@ -87,7 +116,7 @@ impl Forrest {
                 let r_start = if r == 0 { r } else { r - 1 };
                 for r_neigh in r_start..r + 2 {
                     let c_start = if c == 0 { c } else { c - 1 };
-                    for c_neigh in c_start..r + 2 {
+                    for c_neigh in c_start..c + 2 {
                         if (r_neigh, c_neigh) == (r, c) {
                             continue;
                         }
@@ -103,12 +132,6 @@ impl Forrest {
     }
 }
 
-impl Default for TreeState {
-    fn default() -> Self {
-        TreeState::None
-    }
-}
-
 impl Display for TreeState {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         write!(
@@ -149,13 +172,7 @@ pub fn percolation_threshold(grid_size: usize, tree_density: f64, max_iter: usiz
             let mut run = Forrest::new(grid_size, tree_density);
             //        println!("Initial grid: \n {}", run.cells);
 
-            run.cells.column_mut(0).mapv_inplace(|x| {
-                if let TreeState::Tree = x {
-                    TreeState::Burning
-                } else {
-                    x
-                }
-            });
+            run.ignite_left_column();
             //        println!("Left-side trees burning: \n {}", run.cells);
 
             loop {
@@ -183,6 +200,22 @@ pub fn percolation_threshold(grid_size: usize, tree_density: f64, max_iter: usiz
     //    fire_pass_throughs as f64 / max_iter as f64
 }
 
+#[test]
+fn fire_spreads_to_every_neighbour() {
+    // In a full forest the fire burns every tree, one column per update. Neighbours used to be
+    // looked up in columns `c - 1..r + 2`, so trees right of the diagonal never caught fire.
+    let mut forest = Forrest::new(10, 1.);
+    assert_eq!(forest.tree_fraction(), 1.);
+    assert_eq!(forest.burn(), 10);
+    assert_eq!(forest.tree_fraction(), 0.);
+
+    // A single row only burns through its columns.
+    let mut row = Forrest::new(10, 0.);
+    row.cells.row_mut(0).fill(TreeState::Tree);
+    assert_eq!(row.burn(), 10);
+    assert_eq!(row.tree_fraction(), 0.);
+}
+
 #[test]
 fn percolation_one_at_a_time() {
     let grid_size = 10;
@@ -199,10 +232,10 @@ fn percolation_one_at_a_time() {
 #[test]
 #[ignore]
 fn percolation_bunch() {
-    for grid_size in vec![20, 50, 100] {
-        for tree_density in vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9] {
+    for grid_size in [20, 50, 100] {
+        for tree_density in [0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9] {
             //            for max_iter in vec![1, 10, 20, 50, 100, 250] {
-            for max_iter in vec![1, 10, 20] {
+            for max_iter in [1, 10, 20] {
                 //TODO: improve this, by just adding to the already gathered simulations
                 let perco_thres_estimate = percolation_threshold(grid_size, tree_density, max_iter);
                 println!(
@@ -267,7 +300,7 @@ mod hoshen_kopelman {
             &self.label
         }
 
-        pub(crate) fn no_clusters(&self) -> usize {
+        pub fn no_clusters(&self) -> usize {
             self.label.fold(0usize, |acc, x| acc.max(*x))
         }
 
@@ -1,5 +1,993 @@
-// Hierachical bounding volumes
-// Grids
-// Octrees
-// K-d trees
-// BSP trees
+//! Neighbour search for agents in continuous space, in any number of dimensions.
+//!
+//! - Grids: [`UniformGrid`], a cell list, optionally periodic.
+//! - K-d trees: [`KdTree`].
+//! - Octrees: [`Orthtree`], a quadtree in the plane and an octree in space.
+//!
+//! Hierarchical bounding volumes and BSP trees are not implemented.
+//!
+//! Points are identified by a `usize`, e.g. the index of an agent, and can be moved with
+//! `update` as the agents move. [`SpatialIndex`] wraps the three structures.
+use itertools::Itertools;
+use ndarray::{Array1, ArrayView1};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+
+type Numeric = f64;
+pub type Point = Array1<Numeric>;
+
+fn squared_distance(a: ArrayView1<Numeric>, b: ArrayView1<Numeric>) -> Numeric {
+    a.iter().zip(b).map(|(x, y)| (x - y).powi(2)).sum()
+}
+
+/// Total order on distances, for use in heaps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Distance(Numeric);
+
+impl Eq for Distance {}
+
+impl PartialOrd for Distance {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Distance {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0
+            .partial_cmp(&other.0)
+            .expect("distances are never NaN")
+    }
+}
+
+/// Which of the structures to use in a [`SpatialIndex`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Structure {
+    Grid,
+    KdTree,
+    Orthtree,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpatialIndex {
+    Grid(UniformGrid),
+    KdTree(KdTree),
+    Orthtree(Orthtree),
+}
+
+impl SpatialIndex {
+    /// An empty index, where `scale` is the typical query radius. It is the cell size of a grid
+    /// and the initial extent of an orthtree.
+    pub fn new(structure: Structure, dimension: usize, scale: Numeric) -> Self {
+        match structure {
+            Structure::Grid => SpatialIndex::Grid(UniformGrid::new(scale)),
+            Structure::KdTree => SpatialIndex::KdTree(KdTree::new(dimension)),
+            Structure::Orthtree => SpatialIndex::Orthtree(Orthtree::new(
+                Array1::zeros(dimension),
+                Array1::from_elem(dimension, scale),
+                8,
+            )),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            SpatialIndex::Grid(grid) => grid.len(),
+            SpatialIndex::KdTree(tree) => tree.len(),
+            SpatialIndex::Orthtree(tree) => tree.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn insert(&mut self, id: usize, point: Point) {
+        match self {
+            SpatialIndex::Grid(grid) => grid.insert(id, point),
+            SpatialIndex::KdTree(tree) => tree.insert(id, point),
+            SpatialIndex::Orthtree(tree) => tree.insert(id, point),
+        }
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Point> {
+        match self {
+            SpatialIndex::Grid(grid) => grid.remove(id),
+            SpatialIndex::KdTree(tree) => tree.remove(id),
+            SpatialIndex::Orthtree(tree) => tree.remove(id),
+        }
+    }
+
+    pub fn update(&mut self, id: usize, point: Point) {
+        match self {
+            SpatialIndex::Grid(grid) => grid.update(id, point),
+            SpatialIndex::KdTree(tree) => tree.update(id, point),
+            SpatialIndex::Orthtree(tree) => tree.update(id, point),
+        }
+    }
+
+    pub fn within_radius(&self, centre: ArrayView1<Numeric>, radius: Numeric) -> Vec<usize> {
+        match self {
+            SpatialIndex::Grid(grid) => grid.within_radius(centre, radius),
+            SpatialIndex::KdTree(tree) => tree.within_radius(centre, radius),
+            SpatialIndex::Orthtree(tree) => tree.within_radius(centre, radius),
+        }
+    }
+
+    pub fn nearest(&self, centre: ArrayView1<Numeric>, k: usize) -> Vec<usize> {
+        match self {
+            SpatialIndex::Grid(grid) => grid.nearest(centre, k),
+            SpatialIndex::KdTree(tree) => tree.nearest(centre, k),
+            SpatialIndex::Orthtree(tree) => tree.nearest(centre, k),
+        }
+    }
+}
+
+/// Cell list: space is cut into cubes of side at least `cell_size`, and a radius query only
+/// looks at the cells that the ball overlaps. Best when the query radius is about the cell
+/// size and the points are spread evenly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UniformGrid {
+    cell_size: Numeric,
+    /// Number of cells along each axis and the size of the periodic box, if any.
+    period: Option<(Vec<i64>, Point)>,
+    cells: HashMap<Vec<i64>, Vec<usize>>,
+    points: HashMap<usize, Point>,
+}
+
+impl UniformGrid {
+    pub fn new(cell_size: Numeric) -> Self {
+        assert!(
+            cell_size > 0. && cell_size.is_finite(),
+            "the cell size must be positive and finite: {}",
+            cell_size
+        );
+        Self {
+            cell_size,
+            period: None,
+            cells: HashMap::new(),
+            points: HashMap::new(),
+        }
+    }
+
+    /// A grid on the box from the origin to `size` with opposite sides identified. Distances
+    /// are measured across the edges.
+    pub fn periodic(cell_size: Numeric, size: Point) -> Self {
+        assert!(
+            size.iter().all(|length| *length > 0. && length.is_finite()),
+            "the box must have a positive and finite size: {}",
+            size
+        );
+        let counts = size
+            .iter()
+            .map(|length| ((length / cell_size).floor() as i64).max(1))
+            .collect();
+        Self {
+            period: Some((counts, size)),
+            ..Self::new(cell_size)
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Point> {
+        self.points.get(&id)
+    }
+
+    fn cell_width(&self, axis: usize) -> Numeric {
+        match &self.period {
+            Some((counts, size)) => size[axis] / counts[axis] as Numeric,
+            None => self.cell_size,
+        }
+    }
+
+    fn cell_of(&self, point: ArrayView1<Numeric>) -> Vec<i64> {
+        point
+            .iter()
+            .enumerate()
+            .map(|(axis, x)| {
+                let cell = (x / self.cell_width(axis)).floor() as i64;
+                match &self.period {
+                    Some((counts, _)) => cell.rem_euclid(counts[axis]),
+                    None => cell,
+                }
+            })
+            .collect()
+    }
+
+    pub fn distance(&self, a: ArrayView1<Numeric>, b: ArrayView1<Numeric>) -> Numeric {
+        match &self.period {
+            Some((_, size)) => a
+                .iter()
+                .zip(b)
+                .zip(size)
+                .map(|((x, y), length)| {
+                    let d = x - y;
+                    (d - length * (d / length).round()).powi(2)
+                })
+                .sum::<Numeric>()
+                .sqrt(),
+            None => squared_distance(a, b).sqrt(),
+        }
+    }
+
+    pub fn insert(&mut self, id: usize, point: Point) {
+        if let Some(previous) = self.points.get(&id) {
+            panic!("point {} is already in the grid at {}", id, previous);
+        }
+        let cell = self.cell_of(point.view());
+        self.cells.entry(cell).or_default().push(id);
+        self.points.insert(id, point);
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Point> {
+        let point = self.points.remove(&id)?;
+        let cell = self.cell_of(point.view());
+        let ids = self.cells.get_mut(&cell).expect("every point is in a cell");
+        ids.retain(|&other| other != id);
+        if ids.is_empty() {
+            self.cells.remove(&cell);
+        }
+        Some(point)
+    }
+
+    /// Moves point `id`; cheap when it stays in the same cell.
+    pub fn update(&mut self, id: usize, point: Point) {
+        let same_cell = match self.points.get(&id) {
+            Some(previous) => self.cell_of(previous.view()) == self.cell_of(point.view()),
+            None => false,
+        };
+        if same_cell {
+            self.points.insert(id, point);
+        } else {
+            self.remove(id);
+            self.insert(id, point);
+        }
+    }
+
+    pub fn within_radius(&self, centre: ArrayView1<Numeric>, radius: Numeric) -> Vec<usize> {
+        // the cells that the ball overlaps along each axis, at most one period of them
+        let ranges: Vec<(i64, i64)> = (0..centre.len())
+            .map(|axis| {
+                let width = self.cell_width(axis);
+                let low = ((centre[axis] - radius) / width).floor() as i64;
+                let high = ((centre[axis] + radius) / width).floor() as i64;
+                match &self.period {
+                    Some((counts, _)) if high.saturating_sub(low) >= counts[axis] - 1 => {
+                        (0, counts[axis] - 1)
+                    }
+                    _ => (low, high),
+                }
+            })
+            .collect();
+        let is_close = |id: &usize| self.distance(centre, self.points[id].view()) <= radius;
+        let no_cells = ranges.iter().fold(1_usize, |product, (low, high)| {
+            let width = high.saturating_sub(*low).saturating_add(1).max(0) as usize;
+            product.saturating_mul(width)
+        });
+        if no_cells > self.cells.len() {
+            // The ball covers more cells than are occupied.
+            return self.points.keys().copied().filter(is_close).collect();
+        }
+        let axes = ranges.into_iter().enumerate().map(|(axis, (low, high))| {
+            (low..=high)
+                .map(|cell| match &self.period {
+                    Some((counts, _)) => cell.rem_euclid(counts[axis]),
+                    None => cell,
+                })
+                .collect::<Vec<_>>()
+        });
+        axes.multi_cartesian_product()
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .filter(is_close)
+            .collect()
+    }
+
+    /// The `k` points closest to `centre`, closest first.
+    pub fn nearest(&self, centre: ArrayView1<Numeric>, k: usize) -> Vec<usize> {
+        let mut radius = self.cell_size;
+        loop {
+            let mut found = self.within_radius(centre, radius);
+            if found.len() >= k || found.len() == self.len() {
+                found.sort_by_key(|id| Distance(self.distance(centre, self.points[id].view())));
+                found.truncate(k);
+                return found;
+            }
+            radius *= 2.;
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct KdNode {
+    id: usize,
+    point: Point,
+    axis: usize,
+    left: Option<usize>,
+    right: Option<usize>,
+    removed: bool,
+}
+
+/// Each node splits space in two along one axis, cycling through the axes. Removed points are
+/// only marked as such, and the tree is rebuilt, balanced, once it has changed by as many
+/// points as it holds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KdTree {
+    dimension: usize,
+    nodes: Vec<KdNode>,
+    root: Option<usize>,
+    /// Node of each point that hasn't been removed.
+    index: HashMap<usize, usize>,
+    changes: usize,
+}
+
+impl KdTree {
+    pub fn new(dimension: usize) -> Self {
+        assert!(dimension > 0, "a k-d tree needs at least one dimension");
+        Self {
+            dimension,
+            nodes: Vec::new(),
+            root: None,
+            index: HashMap::new(),
+            changes: 0,
+        }
+    }
+
+    /// A balanced tree, splitting at the median.
+    pub fn build(dimension: usize, points: Vec<(usize, Point)>) -> Self {
+        let mut tree = Self::new(dimension);
+        tree.rebuild(points);
+        tree
+    }
+
+    fn rebuild(&mut self, mut points: Vec<(usize, Point)>) {
+        self.nodes.clear();
+        self.index.clear();
+        self.changes = 0;
+        self.root = self.build_subtree(&mut points, 0);
+    }
+
+    fn build_subtree(&mut self, points: &mut [(usize, Point)], depth: usize) -> Option<usize> {
+        if points.is_empty() {
+            return None;
+        }
+        let axis = depth % self.dimension;
+        points.sort_by_key(|(_, point)| Distance(point[axis]));
+        let median = points.len() / 2;
+        let (left, rest) = points.split_at_mut(median);
+        let (median, right) = rest.split_first_mut().unwrap();
+        let node = self.nodes.len();
+        self.nodes.push(KdNode {
+            id: median.0,
+            point: median.1.clone(),
+            axis,
+            left: None,
+            right: None,
+            removed: false,
+        });
+        self.index.insert(median.0, node);
+        self.nodes[node].left = self.build_subtree(left, depth + 1);
+        self.nodes[node].right = self.build_subtree(right, depth + 1);
+        Some(node)
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Point> {
+        self.index.get(&id).map(|&node| &self.nodes[node].point)
+    }
+
+    fn points(&self) -> Vec<(usize, Point)> {
+        self.index
+            .iter()
+            .map(|(&id, &node)| (id, self.nodes[node].point.clone()))
+            .collect()
+    }
+
+    fn changed(&mut self) {
+        self.changes += 1;
+        if self.changes > self.len().max(16) {
+            let points = self.points();
+            self.rebuild(points);
+        }
+    }
+
+    pub fn insert(&mut self, id: usize, point: Point) {
+        assert_eq!(point.len(), self.dimension);
+        assert!(
+            !self.index.contains_key(&id),
+            "point {} is already in the tree",
+            id
+        );
+        let mut parent = match self.root {
+            Some(root) => root,
+            None => {
+                self.nodes.push(KdNode {
+                    id,
+                    point,
+                    axis: 0,
+                    left: None,
+                    right: None,
+                    removed: false,
+                });
+                self.root = Some(self.nodes.len() - 1);
+                self.index.insert(id, self.nodes.len() - 1);
+                return;
+            }
+        };
+        let node = self.nodes.len();
+        loop {
+            let KdNode { axis, .. } = self.nodes[parent];
+            let child = if point[axis] < self.nodes[parent].point[axis] {
+                &mut self.nodes[parent].left
+            } else {
+                &mut self.nodes[parent].right
+            };
+            match *child {
+                Some(next) => parent = next,
+                None => {
+                    *child = Some(node);
+                    self.nodes.push(KdNode {
+                        id,
+                        point,
+                        axis: (axis + 1) % self.dimension,
+                        left: None,
+                        right: None,
+                        removed: false,
+                    });
+                    break;
+                }
+            }
+        }
+        self.index.insert(id, node);
+        self.changed();
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Point> {
+        let node = self.index.remove(&id)?;
+        self.nodes[node].removed = true;
+        let point = self.nodes[node].point.clone();
+        self.changed();
+        Some(point)
+    }
+
+    pub fn update(&mut self, id: usize, point: Point) {
+        self.remove(id);
+        self.insert(id, point);
+    }
+
+    pub fn within_radius(&self, centre: ArrayView1<Numeric>, radius: Numeric) -> Vec<usize> {
+        let mut found = Vec::new();
+        let mut stack: Vec<usize> = self.root.into_iter().collect();
+        while let Some(node) = stack.pop() {
+            let KdNode {
+                id,
+                point,
+                axis,
+                left,
+                right,
+                removed,
+            } = &self.nodes[node];
+            if !removed && squared_distance(centre, point.view()) <= radius * radius {
+                found.push(*id);
+            }
+            let difference = centre[*axis] - point[*axis];
+            if difference - radius < 0. {
+                stack.extend(left);
+            }
+            if difference + radius >= 0. {
+                stack.extend(right);
+            }
+        }
+        found
+    }
+
+    /// The `k` points closest to `centre`, closest first.
+    pub fn nearest(&self, centre: ArrayView1<Numeric>, k: usize) -> Vec<usize> {
+        // Max-heap of the best so far, so the worst of them can be replaced.
+        let mut best: BinaryHeap<(Distance, usize)> = BinaryHeap::new();
+        if k > 0 {
+            self.nearest_in(self.root, centre, k, &mut best);
+        }
+        best.into_sorted_vec()
+            .into_iter()
+            .map(|(_, id)| id)
+            .collect()
+    }
+
+    fn nearest_in(
+        &self,
+        node: Option<usize>,
+        centre: ArrayView1<Numeric>,
+        k: usize,
+        best: &mut BinaryHeap<(Distance, usize)>,
+    ) {
+        let node = match node {
+            Some(node) => &self.nodes[node],
+            None => return,
+        };
+        if !node.removed {
+            let distance = Distance(squared_distance(centre, node.point.view()));
+            if best.len() < k {
+                best.push((distance, node.id));
+            } else if distance < best.peek().unwrap().0 {
+                best.pop();
+                best.push((distance, node.id));
+            }
+        }
+        let difference = centre[node.axis] - node.point[node.axis];
+        let (near, far) = if difference < 0. {
+            (node.left, node.right)
+        } else {
+            (node.right, node.left)
+        };
+        self.nearest_in(near, centre, k, best);
+        if best.len() < k || Distance(difference * difference) <= best.peek().unwrap().0 {
+            self.nearest_in(far, centre, k, best);
+        }
+    }
+}
+
+const ORTHTREE_MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, PartialEq)]
+enum OrthNode {
+    Leaf(Vec<(usize, Point)>),
+    /// `2^dimension` children; bit `k` of the index of a child is set for the upper half
+    /// along axis `k`.
+    Branch(Vec<OrthNode>),
+}
+
+/// Splits a box into `2^dimension` equal boxes when it holds more than `capacity` points, so a
+/// quadtree in the plane and an octree in space. The root box grows to take in points outside
+/// of it, and branches collapse again when points leave.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Orthtree {
+    capacity: usize,
+    min: Point,
+    max: Point,
+    root: OrthNode,
+    points: HashMap<usize, Point>,
+}
+
+fn child_bounds(min: &Point, max: &Point, child: usize) -> (Point, Point) {
+    let mut child_min = min.clone();
+    let mut child_max = max.clone();
+    for axis in 0..min.len() {
+        let middle = 0.5 * (min[axis] + max[axis]);
+        if child >> axis & 1 == 1 {
+            child_min[axis] = middle;
+        } else {
+            child_max[axis] = middle;
+        }
+    }
+    (child_min, child_max)
+}
+
+fn child_of(min: &Point, max: &Point, point: ArrayView1<Numeric>) -> usize {
+    (0..min.len())
+        .filter(|&axis| point[axis] >= 0.5 * (min[axis] + max[axis]))
+        .map(|axis| 1 << axis)
+        .sum()
+}
+
+/// Squared distance from `centre` to the nearest point of the box.
+fn box_distance(min: &Point, max: &Point, centre: ArrayView1<Numeric>) -> Numeric {
+    (0..min.len())
+        .map(|axis| {
+            (min[axis] - centre[axis])
+                .max(centre[axis] - max[axis])
+                .max(0.)
+                .powi(2)
+        })
+        .sum()
+}
+
+impl OrthNode {
+    fn insert(
+        &mut self,
+        min: &Point,
+        max: &Point,
+        capacity: usize,
+        depth: usize,
+        entry: (usize, Point),
+    ) {
+        match self {
+            OrthNode::Leaf(entries) => {
+                entries.push(entry);
+                if entries.len() > capacity && depth < ORTHTREE_MAX_DEPTH {
+                    let mut children = vec![OrthNode::Leaf(Vec::new()); 1 << min.len()];
+                    for entry in entries.drain(..) {
+                        let child = child_of(min, max, entry.1.view());
+                        let (child_min, child_max) = child_bounds(min, max, child);
+                        children[child].insert(&child_min, &child_max, capacity, depth + 1, entry);
+                    }
+                    *self = OrthNode::Branch(children);
+                }
+            }
+            OrthNode::Branch(children) => {
+                let child = child_of(min, max, entry.1.view());
+                let (child_min, child_max) = child_bounds(min, max, child);
+                children[child].insert(&child_min, &child_max, capacity, depth + 1, entry);
+            }
+        }
+    }
+
+    /// Removes point `id` at `point` and returns the number of points left below this node.
+    fn remove(
+        &mut self,
+        min: &Point,
+        max: &Point,
+        capacity: usize,
+        id: usize,
+        point: &Point,
+    ) -> usize {
+        match self {
+            OrthNode::Leaf(entries) => {
+                entries.retain(|(other, _)| *other != id);
+                entries.len()
+            }
+            OrthNode::Branch(children) => {
+                let child = child_of(min, max, point.view());
+                let (child_min, child_max) = child_bounds(min, max, child);
+                children[child].remove(&child_min, &child_max, capacity, id, point);
+                let count = children.iter().map(OrthNode::count).sum();
+                if count <= capacity {
+                    let mut entries = Vec::with_capacity(count);
+                    for child in children.iter_mut() {
+                        child.drain_into(&mut entries);
+                    }
+                    *self = OrthNode::Leaf(entries);
+                }
+                count
+            }
+        }
+    }
+
+    fn count(&self) -> usize {
+        match self {
+            OrthNode::Leaf(entries) => entries.len(),
+            OrthNode::Branch(children) => children.iter().map(OrthNode::count).sum(),
+        }
+    }
+
+    fn drain_into(&mut self, entries: &mut Vec<(usize, Point)>) {
+        match self {
+            OrthNode::Leaf(leaf) => entries.append(leaf),
+            OrthNode::Branch(children) => {
+                for child in children {
+                    child.drain_into(entries);
+                }
+            }
+        }
+    }
+}
+
+impl Orthtree {
+    /// An empty tree on the box from `min` to `max`, splitting boxes with more than `capacity`
+    /// points.
+    pub fn new(min: Point, max: Point, capacity: usize) -> Self {
+        assert_eq!(min.len(), max.len());
+        assert!(min.iter().zip(&max).all(|(low, high)| low < high));
+        Self {
+            capacity: capacity.max(1),
+            min,
+            max,
+            root: OrthNode::Leaf(Vec::new()),
+            points: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Point> {
+        self.points.get(&id)
+    }
+
+    /// Doubles the root box towards `point`, once.
+    fn grow(&mut self, point: ArrayView1<Numeric>) {
+        let mut min = self.min.clone();
+        let mut max = self.max.clone();
+        let mut old_root_child = 0;
+        for axis in 0..min.len() {
+            let width = self.max[axis] - self.min[axis];
+            if point[axis] < self.min[axis] {
+                min[axis] -= width;
+                old_root_child |= 1 << axis;
+            } else {
+                max[axis] += width;
+            }
+        }
+        let mut children = vec![OrthNode::Leaf(Vec::new()); 1 << min.len()];
+        children[old_root_child] = std::mem::replace(&mut self.root, OrthNode::Leaf(Vec::new()));
+        self.root = OrthNode::Branch(children);
+        self.min = min;
+        self.max = max;
+    }
+
+    fn contains(&self, point: ArrayView1<Numeric>) -> bool {
+        (0..self.min.len())
+            .all(|axis| self.min[axis] <= point[axis] && point[axis] < self.max[axis])
+    }
+
+    pub fn insert(&mut self, id: usize, point: Point) {
+        assert_eq!(point.len(), self.min.len());
+        assert!(
+            point.iter().all(|x| x.is_finite()),
+            "point {} is not finite: {}",
+            id,
+            point
+        );
+        while !self.contains(point.view()) {
+            self.grow(point.view());
+        }
+        if let Some(previous) = self.points.insert(id, point.clone()) {
+            panic!("point {} is already in the tree at {}", id, previous);
+        }
+        self.root
+            .insert(&self.min, &self.max, self.capacity, 0, (id, point));
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Point> {
+        let point = self.points.remove(&id)?;
+        self.root
+            .remove(&self.min, &self.max, self.capacity, id, &point);
+        Some(point)
+    }
+
+    pub fn update(&mut self, id: usize, point: Point) {
+        self.remove(id);
+        self.insert(id, point);
+    }
+
+    pub fn within_radius(&self, centre: ArrayView1<Numeric>, radius: Numeric) -> Vec<usize> {
+        let mut found = Vec::new();
+        let mut stack = vec![(&self.root, self.min.clone(), self.max.clone())];
+        while let Some((node, min, max)) = stack.pop() {
+            if box_distance(&min, &max, centre) > radius * radius {
+                continue;
+            }
+            match node {
+                OrthNode::Leaf(entries) => found.extend(
+                    entries
+                        .iter()
+                        .filter(|(_, point)| {
+                            squared_distance(centre, point.view()) <= radius * radius
+                        })
+                        .map(|(id, _)| *id),
+                ),
+                OrthNode::Branch(children) => {
+                    for (child, node) in children.iter().enumerate() {
+                        let (child_min, child_max) = child_bounds(&min, &max, child);
+                        stack.push((node, child_min, child_max));
+                    }
+                }
+            }
+        }
+        found
+    }
+
+    /// The `k` points closest to `centre`, closest first. Boxes and points are visited in order
+    /// of distance.
+    pub fn nearest(&self, centre: ArrayView1<Numeric>, k: usize) -> Vec<usize> {
+        enum Item<'a> {
+            Node(&'a OrthNode, Point, Point),
+            Point(usize),
+        }
+        // Ties are broken by insertion order, as items themselves can't be compared.
+        let mut queue = BinaryHeap::new();
+        let mut items = Vec::new();
+        let distance = box_distance(&self.min, &self.max, centre);
+        queue.push(Reverse((Distance(distance), items.len())));
+        items.push(Some(Item::Node(
+            &self.root,
+            self.min.clone(),
+            self.max.clone(),
+        )));
+
+        let mut found = Vec::new();
+        while let Some(Reverse((_, item))) = queue.pop() {
+            if found.len() == k {
+                break;
+            }
+            match items[item].take().unwrap() {
+                Item::Point(id) => found.push(id),
+                Item::Node(OrthNode::Leaf(entries), _, _) => {
+                    for (id, point) in entries {
+                        let distance = squared_distance(centre, point.view());
+                        queue.push(Reverse((Distance(distance), items.len())));
+                        items.push(Some(Item::Point(*id)));
+                    }
+                }
+                Item::Node(OrthNode::Branch(children), min, max) => {
+                    for (child, node) in children.iter().enumerate() {
+                        let (child_min, child_max) = child_bounds(&min, &max, child);
+                        let distance = box_distance(&child_min, &child_max, centre);
+                        queue.push(Reverse((Distance(distance), items.len())));
+                        items.push(Some(Item::Node(node, child_min, child_max)));
+                    }
+                }
+            }
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+fn brute_force_within_radius(
+    points: &HashMap<usize, Point>,
+    centre: ArrayView1<Numeric>,
+    radius: Numeric,
+) -> Vec<usize> {
+    let mut found: Vec<_> = points
+        .iter()
+        .filter(|(_, point)| squared_distance(centre, point.view()) <= radius * radius)
+        .map(|(&id, _)| id)
+        .collect();
+    found.sort();
+    found
+}
+
+#[test]
+fn structures_agree_with_brute_force() {
+    use ndarray_rand::rand_distr::Uniform;
+    use ndarray_rand::RandomExt;
+
+    for &dimension in &[2, 3] {
+        let mut points: HashMap<usize, Point> = (0..300)
+            .map(|id| (id, Array1::random(dimension, Uniform::new(0., 10.))))
+            .collect();
+        let mut indices: Vec<_> = [Structure::Grid, Structure::KdTree, Structure::Orthtree]
+            .iter()
+            .map(|&structure| {
+                let mut index = SpatialIndex::new(structure, dimension, 1.);
+                for (&id, point) in &points {
+                    index.insert(id, point.clone());
+                }
+                index
+            })
+            .collect();
+
+        for round in 0..3 {
+            for _ in 0..20 {
+                let centre = Array1::random(dimension, Uniform::new(-1., 11.));
+                let radius = 0.5 + 2. * round as Numeric;
+                let expected = brute_force_within_radius(&points, centre.view(), radius);
+                let mut nearest_expected: Vec<_> = points.keys().copied().collect();
+                nearest_expected
+                    .sort_by_key(|id| Distance(squared_distance(centre.view(), points[id].view())));
+                nearest_expected.truncate(5);
+
+                for index in &indices {
+                    let mut found = index.within_radius(centre.view(), radius);
+                    found.sort();
+                    assert_eq!(found, expected, "{:?}", index_name(index));
+                    assert_eq!(index.nearest(centre.view(), 5), nearest_expected);
+                }
+            }
+            // Move every point a little, some of them far, and drop a few.
+            for id in 0..300 {
+                if id % 50 == round {
+                    points.remove(&id);
+                    for index in &mut indices {
+                        assert!(index.remove(id).is_some());
+                    }
+                } else if let Some(point) = points.get_mut(&id) {
+                    *point += &Array1::random(dimension, Uniform::new(-0.3, 0.3));
+                    if id % 37 == 0 {
+                        *point *= -2.;
+                    }
+                    for index in &mut indices {
+                        index.update(id, point.clone());
+                    }
+                }
+            }
+            for index in &indices {
+                assert_eq!(index.len(), points.len());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn index_name(index: &SpatialIndex) -> &'static str {
+    match index {
+        SpatialIndex::Grid(_) => "grid",
+        SpatialIndex::KdTree(_) => "k-d tree",
+        SpatialIndex::Orthtree(_) => "orthtree",
+    }
+}
+
+#[test]
+fn periodic_grid() {
+    let mut grid = UniformGrid::periodic(1., ndarray::arr1(&[10., 5.]));
+    grid.insert(0, ndarray::arr1(&[0.1, 2.5]));
+    grid.insert(1, ndarray::arr1(&[9.8, 2.5]));
+    grid.insert(2, ndarray::arr1(&[5., 4.9]));
+    grid.insert(3, ndarray::arr1(&[5., 0.2]));
+    grid.insert(4, ndarray::arr1(&[5., 2.5]));
+
+    let mut found = grid.within_radius(ndarray::arr1(&[0., 2.5]).view(), 0.5);
+    found.sort();
+    assert_eq!(found, vec![0, 1]);
+    let mut found = grid.within_radius(ndarray::arr1(&[5., 0.]).view(), 0.5);
+    found.sort();
+    assert_eq!(found, vec![2, 3]);
+    assert_eq!(
+        grid.nearest(ndarray::arr1(&[9.9, 2.5]).view(), 2),
+        vec![1, 0]
+    );
+    // A radius larger than the box finds everything once.
+    assert_eq!(
+        grid.within_radius(ndarray::arr1(&[0., 0.]).view(), 20.)
+            .len(),
+        5
+    );
+
+    grid.update(1, ndarray::arr1(&[5.5, 2.5]));
+    assert_eq!(
+        grid.nearest(ndarray::arr1(&[5., 2.5]).view(), 2),
+        vec![4, 1]
+    );
+}
+
+#[test]
+#[should_panic(expected = "at least one dimension")]
+fn kd_tree_without_dimensions() {
+    KdTree::new(0);
+}
+
+#[test]
+#[should_panic(expected = "not finite")]
+fn orthtree_rejects_nan() {
+    let mut tree = Orthtree::new(Array1::zeros(2), Array1::ones(2), 1);
+    tree.insert(0, ndarray::arr1(&[Numeric::NAN, 0.5]));
+}
+
+#[test]
+fn uniform_grid_with_a_huge_radius() {
+    let mut grid = UniformGrid::new(1e-6);
+    grid.insert(0, ndarray::arr1(&[0., 0., 0.]));
+    grid.insert(1, ndarray::arr1(&[5., 5., 5.]));
+    let mut found = grid.within_radius(ndarray::arr1(&[0., 0., 0.]).view(), 10.);
+    found.sort_unstable();
+    assert_eq!(found, vec![0, 1]);
+}
+
+#[test]
+#[should_panic(expected = "cell size must be positive")]
+fn uniform_grid_without_a_cell_size() {
+    UniformGrid::new(0.);
+}
+
+#[test]
+fn inserting_a_duplicate_leaves_the_grid_unchanged() {
+    let mut grid = UniformGrid::new(1.);
+    grid.insert(0, ndarray::arr1(&[0.5, 0.5]));
+    let before = grid.clone();
+    let duplicate = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        grid.insert(0, ndarray::arr1(&[3.5, 0.5]))
+    }));
+    assert!(duplicate.is_err());
+    assert_eq!(grid, before);
+}
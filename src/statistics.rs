@@ -0,0 +1,28 @@
+//! The standard normal distribution.
+type Numeric = f64;
+
+/// Abramowitz and Stegun 7.1.26, with an absolute error below `1.5e-7`.
+fn erf(x: Numeric) -> Numeric {
+    let t = 1. / (1. + 0.327_591_1 * x.abs());
+    let polynomial = t
+        * (0.254_829_592
+            + t * (-0.284_496_736
+                + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    let y = 1. - polynomial * (-x * x).exp();
+    y.copysign(x)
+}
+
+pub fn normal_cdf(z: Numeric) -> Numeric {
+    0.5 * (1. + erf(z / std::f64::consts::SQRT_2))
+}
+
+pub fn normal_pdf(z: Numeric) -> Numeric {
+    (-0.5 * z * z).exp() / (2. * std::f64::consts::PI).sqrt()
+}
+
+#[test]
+fn standard_normal() {
+    assert!((erf(0.5) - 0.520_499_877_8).abs() < 2e-7);
+    assert!((normal_cdf(1.96) - 0.975).abs() < 1e-4);
+    assert!((normal_pdf(0.) - 0.398_942_280_4).abs() < 1e-9);
+}
@@ -1,3 +1,10 @@
+//! The SIR model with births and deaths at rate `m`, hunting at rate `h` and vaccination at
+//! rate `v`, which has a disease-free and an endemic equilibrium.
+//!
+//! Source: [](https://mpra.ub.uni-muenchen.de/68939/1/MPRA_paper_68939.pdf)
+use crate::equilibrium::{continuation, Equilibrium};
+use crate::interventions::{Interventions, Observation, Transition};
+use ndarray::{arr1, Array1, ArrayView1};
 use std::fmt::{Display, Error, Formatter};
 
 type Count = f64;
@@ -20,28 +27,62 @@ struct Population {
     population: Count,
 }
 
-struct SteadyStateSIRModelParameters {
-    m: Rate,
-    alpha: Rate,
-    beta: Rate,
-    delta: Rate,
-    h: Rate,
-    v: Rate,
+/// `m` is the birth and death rate, `alpha` the infection rate, `beta` the recovery rate,
+/// `delta` the disease induced death rate, `h` the hunting rate and `v` the vaccination rate.
+#[derive(Debug, Clone, Copy)]
+pub struct SteadyStateSIRModelParameters {
+    pub m: Rate,
+    pub alpha: Rate,
+    pub beta: Rate,
+    pub delta: Rate,
+    pub h: Rate,
+    pub v: Rate,
 }
 
-struct SteadyStateSIRModel {
+impl SteadyStateSIRModelParameters {
+    /// Rate of change of the susceptible, infected and recovered in a population of size `n`.
+    fn rate_of_change(&self, n: Count, state: ArrayView1<Count>) -> Array1<Rate> {
+        let SteadyStateSIRModelParameters {
+            m,
+            alpha,
+            beta,
+            delta,
+            h,
+            v,
+        } = *self;
+        let (susceptible, infected, recovered) = (state[0], state[1], state[2]);
+
+        let diff_susceptible = m * n - m * susceptible - alpha * susceptible * infected;
+        let diff_infected = alpha * susceptible * infected - (m + delta + beta) * infected;
+        let diff_recovered = beta * infected - m * recovered;
+
+        let diff_susceptible = diff_susceptible - h * susceptible;
+        let diff_infected = diff_infected - h * infected;
+        let diff_recovered = diff_recovered - h * recovered;
+
+        let diff_susceptible = diff_susceptible - v * susceptible;
+        let diff_recovered = diff_recovered + v * susceptible;
+
+        arr1(&[diff_susceptible, diff_infected, diff_recovered])
+    }
+}
+
+pub struct SteadyStateSIRModel {
     parameters: SteadyStateSIRModelParameters,
     initial_population: Population,
     states: Vec<PopulationState>,
 }
 
 impl SteadyStateSIRModel {
-    fn set_disease_parameters(mut self, disease_parameters: SteadyStateSIRModelParameters) -> Self {
+    pub fn set_disease_parameters(
+        mut self,
+        disease_parameters: SteadyStateSIRModelParameters,
+    ) -> Self {
         self.parameters = disease_parameters;
         self
     }
 
-    fn new(susceptible: Count, infected: Count) -> Self {
+    pub fn new(susceptible: Count, infected: Count) -> Self {
         Self {
             parameters: SteadyStateSIRModelParameters {
                 m: 0.0,
@@ -65,15 +106,14 @@ impl SteadyStateSIRModel {
         }
     }
 
-    fn update(&mut self, timesteps: u64) {
-        let SteadyStateSIRModelParameters {
-            m,
-            alpha,
-            beta,
-            delta,
-            h,
-            v,
-        } = self.parameters;
+    pub fn update(&mut self, timesteps: u64) {
+        self.update_with(timesteps, &mut Interventions::new());
+    }
+
+    /// Like [`SteadyStateSIRModel::update`], with the parameters set by the schedules of
+    /// `interventions` at each time. The vaccinations and isolations of their response are made
+    /// at the start of each update.
+    pub fn update_with(&mut self, timesteps: u64, interventions: &mut Interventions) {
         #[allow(non_snake_case)]
         let N = self.initial_population.population;
         let PopulationState {
@@ -91,20 +131,33 @@ impl SteadyStateSIRModel {
             .expect("failed to initialise population");
 
         for time_increment in 1..=timesteps {
-            let diff_susceptible = m * N - m * susceptible - alpha * susceptible * infected;
-            let diff_infected = alpha * susceptible * infected - (m + delta + beta) * infected;
-            let diff_recovered = beta * infected - m * recovered;
+            let now = (time + time_increment - 1) as usize;
+            let response = interventions.respond(Observation {
+                time: now,
+                susceptible,
+                infectious: infected,
+            });
+            let isolated = response.isolation * infected;
+            susceptible -= response.vaccinations;
+            infected -= isolated;
+            recovered += response.vaccinations + isolated;
 
-            let diff_susceptible = diff_susceptible - h * susceptible;
-            let diff_infected = diff_infected - h * infected;
-            let diff_recovered = diff_recovered - h * recovered;
+            let rate = |transition, default| interventions.rate(transition, default, now);
+            let parameters = SteadyStateSIRModelParameters {
+                m: rate(Transition::Demography, self.parameters.m),
+                alpha: rate(Transition::Infection, self.parameters.alpha)
+                    * response.infection_factor,
+                beta: rate(Transition::Recovery, self.parameters.beta),
+                delta: rate(Transition::DiseaseDeath, self.parameters.delta),
+                h: rate(Transition::Hunting, self.parameters.h),
+                v: rate(Transition::Vaccination, self.parameters.v),
+            };
+            let diff =
+                parameters.rate_of_change(N, arr1(&[susceptible, infected, recovered]).view());
 
-            let diff_susceptible = diff_susceptible - v * susceptible;
-            let diff_recovered = diff_recovered + v * recovered;
-
-            susceptible += diff_susceptible;
-            infected += diff_infected;
-            recovered += diff_recovered;
+            susceptible += diff[0];
+            infected += diff[1];
+            recovered += diff[2];
 
             self.states.push(PopulationState {
                 time: time + time_increment,
@@ -116,6 +169,75 @@ impl SteadyStateSIRModel {
             })
         }
     }
+
+    pub fn parameters(&self) -> &SteadyStateSIRModelParameters {
+        &self.parameters
+    }
+
+    /// Rate of change of `[susceptible, infected, recovered]`.
+    pub fn rate_of_change(&self, state: ArrayView1<Count>) -> Array1<Rate> {
+        self.parameters
+            .rate_of_change(self.initial_population.population, state)
+    }
+
+    /// The equilibrium without infected, found from the whole population being susceptible.
+    pub fn disease_free_equilibrium(&self) -> Equilibrium {
+        let n = self.initial_population.population;
+        Equilibrium::find(|x| self.rate_of_change(x), arr1(&[n, 0., 0.]).view())
+            .expect("the disease-free equilibrium always exists")
+    }
+
+    /// The equilibrium with infected, if the infected can persist. Newton iteration starts from
+    /// the susceptible level at which the infected neither grow nor decline.
+    pub fn endemic_equilibrium(&self) -> Option<Equilibrium> {
+        let initial = self.endemic_guess(&self.parameters)?;
+        Equilibrium::find(|x| self.rate_of_change(x), initial.view())
+    }
+
+    /// `[susceptible, infected, recovered]` at the endemic equilibrium of the linear part, or
+    /// `None` if the infected would be negative.
+    fn endemic_guess(&self, parameters: &SteadyStateSIRModelParameters) -> Option<Array1<Count>> {
+        let SteadyStateSIRModelParameters {
+            m,
+            alpha,
+            beta,
+            delta,
+            h,
+            v,
+        } = *parameters;
+        let n = self.initial_population.population;
+        let susceptible = (m + delta + beta + h) / alpha;
+        let infected = (m * n - (m + h + v) * susceptible) / (alpha * susceptible);
+        let recovered = (beta * infected + v * susceptible) / (m + h);
+        if infected > 0. {
+            Some(arr1(&[susceptible, infected, recovered]))
+        } else {
+            None
+        }
+    }
+
+    /// The endemic equilibrium as the vaccination rate runs through `vaccination_rates`, from
+    /// the first of them. Past the threshold of vaccination the branch continues with negative
+    /// infected, where it is unstable and the disease-free equilibrium is stable instead.
+    pub fn vaccination_continuation(&self, vaccination_rates: &[Rate]) -> Vec<(Rate, Equilibrium)> {
+        let first = match vaccination_rates.first() {
+            Some(&v) => SteadyStateSIRModelParameters {
+                v,
+                ..self.parameters
+            },
+            None => return vec![],
+        };
+        let initial = match self.endemic_guess(&first) {
+            Some(initial) => initial,
+            None => return vec![],
+        };
+        let n = self.initial_population.population;
+        continuation(
+            |v, x| SteadyStateSIRModelParameters { v, ..first }.rate_of_change(n, x),
+            initial.view(),
+            vaccination_rates,
+        )
+    }
 }
 
 impl Display for Population {
@@ -230,3 +352,137 @@ fn numerical_example_9_of_sir_model_2_vaccine() {
 
     println!("{}", model);
 }
+
+/// Vaccination moves susceptible individuals to the recovered. The recovered used to gain `v`
+/// times the recovered instead, so the vaccinated were lost from the population.
+#[test]
+fn vaccinating_the_susceptible() {
+    let (m, alpha, beta, delta, v) = (0.0001, 0.02, 0.5, 0.1, 0.095);
+    let mut model =
+        SteadyStateSIRModel::new(50., 1.).set_disease_parameters(SteadyStateSIRModelParameters {
+            m,
+            alpha,
+            beta,
+            delta,
+            h: 0.,
+            v,
+        });
+    model.update(13);
+
+    let first = &model.states[1].state;
+    assert!((first.susceptible - (50. + 0.0001 - 1. - 0.095 * 50.)).abs() < 1e-12);
+    assert!((first.infected - (1. + 1. - (0.0001 + 0.1 + 0.5))).abs() < 1e-12);
+    assert!((first.recovered - (0.5 + 0.095 * 50.)).abs() < 1e-12);
+    for pair in model.states.windows(2) {
+        let (before, after) = (&pair[0].state, &pair[1].state);
+        let (s, i, r) = (before.susceptible, before.infected, before.recovered);
+        let vaccinated = v * s;
+        assert!(
+            (after.susceptible - (s + m * 51. - m * s - alpha * s * i - vaccinated)).abs() < 1e-12
+        );
+        assert!((after.recovered - (r + beta * i - m * r + vaccinated)).abs() < 1e-12);
+    }
+    let last = &model.states[13].state;
+    assert!((last.susceptible - 9.119_785).abs() < 1e-6);
+    assert!((last.infected - 0.157_474).abs() < 1e-6);
+    assert!((last.recovered - 39.920_508).abs() < 1e-6);
+}
+
+#[test]
+fn endemic_and_disease_free_equilibria() {
+    use crate::equilibrium::Stability;
+
+    let parameters = SteadyStateSIRModelParameters {
+        m: 0.02,
+        alpha: 0.01,
+        beta: 0.1,
+        delta: 0.,
+        h: 0.,
+        v: 0.,
+    };
+    let model = SteadyStateSIRModel::new(99., 1.).set_disease_parameters(parameters);
+
+    let disease_free = model.disease_free_equilibrium();
+    println!("disease free {}", disease_free);
+    assert!((disease_free.state()[0] - 100.).abs() < 1e-6);
+    assert_eq!(disease_free.stability(), Stability::Saddle);
+
+    let endemic = model.endemic_equilibrium().unwrap();
+    println!("endemic {}", endemic);
+    assert!((endemic.state()[0] - 12.).abs() < 1e-6);
+    assert!((endemic.state()[1] - 2. * 0.88 / 0.12).abs() < 1e-6);
+    assert!(endemic.stability().is_stable());
+
+    // the infected vanish at v = m (N / S* - 1)
+    let threshold = 0.02 * (100. / 12. - 1.);
+    let rates: Vec<_> = (0..=20).map(|k| 0.01 * k as Rate).collect();
+    let branch = model.vaccination_continuation(&rates);
+    assert_eq!(branch.len(), rates.len());
+    assert!(model.vaccination_continuation(&[]).is_empty());
+    for (v, equilibrium) in &branch {
+        assert_eq!(equilibrium.state()[1] > 0., *v < threshold);
+        assert_eq!(equilibrium.stability().is_stable(), *v < threshold);
+    }
+    let vaccinated = model.set_disease_parameters(SteadyStateSIRModelParameters {
+        v: 0.2,
+        ..parameters
+    });
+    assert!(vaccinated.endemic_equilibrium().is_none());
+    assert!(vaccinated
+        .disease_free_equilibrium()
+        .stability()
+        .is_stable());
+}
+
+#[test]
+fn disease_free_equilibrium_with_repeated_eigenvalues() {
+    use crate::equilibrium::Stability;
+    use num_complex::Complex;
+
+    // with alpha N = beta the susceptible, infected and recovered all decline at rate m
+    let model =
+        SteadyStateSIRModel::new(99., 1.).set_disease_parameters(SteadyStateSIRModelParameters {
+            m: 0.02,
+            alpha: 0.001,
+            beta: 0.1,
+            delta: 0.,
+            h: 0.,
+            v: 0.,
+        });
+    let disease_free = model.disease_free_equilibrium();
+    println!("disease free {}", disease_free);
+    assert!(disease_free
+        .eigenvalues()
+        .iter()
+        .all(|z| (z - Complex::new(-0.02, 0.)).norm() < 1e-9));
+    assert_eq!(disease_free.stability(), Stability::StableNode);
+}
+
+#[test]
+fn hunting_season() {
+    use crate::interventions::Schedule;
+
+    let parameters = SteadyStateSIRModelParameters {
+        m: 0.0001,
+        alpha: 0.02,
+        beta: 0.5,
+        delta: 0.1,
+        h: 0.,
+        v: 0.,
+    };
+    let mut constant = SteadyStateSIRModel::new(50., 1.).set_disease_parameters(parameters);
+    let mut season = SteadyStateSIRModel::new(50., 1.).set_disease_parameters(parameters);
+    constant.update(13);
+    season.update_with(
+        13,
+        &mut Interventions::new().add_schedule(
+            Transition::Hunting,
+            Schedule::Piecewise(vec![(5, 0.1), (10, 0.)]),
+        ),
+    );
+    println!("{}", season);
+    // the runs agree until the hunting season opens at time 5
+    let infected = |model: &SteadyStateSIRModel, time: usize| model.states[time].state.infected;
+    assert!((infected(&constant, 5) - infected(&season, 5)).abs() < 1e-12);
+    assert!(infected(&season, 13) < infected(&constant, 13));
+}
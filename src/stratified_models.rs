@@ -0,0 +1,401 @@
+//! Multi-group compartment models, where each compartment of [`disease::Population`] is indexed
+//! by group, e.g. age band, region or risk group. Group `i` is infected at the rate
+//! `infection_rate * Σ_j C_ij I_j / N_j`, where `C_ij` is the average number of contacts a member
+//! of group `i` has with members of group `j` per time step.
+//!
+//! The contact matrix and the group sizes can be read from CSV. Contacts are a square table with
+//! the group names as header, where each row may be preceded by the name of its group:
+//!
+//! ```text
+//! ,children,adults
+//! children,10,4
+//! adults,3,6
+//! ```
+//!
+//! The group sizes are a table with the group names in the first column.
+//!
+//! [`disease::Population`]: crate::disease::Population
+use crate::csv::parse_csv_column;
+use crate::disease::DiseaseCompartments;
+use crate::epidemic_summary::spectral_radius;
+use ndarray::{Array1, Array2, ArrayView1};
+use std::fmt::{Display, Error, Formatter};
+use std::path::Path;
+
+type Count = f64;
+type Rate = f64;
+
+type StratifiedStates = DiseaseCompartments<Array1<Count>>;
+type StratifiedRates = DiseaseCompartments<Array1<Rate>>;
+
+impl StratifiedStates {
+    fn zeros(groups: usize) -> Self {
+        Self {
+            susceptible: Array1::zeros(groups),
+            exposed: Array1::zeros(groups),
+            infectious: Array1::zeros(groups),
+            removed: Array1::zeros(groups),
+            recovered: Array1::zeros(groups),
+        }
+    }
+
+    fn update_disease(&mut self, disease_rate: StratifiedRates) {
+        self.susceptible += &disease_rate.susceptible;
+        self.exposed += &disease_rate.exposed;
+        self.infectious += &disease_rate.infectious;
+        self.removed += &disease_rate.removed;
+        self.recovered += &disease_rate.recovered;
+    }
+
+    fn total(&self) -> Array1<Count> {
+        &self.susceptible + &self.exposed + &self.infectious + &self.recovered
+    }
+}
+
+pub struct StratifiedPopulation {
+    groups: Vec<String>,
+    disease_states: StratifiedStates,
+    contact_matrix: Array2<Rate>,
+    infection_rate: Rate,
+    latency_rate: Option<Rate>,
+    recovery_rate: Rate,
+    immunity_decay_rate: Rate,
+}
+
+impl StratifiedPopulation {
+    /// A population with `group_sizes` susceptible individuals in the `groups`. `infection_rate`
+    /// is the probability that a contact with an infectious individual infects.
+    pub fn new(
+        groups: Vec<String>,
+        group_sizes: Array1<Count>,
+        contact_matrix: Array2<Rate>,
+        infection_rate: Rate,
+        recovery_rate: Rate,
+    ) -> Self {
+        let n = groups.len();
+        assert_eq!(group_sizes.len(), n, "a size is needed for every group");
+        assert_eq!(
+            contact_matrix.dim(),
+            (n, n),
+            "the contact matrix must have a row and a column per group"
+        );
+        let mut disease_states = StratifiedStates::zeros(n);
+        disease_states.susceptible = group_sizes;
+        Self {
+            groups,
+            disease_states,
+            contact_matrix,
+            infection_rate,
+            latency_rate: None,
+            recovery_rate,
+            immunity_decay_rate: 0.,
+        }
+    }
+
+    /// Reads the contact matrix and the column `column` of the group sizes, which are matched to
+    /// the contacts by group name.
+    pub fn read_csv<P: AsRef<Path>>(
+        contacts: P,
+        group_sizes: P,
+        column: &str,
+        infection_rate: Rate,
+        recovery_rate: Rate,
+    ) -> Result<Self, String> {
+        let read = |path: P| std::fs::read_to_string(path).map_err(|error| error.to_string());
+        let (groups, contact_matrix) = parse_contact_matrix(&read(contacts)?)?;
+        let group_sizes = parse_group_sizes(&read(group_sizes)?, column, &groups)?;
+        Ok(Self::new(
+            groups,
+            group_sizes,
+            contact_matrix,
+            infection_rate,
+            recovery_rate,
+        ))
+    }
+
+    /// Moves `infected` individuals of `group` from susceptible to infectious.
+    pub fn set_infected(mut self, group: usize, infected: Count) -> Self {
+        self.disease_states.susceptible[group] -= infected;
+        self.disease_states.infectious[group] += infected;
+        self
+    }
+
+    /// Adds a latent compartment that is left at `latency_rate`, making the model SEIR.
+    pub fn set_latency_rate(mut self, latency_rate: Rate) -> Self {
+        self.latency_rate = Some(latency_rate);
+        self
+    }
+
+    /// Recovered individuals become susceptible again at `immunity_decay_rate`, as in SIRS.
+    pub fn set_immunity_decay_rate(mut self, immunity_decay_rate: Rate) -> Self {
+        self.immunity_decay_rate = immunity_decay_rate;
+        self
+    }
+
+    pub fn groups(&self) -> &[String] {
+        &self.groups
+    }
+
+    pub fn contact_matrix(&self) -> &Array2<Rate> {
+        &self.contact_matrix
+    }
+
+    pub fn susceptible(&self) -> ArrayView1<'_, Count> {
+        self.disease_states.susceptible.view()
+    }
+
+    pub fn exposed(&self) -> ArrayView1<'_, Count> {
+        self.disease_states.exposed.view()
+    }
+
+    pub fn infectious(&self) -> ArrayView1<'_, Count> {
+        self.disease_states.infectious.view()
+    }
+
+    pub fn recovered(&self) -> ArrayView1<'_, Count> {
+        self.disease_states.recovered.view()
+    }
+
+    pub fn group_sizes(&self) -> Array1<Count> {
+        self.disease_states.total()
+    }
+
+    pub fn total_population(&self) -> Count {
+        self.group_sizes().sum()
+    }
+
+    /// The rate at which a susceptible individual of each group is infected.
+    pub fn force_of_infection(&self) -> Array1<Rate> {
+        let prevalence = &self.disease_states.infectious / &self.group_sizes();
+        self.infection_rate * self.contact_matrix.dot(&prevalence)
+    }
+
+    /// Number of new infections in each group in the next update.
+    pub fn incidence(&self) -> Array1<Count> {
+        self.force_of_infection() * &self.disease_states.susceptible
+    }
+
+    /// The next-generation matrix, with the expected number of infections in group `i` caused
+    /// by an infectious individual of group `j` in a wholly susceptible population.
+    pub fn next_generation_matrix(&self) -> Array2<Rate> {
+        let sizes = self.group_sizes();
+        let mut k = self.contact_matrix.clone() * (self.infection_rate / self.recovery_rate);
+        for ((i, j), k) in k.indexed_iter_mut() {
+            *k *= sizes[i] / sizes[j];
+        }
+        k
+    }
+
+    pub fn reproduction_number(&self) -> Rate {
+        spectral_radius(&self.next_generation_matrix())
+    }
+
+    fn disease_rate(&self) -> StratifiedRates {
+        let states = &self.disease_states;
+        let newly_infected = self.incidence();
+        let newly_susceptible = self.immunity_decay_rate * &states.recovered;
+        let recovered = self.recovery_rate * &states.infectious;
+        let (exposed, infectious) = match self.latency_rate {
+            Some(latency_rate) => {
+                let newly_infectious = latency_rate * &states.exposed;
+                (
+                    &newly_infected - &newly_infectious,
+                    newly_infectious - &recovered,
+                )
+            }
+            None => (
+                Array1::zeros(self.groups.len()),
+                &newly_infected - &recovered,
+            ),
+        };
+        DiseaseCompartments {
+            susceptible: &newly_susceptible - &newly_infected,
+            exposed,
+            infectious,
+            removed: Array1::zeros(self.groups.len()),
+            recovered: recovered - &newly_susceptible,
+        }
+    }
+
+    pub fn update_disease_state(&mut self) {
+        self.disease_states.update_disease(self.disease_rate());
+    }
+
+    /// The infectious of each group at the current time and after each of `steps` updates, one
+    /// row per time.
+    pub fn infectious_trajectory(&mut self, steps: usize) -> Array2<Count> {
+        let mut trajectory = Array2::zeros((steps + 1, self.groups.len()));
+        for (time, mut row) in trajectory.genrows_mut().into_iter().enumerate() {
+            if time > 0 {
+                self.update_disease_state();
+            }
+            row.assign(&self.disease_states.infectious);
+        }
+        trajectory
+    }
+}
+
+impl Display for StratifiedPopulation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        for (i, group) in self.groups.iter().enumerate() {
+            writeln!(
+                f,
+                "{:<10} {:>10.6} {:>10.6} {:>10.6} {:>10.6}",
+                group,
+                self.disease_states.susceptible[i],
+                self.disease_states.exposed[i],
+                self.disease_states.infectious[i],
+                self.disease_states.recovered[i]
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The group names and the square contact matrix in the comma-separated `contents`.
+pub fn parse_contact_matrix(contents: &str) -> Result<(Vec<String>, Array2<Rate>), String> {
+    let mut lines = contents.lines().filter(|line| !line.trim().is_empty());
+    let header: Vec<_> = lines
+        .next()
+        .ok_or_else(|| "missing header".to_string())?
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .collect();
+    let rows = lines
+        .enumerate()
+        .map(|(row, line)| {
+            let mut cells: Vec<_> = line.split(',').map(str::trim).collect();
+            // skip the name of the group
+            let labelled = match cells.first() {
+                Some(cell) => cell.parse::<Rate>().is_err(),
+                None => false,
+            };
+            if labelled {
+                cells.remove(0);
+            }
+            cells
+                .into_iter()
+                .map(|cell| {
+                    cell.parse::<Rate>()
+                        .map_err(|error| format!("row {}: {}", row + 1, error))
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let n = rows.len();
+    let groups = if header.len() == n + 1 {
+        header[1..].to_vec()
+    } else {
+        header
+    };
+    if groups.len() != n || rows.iter().any(|row| row.len() != n) {
+        return Err(format!(
+            "contact matrix must be square with {} groups",
+            groups.len()
+        ));
+    }
+    let contact_matrix =
+        Array2::from_shape_vec((n, n), rows.concat()).map_err(|error| error.to_string())?;
+    Ok((groups, contact_matrix))
+}
+
+/// The sizes in the column `column` of the comma-separated `contents`, ordered as `groups`,
+/// which are looked up in the first column.
+pub fn parse_group_sizes(
+    contents: &str,
+    column: &str,
+    groups: &[String],
+) -> Result<Array1<Count>, String> {
+    let sizes = parse_csv_column(contents, column)?;
+    let names: Vec<_> = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .skip(1)
+        .map(|line| line.split(',').next().unwrap_or_default().trim())
+        .collect();
+    groups
+        .iter()
+        .map(|group| {
+            names
+                .iter()
+                .position(|name| name == group)
+                .map(|row| sizes[row])
+                .ok_or_else(|| format!("no size for group `{}`", group))
+        })
+        .collect()
+}
+
+#[test]
+fn single_group_is_the_sir_model() {
+    use crate::disease::create_sir_population;
+    use ndarray::{arr1, arr2};
+
+    let mut stratified = StratifiedPopulation::new(
+        vec!["all".to_string()],
+        arr1(&[1000.]),
+        arr2(&[[4.]]),
+        0.1,
+        0.2,
+    )
+    .set_infected(0, 1.);
+    assert!((stratified.reproduction_number() - 2.).abs() < 1e-9);
+
+    let mut population = create_sir_population(999., 1., 0.0004, 0.2);
+    for _ in 0..50 {
+        stratified.update_disease_state();
+        population.update_disease_state();
+    }
+    assert!((stratified.infectious()[0] - population.infectious()).abs() < 1e-9);
+    assert!((stratified.susceptible()[0] - population.susceptible()).abs() < 1e-9);
+}
+
+#[test]
+fn reading_contacts_and_group_sizes() {
+    let contacts = ",children,adults\nchildren,10,4\nadults,3,6\n";
+    let (groups, contact_matrix) = parse_contact_matrix(contacts).unwrap();
+    assert_eq!(groups, vec!["children", "adults"]);
+    assert_eq!(contact_matrix, ndarray::arr2(&[[10., 4.], [3., 6.]]));
+    let unlabelled = parse_contact_matrix("children,adults\n10,4\n3,6").unwrap();
+    assert_eq!(unlabelled, (groups.clone(), contact_matrix));
+    assert!(parse_contact_matrix("a,b\n1,2\n").is_err());
+
+    let sizes = "group,population\nadults,300\nchildren,100\n";
+    let sizes = parse_group_sizes(sizes, "population", &groups).unwrap();
+    assert_eq!(sizes, ndarray::arr1(&[100., 300.]));
+    assert!(parse_group_sizes("group,population\nadults,300\n", "population", &groups).is_err());
+}
+
+#[test]
+fn high_contact_group_is_infected_first() {
+    let dir = std::env::temp_dir();
+    let contacts = dir.join("epibox_contacts.csv");
+    let sizes = dir.join("epibox_group_sizes.csv");
+    std::fs::write(&contacts, ",young,old\nyoung,12,2\nold,2,3\n").unwrap();
+    std::fs::write(&sizes, "group,size\nyoung,600\nold,400\n").unwrap();
+    let mut population = StratifiedPopulation::read_csv(&contacts, &sizes, "size", 0.05, 0.25)
+        .unwrap()
+        .set_latency_rate(0.5)
+        .set_infected(1, 1.);
+    println!("R0 {:.4}", population.reproduction_number());
+    assert!(population.reproduction_number() > 1.);
+
+    let trajectory = population.infectious_trajectory(200);
+    println!("{}", population);
+    let peak = |group: usize| {
+        trajectory
+            .column(group)
+            .iter()
+            .enumerate()
+            .fold(
+                (0, 0.),
+                |peak, (time, &x)| if x > peak.1 { (time, x) } else { peak },
+            )
+    };
+    assert!(peak(0).0 < peak(1).0);
+    let attack_rate =
+        (&population.recovered() + &population.infectious()) / &population.group_sizes();
+    assert!(attack_rate[0] > attack_rate[1]);
+    assert!((population.total_population() - 1000.).abs() < 1e-6);
+}
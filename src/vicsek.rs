@@ -0,0 +1,170 @@
+//! Source: [Vicsek et al. (1995), Novel Type of Phase Transition in a System of Self-Driven Particles](https://doi.org/10.1103/PhysRevLett.75.1226)
+//!
+//! Particles move at constant speed in a periodic square and take on the mean heading of the
+//! particles within `radius`, up to a uniformly distributed angular noise in `[-η/2, η/2]`.
+use crate::boids::{polarisation, Arena};
+use crate::spatial_data_structures::UniformGrid;
+use ndarray::{Array1, Array2};
+use ndarray_rand::rand_distr::Uniform;
+use ndarray_rand::RandomExt;
+use rand::prelude::*;
+use rayon::prelude::*;
+use std::f64::consts::PI;
+
+type Numeric = f64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vicsek {
+    position: Array2<Numeric>,
+    /// Angle of the velocity, in radians.
+    heading: Array1<Numeric>,
+    speed: Numeric,
+    radius: Numeric,
+    noise: Numeric,
+    arena: Arena,
+    grid: UniformGrid,
+    time: usize,
+}
+
+impl Vicsek {
+    /// `n` particles placed uniformly at random in a periodic square of side `size`, heading in
+    /// uniformly random directions.
+    pub fn new(n: usize, size: Numeric, speed: Numeric, radius: Numeric, noise: Numeric) -> Self {
+        let position = Array2::random((n, 2), Uniform::new(0., size));
+        let arena = Arena::Periodic {
+            size: ndarray::arr1(&[size, size]),
+        };
+        let mut grid = arena.grid(radius);
+        for (i, position) in position.genrows().into_iter().enumerate() {
+            grid.insert(i, position.to_owned());
+        }
+        Self {
+            position,
+            heading: Array1::random(n, Uniform::new(-PI, PI)),
+            speed,
+            radius,
+            noise,
+            arena,
+            grid,
+            time: 0,
+        }
+    }
+
+    pub fn position(&self) -> &Array2<Numeric> {
+        &self.position
+    }
+
+    pub fn heading(&self) -> &Array1<Numeric> {
+        &self.heading
+    }
+
+    pub fn arena(&self) -> &Arena {
+        &self.arena
+    }
+
+    pub fn time(&self) -> usize {
+        self.time
+    }
+
+    pub fn velocity(&self) -> Array2<Numeric> {
+        Array2::from_shape_fn((self.heading.len(), 2), |(i, k)| {
+            self.speed
+                * if k == 0 {
+                    self.heading[i].cos()
+                } else {
+                    self.heading[i].sin()
+                }
+        })
+    }
+
+    /// Norm of the mean heading, the order parameter of the model.
+    pub fn order_parameter(&self) -> Numeric {
+        polarisation(&self.velocity())
+    }
+
+    pub fn step(&mut self) {
+        let mut rng = thread_rng();
+        let heading: Array1<Numeric> = (0..self.heading.len())
+            .map(|i| {
+                // The particle itself is within the radius, too.
+                let (sin, cos) = self
+                    .grid
+                    .within_radius(self.position.row(i), self.radius)
+                    .into_iter()
+                    .map(|j| self.heading[j])
+                    .fold((0., 0.), |(sin, cos), angle| {
+                        (sin + angle.sin(), cos + angle.cos())
+                    });
+                sin.atan2(cos) + self.noise * (rng.gen::<Numeric>() - 0.5)
+            })
+            .collect();
+        self.heading = heading;
+        let velocity = self.velocity();
+        self.position += &velocity;
+        if let Arena::Periodic { size } = &self.arena {
+            for mut row in self.position.genrows_mut() {
+                row.zip_mut_with(size, |x, &length| *x = x.rem_euclid(length));
+            }
+        }
+        for (i, position) in self.position.genrows().into_iter().enumerate() {
+            self.grid.update(i, position.to_owned());
+        }
+        self.time += 1;
+    }
+
+    pub fn run(&mut self, steps: usize) {
+        for _ in 0..steps {
+            self.step();
+        }
+    }
+}
+
+/// The order parameter for each of the `noises`, averaged over `measurements` steps after
+/// discarding the first `transient` steps. The noise levels run in parallel.
+pub fn noise_sweep(
+    n: usize,
+    size: Numeric,
+    speed: Numeric,
+    radius: Numeric,
+    noises: &[Numeric],
+    transient: usize,
+    measurements: usize,
+) -> Vec<(Numeric, Numeric)> {
+    noises
+        .par_iter()
+        .map(|&noise| {
+            let mut model = Vicsek::new(n, size, speed, radius, noise);
+            model.run(transient);
+            let order_parameter = (0..measurements)
+                .map(|_| {
+                    model.step();
+                    model.order_parameter()
+                })
+                .sum::<Numeric>()
+                / measurements as Numeric;
+            (noise, order_parameter)
+        })
+        .collect()
+}
+
+#[test]
+fn kinetic_phase_transition() {
+    let sweep = noise_sweep(64, 4., 0.03, 1., &[0.1, 2., 5.], 150, 50);
+    for (noise, order_parameter) in &sweep {
+        println!("η = {:<4} φ = {:.3}", noise, order_parameter);
+    }
+    assert!(sweep[0].1 > 0.9);
+    assert!(sweep[0].1 > sweep[1].1 && sweep[1].1 > sweep[2].1);
+    assert!(sweep[2].1 < 0.4);
+}
+
+#[test]
+fn constant_speed_in_the_square() {
+    let mut model = Vicsek::new(50, 10., 0.5, 1., 1.);
+    model.run(20);
+    assert_eq!(model.time(), 20);
+    assert!(model.position().iter().all(|x| (0. ..10.).contains(x)));
+    for velocity in model.velocity().genrows() {
+        assert!((crate::boids::norm(velocity) - 0.5).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,353 @@
+//! Individual-based predator–prey model of sheep and wolves on a lattice, after the NetLogo
+//! Wolf Sheep Predation model. Animals move to a random neighbouring cell in every update, spend
+//! energy, eat, reproduce by splitting their energy with the offspring and die when they run out
+//! of energy. Wolves eat a sheep sharing their cell. With grass, sheep only gain energy by eating
+//! grown grass, which regrows after a fixed time; without grass sheep never starve.
+//!
+//! The counts are returned as [`Population`]s with the sheep as the prey `x` and the wolves as
+//! the predators `y`, so they compare directly with [`lotka_volterra_models::Model`].
+//!
+//! Source: [Wilensky, NetLogo Wolf Sheep Predation model](http://ccl.northwestern.edu/netlogo/models/WolfSheepPredation)
+//!
+//! [`lotka_volterra_models::Model`]: crate::lotka_volterra_models::Model
+use crate::lotka_volterra_models::Population;
+use ndarray::Array2;
+use rand::seq::SliceRandom;
+use rand::{thread_rng, Rng};
+use std::fmt::{Display, Error, Formatter};
+
+type Numeric = f64;
+type Energy = f64;
+type Position = (usize, usize);
+
+/// The run stops once there are more sheep than this, as the NetLogo model does.
+const MAX_SHEEP: usize = 30_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Species {
+    Sheep,
+    Wolf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Animal {
+    pub species: Species,
+    pub position: Position,
+    pub energy: Energy,
+}
+
+/// The defaults are those of the NetLogo model.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Parameters {
+    pub sheep_gain_from_food: Energy,
+    pub wolf_gain_from_food: Energy,
+    /// Probability that a sheep reproduces in an update.
+    pub sheep_reproduction: f64,
+    /// Probability that a wolf reproduces in an update.
+    pub wolf_reproduction: f64,
+    /// Number of updates until eaten grass has grown back, or `None` for a model without grass.
+    pub grass_regrowth_time: Option<usize>,
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Self {
+            sheep_gain_from_food: 4.,
+            wolf_gain_from_food: 20.,
+            sheep_reproduction: 0.04,
+            wolf_reproduction: 0.05,
+            grass_regrowth_time: Some(30),
+        }
+    }
+}
+
+pub struct Model {
+    size: usize,
+    sheep: Vec<Animal>,
+    wolves: Vec<Animal>,
+    /// Updates until the grass of each cell has grown back, where zero is grown.
+    grass: Array2<usize>,
+    parameters: Parameters,
+    time: usize,
+}
+
+impl Model {
+    /// A `size` by `size` torus with `population.x` sheep and `population.y` wolves at random
+    /// cells, with random energies below twice their gain from food, and grass grown on about
+    /// half of the cells.
+    pub fn new(size: usize, population: Population, parameters: Parameters) -> Self {
+        assert!(size > 0, "the lattice needs at least one cell");
+        let mut rng = thread_rng();
+        let mut animals = |species, count: Numeric, gain: Energy| -> Vec<Animal> {
+            (0..count.round() as usize)
+                .map(|_| Animal {
+                    species,
+                    position: (rng.gen_range(0, size), rng.gen_range(0, size)),
+                    energy: rng.gen_range(0., 2. * gain),
+                })
+                .collect()
+        };
+        let sheep = animals(
+            Species::Sheep,
+            population.x,
+            parameters.sheep_gain_from_food,
+        );
+        let wolves = animals(Species::Wolf, population.y, parameters.wolf_gain_from_food);
+        let grass = match parameters.grass_regrowth_time {
+            Some(regrowth) => Array2::from_shape_fn((size, size), |_| {
+                if rng.gen_bool(0.5) {
+                    0
+                } else {
+                    rng.gen_range(0, regrowth.max(1))
+                }
+            }),
+            None => Array2::zeros((size, size)),
+        };
+
+        Self {
+            size,
+            sheep,
+            wolves,
+            grass,
+            parameters,
+            time: 0,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn time(&self) -> usize {
+        self.time
+    }
+
+    pub fn sheep(&self) -> &[Animal] {
+        &self.sheep
+    }
+
+    pub fn wolves(&self) -> &[Animal] {
+        &self.wolves
+    }
+
+    /// The number of sheep as `x` and of wolves as `y`.
+    pub fn population(&self) -> Population {
+        Population {
+            x: self.sheep.len() as Numeric,
+            y: self.wolves.len() as Numeric,
+        }
+    }
+
+    /// The number of cells with grown grass, or `None` for a model without grass.
+    pub fn grass(&self) -> Option<usize> {
+        self.parameters.grass_regrowth_time.map(|_| {
+            self.grass
+                .iter()
+                .filter(|&&countdown| countdown == 0)
+                .count()
+        })
+    }
+
+    /// Returns true if all the animals are dead or the sheep have overrun the lattice.
+    pub fn is_over(&self) -> bool {
+        (self.sheep.is_empty() && self.wolves.is_empty()) || self.sheep.len() > MAX_SHEEP
+    }
+
+    /// A random one of the eight neighbours of `position`.
+    fn neighbour<R: Rng>(&self, (r, c): Position, rng: &mut R) -> Position {
+        let (dr, dc) = loop {
+            let step = (rng.gen_range(0, 3), rng.gen_range(0, 3));
+            if step != (1, 1) {
+                break step;
+            }
+        };
+        (
+            (r + self.size + dr - 1) % self.size,
+            (c + self.size + dc - 1) % self.size,
+        )
+    }
+
+    /// Lets `animal` reproduce with probability `reproduction`, returning the offspring at a
+    /// neighbouring cell if there is one.
+    fn reproduce<R: Rng>(
+        &self,
+        animal: &mut Animal,
+        reproduction: f64,
+        rng: &mut R,
+    ) -> Option<Animal> {
+        if !rng.gen_bool(reproduction) {
+            return None;
+        }
+        animal.energy /= 2.;
+        Some(Animal {
+            position: self.neighbour(animal.position, rng),
+            ..*animal
+        })
+    }
+
+    pub fn update(&mut self) {
+        let mut rng = thread_rng();
+        let Parameters {
+            sheep_gain_from_food,
+            wolf_gain_from_food,
+            sheep_reproduction,
+            wolf_reproduction,
+            grass_regrowth_time,
+        } = self.parameters;
+
+        let mut sheep = std::mem::take(&mut self.sheep);
+        sheep.shuffle(&mut rng);
+        let mut lambs = Vec::new();
+        for animal in sheep.iter_mut() {
+            animal.position = self.neighbour(animal.position, &mut rng);
+            if let Some(regrowth) = grass_regrowth_time {
+                animal.energy -= 1.;
+                let grass = &mut self.grass[animal.position];
+                if *grass == 0 {
+                    animal.energy += sheep_gain_from_food;
+                    *grass = regrowth;
+                }
+                if animal.energy < 0. {
+                    continue;
+                }
+            }
+            lambs.extend(self.reproduce(animal, sheep_reproduction, &mut rng));
+        }
+        sheep.retain(|animal| animal.energy >= 0.);
+        sheep.append(&mut lambs);
+
+        // the sheep on each cell, which the wolves on it may eat
+        let mut flocks = Array2::from_elem((self.size, self.size), Vec::new());
+        for (i, animal) in sheep.iter().enumerate() {
+            flocks[animal.position].push(i);
+        }
+        let mut eaten = vec![false; sheep.len()];
+
+        let mut wolves = std::mem::take(&mut self.wolves);
+        wolves.shuffle(&mut rng);
+        let mut cubs = Vec::new();
+        for animal in wolves.iter_mut() {
+            animal.position = self.neighbour(animal.position, &mut rng);
+            animal.energy -= 1.;
+            let flock = &mut flocks[animal.position];
+            if !flock.is_empty() {
+                let prey = flock.swap_remove(rng.gen_range(0, flock.len()));
+                eaten[prey] = true;
+                animal.energy += wolf_gain_from_food;
+            }
+            if animal.energy < 0. {
+                continue;
+            }
+            cubs.extend(self.reproduce(animal, wolf_reproduction, &mut rng));
+        }
+        wolves.retain(|animal| animal.energy >= 0.);
+        wolves.append(&mut cubs);
+
+        self.sheep = sheep
+            .into_iter()
+            .zip(eaten)
+            .filter(|(_, eaten)| !eaten)
+            .map(|(animal, _)| animal)
+            .collect();
+        self.wolves = wolves;
+        if grass_regrowth_time.is_some() {
+            self.grass
+                .mapv_inplace(|countdown| countdown.saturating_sub(1));
+        }
+        self.time += 1;
+    }
+
+    /// The population now and after each of at most `steps` updates, stopping early when the
+    /// run [is over](Model::is_over).
+    pub fn run(&mut self, steps: usize) -> Vec<Population> {
+        let mut populations = vec![self.population()];
+        for _ in 0..steps {
+            if self.is_over() {
+                break;
+            }
+            self.update();
+            populations.push(self.population());
+        }
+        populations
+    }
+}
+
+impl Display for Model {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        let mut cells = self.grass.mapv(|countdown| {
+            if countdown == 0 && self.grass().is_some() {
+                '.'
+            } else {
+                ' '
+            }
+        });
+        for animal in &self.sheep {
+            cells[animal.position] = 'S';
+        }
+        for animal in &self.wolves {
+            cells[animal.position] = 'W';
+        }
+        for row in cells.genrows() {
+            writeln!(f, "{}", row.iter().collect::<String>())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn wolves_without_sheep_starve() {
+    let mut model = Model::new(10, Population { x: 0., y: 20. }, Default::default());
+    // the initial energy is below twice the gain from food and every update costs one
+    let populations = model.run(100);
+    assert!(model.is_over());
+    assert_eq!(model.population().y, 0.);
+    assert!(populations.len() <= 2 * 20 + 2);
+    assert!(populations
+        .windows(2)
+        .all(|pair| pair[1].y <= pair[0].y * 2.));
+}
+
+#[test]
+fn grass_limits_the_sheep() {
+    let mut model = Model::new(20, Population { x: 50., y: 0. }, Default::default());
+    let populations = model.run(300);
+    println!("{}", model);
+    assert_eq!(populations.len(), 301);
+    assert!(populations.iter().all(|p| p.y == 0.));
+    // each sheep needs a quarter of a cell's grass per update and each cell regrows every 30
+    let last = populations.last().unwrap().x;
+    assert!(last > 0. && last < 400., "{} sheep", last);
+    assert!(model.grass().unwrap() < 400);
+
+    let mut unlimited = Model::new(
+        20,
+        Population { x: 50., y: 0. },
+        Parameters {
+            grass_regrowth_time: None,
+            ..Default::default()
+        },
+    );
+    unlimited.run(300);
+    assert_eq!(unlimited.grass(), None);
+    assert!(unlimited.population().x > 1000.);
+}
+
+#[test]
+fn wolves_grow_on_abundant_sheep() {
+    let parameters = Parameters {
+        grass_regrowth_time: None,
+        ..Default::default()
+    };
+    let mut model = Model::new(20, Population { x: 400., y: 10. }, parameters);
+    let populations = model.run(30);
+    assert_eq!(populations[0].x, 400.);
+    assert_eq!(populations[0].y, 10.);
+    assert!(model.population().y > 10.);
+    assert!(model.population().x < 400. * 1.04_f64.powi(30));
+    assert!(model
+        .wolves()
+        .iter()
+        .chain(model.sheep())
+        .all(|animal| animal.position.0 < 20 && animal.position.1 < 20));
+}